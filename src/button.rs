@@ -0,0 +1,203 @@
+//! Debounced buttons that report press, release, click, double click, long press and repeat
+//! events.
+//!
+//! `Button::poll` samples the pin and must be called regularly, e.g. from the main loop or a
+//! periodic `system_clock::timer`, because the debounce, double click and long press times are
+//! evaluated there. It can additionally be called from an EXTI handler of the pin to react to
+//! edges immediately. In that case the button is shared with the interrupt handlers through a
+//! `static` `spin::Mutex`, which the main loop must only lock inside `interrupt::free`.
+//!
+//! A click is reported when the double click time has passed after a release without a second
+//! press, so `Click` and `DoubleClick` never occur for the same presses. No click is reported
+//! for a press that was reported as long press.
+//!
+//! Example
+//!
+//! ```ignore
+//! let mut button = Button::new(pins.button, Config::default());
+//! loop {
+//!     button.poll();
+//!     while let Some(event) = button.next_event() {
+//!         match event {
+//!             Event::Click => pins.led.toggle(),
+//!             _ => {}
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Polled from the EXTI handler and a periodic timer:
+//!
+//! ```ignore
+//! static BUTTON: Mutex<Option<Button<ExtiPin<UserButtonPin>>>> = Mutex::new(None);
+//!
+//! let button_pin = exti
+//!     .listen(pins.button, Edge::Both, poll_button, &mut syscfg, &mut nvic)
+//!     .expect("EXTI line of the button already in use");
+//! *BUTTON.lock() = Some(Button::new(button_pin, Config::default()));
+//! timer::start_periodic(10, Context::Interrupt, poll_button);
+//! loop {
+//!     let event = interrupt::free(|_| BUTTON.lock().as_mut().and_then(Button::next_event));
+//!     if event == Some(Event::Click) {
+//!         pins.led.toggle();
+//!     }
+//! }
+//!
+//! fn poll_button() {
+//!     if let Some(ref mut button) = *BUTTON.lock() {
+//!         button.poll();
+//!     }
+//! }
+//! ```
+
+use arrayvec::ArrayVec;
+use gpio::InputPin;
+use system_clock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Press,
+    Release,
+    Click,
+    DoubleClick,
+    /// The button was held down for the long press time.
+    LongPress,
+    /// The button is still held down after a long press, reported every repeat interval.
+    Repeat,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    /// The time the pin level must be stable before a change is accepted.
+    pub debounce_ms: usize,
+    /// The maximum time between the first release and the second press of a double click.
+    pub double_click_ms: usize,
+    pub long_press_ms: usize,
+    /// The interval of repeat events after a long press, or `None` to disable them.
+    pub repeat_ms: Option<usize>,
+    /// Set this if the pin is low while the button is pressed.
+    pub active_low: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            debounce_ms: 20,
+            double_click_ms: 300,
+            long_press_ms: 1000,
+            repeat_ms: Some(200),
+            active_low: false,
+        }
+    }
+}
+
+pub struct Button<P: InputPin> {
+    pin: P,
+    config: Config,
+    /// The undebounced level and the time when it last changed.
+    raw: (bool, usize),
+    pressed: bool,
+    pressed_at: usize,
+    long_press: bool,
+    next_repeat: usize,
+    /// The release time of a click that might become a double click.
+    pending_click: Option<usize>,
+    events: ArrayVec<[Event; 8]>,
+}
+
+impl<P: InputPin> Button<P> {
+    pub fn new(pin: P, config: Config) -> Button<P> {
+        let now = system_clock::ms();
+        let level = pin.get() != config.active_low;
+        Button {
+            pin: pin,
+            config: config,
+            raw: (level, now),
+            pressed: level,
+            pressed_at: now,
+            // don't report a long press if the button is already pressed at creation
+            long_press: level,
+            next_repeat: now,
+            pending_click: None,
+            events: ArrayVec::new(),
+        }
+    }
+
+    /// Returns true if the button is pressed (debounced).
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Samples the pin and updates the events.
+    pub fn poll(&mut self) {
+        let level = self.pin.get() != self.config.active_low;
+        self.update(level, system_clock::ms());
+    }
+
+    /// Returns the oldest unread event.
+    ///
+    /// At most 8 events are buffered, newer events are dropped if they are not read.
+    pub fn next_event(&mut self) -> Option<Event> {
+        if self.events.is_empty() {
+            None
+        } else {
+            Some(self.events.remove(0))
+        }
+    }
+
+    pub fn release(self) -> P {
+        self.pin
+    }
+
+    fn update(&mut self, level: bool, now: usize) {
+        if level != self.raw.0 {
+            self.raw = (level, now);
+        }
+        let stable = now.wrapping_sub(self.raw.1) >= self.config.debounce_ms;
+
+        if stable && level != self.pressed {
+            self.pressed = level;
+            if level {
+                self.pressed_at = now;
+                self.long_press = false;
+                self.push(Event::Press);
+            } else {
+                self.push(Event::Release);
+                if !self.long_press {
+                    match self.pending_click.take() {
+                        Some(_) => self.push(Event::DoubleClick),
+                        None => self.pending_click = Some(now),
+                    }
+                }
+            }
+        }
+
+        if self.pressed {
+            let held = now.wrapping_sub(self.pressed_at);
+            if !self.long_press && held >= self.config.long_press_ms {
+                self.long_press = true;
+                // a pending click is not followed by a second click
+                if self.pending_click.take().is_some() {
+                    self.push(Event::Click);
+                }
+                self.push(Event::LongPress);
+                self.next_repeat = now;
+            }
+            if let Some(repeat_ms) = self.config.repeat_ms {
+                if self.long_press && now.wrapping_sub(self.next_repeat) >= repeat_ms {
+                    self.next_repeat = self.next_repeat.wrapping_add(repeat_ms);
+                    self.push(Event::Repeat);
+                }
+            }
+        } else if let Some(released_at) = self.pending_click {
+            if now.wrapping_sub(released_at) >= self.config.double_click_ms {
+                self.pending_click = None;
+                self.push(Event::Click);
+            }
+        }
+    }
+
+    fn push(&mut self, event: Event) {
+        let _ = self.events.try_push(event);
+    }
+}
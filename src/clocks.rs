@@ -0,0 +1,454 @@
+//! Configuration of the system clock tree.
+//!
+//! A `ClockConfig` describes the desired SYSCLK, HCLK, PCLK1, PCLK2 and 48 MHz clock. `freeze`
+//! computes the PLL factors, bus prescalers, flash wait states and overdrive setting, validates
+//! them against the limits of the STM32F74x and applies them. The returned `Clocks` can't be
+//! modified anymore and should be passed to all drivers that depend on clock frequencies.
+//!
+//! Example
+//!
+//! ```ignore
+//! let clocks = ClockConfig::new(Hz(25_000_000))
+//!     .sysclk(Hz(192_000_000))
+//!     .pclk1(Hz(48_000_000))
+//!     .freeze(&mut rcc, &mut pwr, &mut flash)
+//!     .expect("invalid clock configuration");
+//! system_clock::init(Hz(1000), &mut systick, &clocks);
+//! ```
+
+use stm32f7::stm32f7x6::{FLASH, PWR, RCC};
+use system_clock::Hz;
+
+const MHZ: usize = 1_000_000;
+
+/// The maximum SYSCLK and HCLK frequency with overdrive.
+const SYSCLK_MAX: usize = 216 * MHZ;
+/// Above this frequency the overdrive mode must be enabled.
+const OVERDRIVE_THRESHOLD: usize = 180 * MHZ;
+/// The maximum APB1 and APB2 frequencies without and with overdrive.
+const PCLK1_MAX: (usize, usize) = (45 * MHZ, 54 * MHZ);
+const PCLK2_MAX: (usize, usize) = (90 * MHZ, 108 * MHZ);
+/// The maximum HCLK frequency per flash wait state at 2.7 V to 3.6 V.
+const FLASH_WAIT_STATE_STEP: usize = 30 * MHZ;
+
+const CLK48: usize = 48 * MHZ;
+/// The allowed range of the VCO output frequency and of the PLLN multiplier of all PLLs.
+const VCO_RANGE: (usize, usize) = (100 * MHZ, 432 * MHZ);
+const PLLN_RANGE: (usize, usize) = (50, 432);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No PLL factors produce the requested SYSCLK (and 48 MHz clock) from the HSE.
+    NoPllConfiguration,
+    /// The frequency is above the maximum of the clock.
+    FrequencyTooHigh(Clock, Hz),
+    /// The clock can't be derived from its parent clock with one of the available prescalers.
+    NoPrescaler(Clock, Hz),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    Sysclk,
+    Hclk,
+    Pclk1,
+    Pclk2,
+}
+
+/// The desired clock frequencies.
+///
+/// Clocks that are not set explicitly run at the highest allowed frequency.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockConfig {
+    hse: Hz,
+    sysclk: Hz,
+    hclk: Option<Hz>,
+    pclk1: Option<Hz>,
+    pclk2: Option<Hz>,
+    clk48: bool,
+}
+
+impl Default for ClockConfig {
+    /// The 25 MHz HSE of the discovery board, a SYSCLK of 216 MHz and a 48 MHz clock.
+    fn default() -> ClockConfig {
+        ClockConfig::new(Hz(25 * MHZ))
+    }
+}
+
+impl ClockConfig {
+    /// Creates a configuration for the given HSE frequency with a SYSCLK of 216 MHz and a
+    /// 48 MHz clock.
+    pub fn new(hse: Hz) -> ClockConfig {
+        ClockConfig {
+            hse: hse,
+            sysclk: Hz(SYSCLK_MAX),
+            hclk: None,
+            pclk1: None,
+            pclk2: None,
+            clk48: true,
+        }
+    }
+
+    pub fn sysclk(mut self, frequency: Hz) -> ClockConfig {
+        self.sysclk = frequency;
+        self
+    }
+
+    pub fn hclk(mut self, frequency: Hz) -> ClockConfig {
+        self.hclk = Some(frequency);
+        self
+    }
+
+    pub fn pclk1(mut self, frequency: Hz) -> ClockConfig {
+        self.pclk1 = Some(frequency);
+        self
+    }
+
+    pub fn pclk2(mut self, frequency: Hz) -> ClockConfig {
+        self.pclk2 = Some(frequency);
+        self
+    }
+
+    /// Sets whether the 48 MHz clock for USB, RNG and SDMMC is required.
+    ///
+    /// If it is not required, the PLL factors are not restricted to a VCO frequency that is a
+    /// multiple of 48 MHz.
+    pub fn clk48(mut self, required: bool) -> ClockConfig {
+        self.clk48 = required;
+        self
+    }
+
+    /// Computes and validates the clock tree without changing any register.
+    pub fn compute(&self) -> Result<Clocks, Error> {
+        let Hz(sysclk) = self.sysclk;
+        if sysclk > SYSCLK_MAX {
+            return Err(Error::FrequencyTooHigh(Clock::Sysclk, self.sysclk));
+        }
+        let pll = pll_factors(self.hse.0, sysclk, self.clk48).ok_or(Error::NoPllConfiguration)?;
+
+        let hpre = match self.hclk {
+            Some(hclk) => prescaler(sysclk, hclk.0, &AHB_PRESCALERS)
+                .ok_or(Error::NoPrescaler(Clock::Hclk, hclk))?,
+            None => 1,
+        };
+        let hclk = sysclk / hpre;
+        let overdrive = hclk > OVERDRIVE_THRESHOLD;
+
+        let (pclk1_max, pclk2_max) = if overdrive {
+            (PCLK1_MAX.1, PCLK2_MAX.1)
+        } else {
+            (PCLK1_MAX.0, PCLK2_MAX.0)
+        };
+        let ppre1 = apb_prescaler(hclk, self.pclk1, pclk1_max, Clock::Pclk1)?;
+        let ppre2 = apb_prescaler(hclk, self.pclk2, pclk2_max, Clock::Pclk2)?;
+
+        let vco = pll.input * pll.n;
+        Ok(Clocks {
+            hse: self.hse,
+            pll_input: Hz(pll.input),
+            sysclk: Hz(sysclk),
+            hclk: Hz(hclk),
+            pclk1: Hz(hclk / ppre1),
+            pclk2: Hz(hclk / ppre2),
+            clk48: if self.clk48 {
+                Some(Hz(vco / pll.q))
+            } else {
+                None
+            },
+            pll: pll,
+            hpre: hpre,
+            ppre1: ppre1,
+            ppre2: ppre2,
+            flash_latency: ((hclk - 1) / FLASH_WAIT_STATE_STEP) as u8,
+            overdrive: overdrive,
+        })
+    }
+
+    /// Applies the configuration.
+    ///
+    /// The system must not run from the PLL when this function is called, which is the case
+    /// after reset.
+    pub fn freeze(self, rcc: &mut RCC, pwr: &mut PWR, flash: &mut FLASH) -> Result<Clocks, Error> {
+        let clocks = self.compute()?;
+
+        // enable power control clock
+        rcc.apb1enr.modify(|_, w| w.pwren().enabled());
+        rcc.apb1enr.read(); // delay
+
+        // reset HSEON and HSEBYP bits before configuring HSE
+        rcc.cr.modify(|_, w| {
+            w.hseon().clear_bit();
+            w.hsebyp().clear_bit();
+            w
+        });
+        // wait until HSE is disabled
+        while rcc.cr.read().hserdy().bit_is_set() {}
+        // turn HSE on
+        rcc.cr.modify(|_, w| w.hseon().set_bit());
+        // wait until HSE is enabled
+        while rcc.cr.read().hserdy().bit_is_clear() {}
+
+        // disable main PLL
+        rcc.cr.modify(|_, w| w.pllon().clear_bit());
+        while rcc.cr.read().pllrdy().bit_is_set() {}
+
+        // VCO input frequency = HSE / PLLM, VCO output frequency = VCO input × PLLN
+        // SYSCLK = VCO output / PLLP, 48 MHz clock = VCO output / PLLQ
+        let pll = clocks.pll;
+        rcc.pllcfgr.modify(|_, w| {
+            w.pllsrc().hse();
+            match pll.p {
+                2 => w.pllp().div2(),
+                4 => w.pllp().div4(),
+                6 => w.pllp().div6(),
+                8 => w.pllp().div8(),
+                _ => unreachable!(),
+            };
+            unsafe {
+                w.pllm().bits(pll.m as u8);
+                w.plln().bits(pll.n as u16);
+                w.pllq().bits(pll.q as u8);
+            }
+            w
+        });
+        // enable main PLL
+        rcc.cr.modify(|_, w| w.pllon().set_bit());
+        while rcc.cr.read().pllrdy().bit_is_clear() {}
+
+        if clocks.overdrive {
+            // enable overdrive
+            pwr.cr1.modify(|_, w| w.oden().set_bit());
+            while pwr.csr1.read().odrdy().bit_is_clear() {}
+            // enable overdrive switching
+            pwr.cr1.modify(|_, w| w.odswen().set_bit());
+            while pwr.csr1.read().odswrdy().bit_is_clear() {}
+        }
+
+        // Program the new number of wait states to the LATENCY bits in the FLASH_ACR register
+        flash
+            .acr
+            .modify(|_, w| w.latency().bits(clocks.flash_latency));
+        // Check that the new number of wait states is taken into account to access the Flash
+        // memory by reading the FLASH_ACR register
+        assert_eq!(flash.acr.read().latency().bits(), clocks.flash_latency);
+
+        // set the bus prescalers before switching to the PLL, so that the buses never exceed
+        // their maximum frequency
+        rcc.cfgr.modify(|_, w| {
+            match clocks.hpre {
+                1 => w.hpre().div1(),
+                2 => w.hpre().div2(),
+                4 => w.hpre().div4(),
+                8 => w.hpre().div8(),
+                16 => w.hpre().div16(),
+                64 => w.hpre().div64(),
+                128 => w.hpre().div128(),
+                256 => w.hpre().div256(),
+                512 => w.hpre().div512(),
+                _ => unreachable!(),
+            };
+            match clocks.ppre1 {
+                1 => w.ppre1().div1(),
+                2 => w.ppre1().div2(),
+                4 => w.ppre1().div4(),
+                8 => w.ppre1().div8(),
+                16 => w.ppre1().div16(),
+                _ => unreachable!(),
+            };
+            match clocks.ppre2 {
+                1 => w.ppre2().div1(),
+                2 => w.ppre2().div2(),
+                4 => w.ppre2().div4(),
+                8 => w.ppre2().div8(),
+                16 => w.ppre2().div16(),
+                _ => unreachable!(),
+            };
+            w
+        });
+
+        // SYSCLK Configuration
+        rcc.cfgr.modify(|_, w| w.sw().pll());
+        while !rcc.cfgr.read().sws().is_pll() {}
+
+        Ok(clocks)
+    }
+}
+
+/// The frozen clock frequencies.
+#[derive(Debug, Clone, Copy)]
+pub struct Clocks {
+    hse: Hz,
+    pll_input: Hz,
+    sysclk: Hz,
+    hclk: Hz,
+    pclk1: Hz,
+    pclk2: Hz,
+    clk48: Option<Hz>,
+    pll: PllFactors,
+    hpre: usize,
+    ppre1: usize,
+    ppre2: usize,
+    flash_latency: u8,
+    overdrive: bool,
+}
+
+impl Clocks {
+    pub fn hse(&self) -> Hz {
+        self.hse
+    }
+
+    /// The VCO input frequency of all PLLs (HSE / PLLM), which is shared by the main PLL, the
+    /// PLLI2S and the PLLSAI.
+    pub fn pll_input(&self) -> Hz {
+        self.pll_input
+    }
+
+    pub fn sysclk(&self) -> Hz {
+        self.sysclk
+    }
+
+    /// The AHB clock, which also drives the core and SysTick.
+    pub fn hclk(&self) -> Hz {
+        self.hclk
+    }
+
+    /// The APB1 clock.
+    pub fn pclk1(&self) -> Hz {
+        self.pclk1
+    }
+
+    /// The APB2 clock.
+    pub fn pclk2(&self) -> Hz {
+        self.pclk2
+    }
+
+    /// The clock of the timers on APB1, which is twice the APB1 clock if APB1 is divided.
+    pub fn timclk1(&self) -> Hz {
+        timer_clock(self.pclk1, self.ppre1)
+    }
+
+    /// The clock of the timers on APB2, which is twice the APB2 clock if APB2 is divided.
+    pub fn timclk2(&self) -> Hz {
+        timer_clock(self.pclk2, self.ppre2)
+    }
+
+    /// The 48 MHz clock for USB, RNG and SDMMC, if it was required in the configuration.
+    pub fn clk48(&self) -> Option<Hz> {
+        self.clk48
+    }
+
+    /// The PLLN multiplier for the PLLI2S or PLLSAI that produces exactly the VCO output
+    /// frequency `vco` from `pll_input`.
+    ///
+    /// Returns `None` if `vco` is not a multiple of `pll_input` or if it or the multiplier is out
+    /// of range.
+    pub fn pll_multiplier(&self, vco: Hz) -> Option<u16> {
+        let (Hz(vco), Hz(input)) = (vco, self.pll_input);
+        if vco < VCO_RANGE.0 || vco > VCO_RANGE.1 || vco % input != 0 {
+            return None;
+        }
+        let n = vco / input;
+        if n < PLLN_RANGE.0 || n > PLLN_RANGE.1 {
+            return None;
+        }
+        Some(n as u16)
+    }
+
+    pub fn flash_latency(&self) -> u8 {
+        self.flash_latency
+    }
+
+    pub fn overdrive(&self) -> bool {
+        self.overdrive
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PllFactors {
+    input: usize,
+    m: usize,
+    n: usize,
+    p: usize,
+    q: usize,
+}
+
+/// Searches PLL factors that produce exactly `sysclk` and, if `clk48` is set, exactly 48 MHz.
+///
+/// Higher VCO input frequencies are preferred because they reduce the PLL jitter. Only
+/// integral VCO input frequencies are used, so that the PLLI2S and PLLSAI can be configured
+/// exactly.
+fn pll_factors(hse: usize, sysclk: usize, clk48: bool) -> Option<PllFactors> {
+    for m in 2..64 {
+        if hse % m != 0 {
+            continue;
+        }
+        let input = hse / m;
+        if input < MHZ || input > 2 * MHZ {
+            continue;
+        }
+        for &p in &[2, 4, 6, 8] {
+            let vco = sysclk * p;
+            if vco < VCO_RANGE.0 || vco > VCO_RANGE.1 || vco % input != 0 {
+                continue;
+            }
+            let n = vco / input;
+            if n < PLLN_RANGE.0 || n > PLLN_RANGE.1 {
+                continue;
+            }
+            let q = if clk48 {
+                if vco % CLK48 != 0 {
+                    continue;
+                }
+                vco / CLK48
+            } else {
+                // keep the unused output at or below 48 MHz
+                (vco + CLK48 - 1) / CLK48
+            };
+            if q < 2 || q > 15 {
+                continue;
+            }
+            return Some(PllFactors {
+                input: input,
+                m: m,
+                n: n,
+                p: p,
+                q: q,
+            });
+        }
+    }
+    None
+}
+
+const AHB_PRESCALERS: [usize; 9] = [1, 2, 4, 8, 16, 64, 128, 256, 512];
+const APB_PRESCALERS: [usize; 5] = [1, 2, 4, 8, 16];
+
+fn prescaler(input: usize, output: usize, prescalers: &[usize]) -> Option<usize> {
+    prescalers
+        .iter()
+        .cloned()
+        .find(|&div| input / div == output && input % div == 0)
+}
+
+fn apb_prescaler(hclk: usize, pclk: Option<Hz>, max: usize, clock: Clock) -> Result<usize, Error> {
+    match pclk {
+        Some(pclk) => {
+            if pclk.0 > max {
+                return Err(Error::FrequencyTooHigh(clock, pclk));
+            }
+            prescaler(hclk, pclk.0, &APB_PRESCALERS).ok_or(Error::NoPrescaler(clock, pclk))
+        }
+        // the fastest allowed clock
+        None => Ok(APB_PRESCALERS
+            .iter()
+            .cloned()
+            .find(|&div| hclk / div <= max)
+            .expect("HCLK is too high for all APB prescalers")),
+    }
+}
+
+fn timer_clock(Hz(pclk): Hz, ppre: usize) -> Hz {
+    if ppre == 1 {
+        Hz(pclk)
+    } else {
+        Hz(pclk * 2)
+    }
+}
@@ -0,0 +1,386 @@
+//! Crash dumps that survive a reset.
+//!
+//! The HardFault and panic handlers record the stacked registers, the fault status registers,
+//! the panic message and a snapshot of the stack in the last `RESERVED_SIZE` bytes of the
+//! backup SRAM. The backup SRAM keeps its content across resets, so the dump can be retrieved
+//! through `take` after the next boot, e.g. to print it or to store it on the SD card.
+//!
+//! Example
+//!
+//! ```ignore
+//! exception!(HardFault, hard_fault);
+//!
+//! fn hard_fault(ef: &ExceptionFrame) -> ! {
+//!     crash::record_hard_fault(ef);
+//!     panic!("HardFault at {:#?}", ef);
+//! }
+//!
+//! #[panic_implementation]
+//! fn panic(info: &PanicInfo) -> ! {
+//!     crash::record_panic(info);
+//!     crash::system_reset()
+//! }
+//!
+//! // after the next boot
+//! if let Some(dump) = crash::take() {
+//!     println!("{}", dump);
+//! }
+//! ```
+
+use core::fmt::{self, Write};
+use core::mem;
+use core::panic::PanicInfo;
+use core::ptr;
+use core::slice;
+use core::str;
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::peripheral::{DCB, SCB};
+use cortex_m_rt::ExceptionFrame;
+use stm32f7::stm32f7x6::{PWR, RCC};
+
+/// The number of bytes at the end of the backup SRAM that are reserved for the crash dump.
+pub const RESERVED_SIZE: usize = 1024;
+/// The maximal number of stack words in a dump.
+pub const STACK_WORDS: usize = 64;
+/// The maximal length of the panic message in bytes, longer messages are truncated.
+pub const MESSAGE_LEN: usize = 256;
+
+const BACKUP_SRAM_END: usize = 0x4002_5000;
+const RECORD: *mut Record = (BACKUP_SRAM_END - RESERVED_SIZE) as *mut Record;
+const MAGIC: u32 = 0xdead_c0de;
+const RAM_START: u32 = 0x2000_0000;
+
+// DCB DHCSR
+const C_DEBUGEN: u32 = 1 << 0;
+
+extern "C" {
+    /// The initial stack pointer, defined by the linker script of cortex-m-rt.
+    static _stack_start: u32;
+}
+
+/// Set when a HardFault was recorded, so that the following panic only adds its message.
+static HARD_FAULT_RECORDED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    HardFault,
+    Panic,
+}
+
+/// The registers that the processor pushes to the stack on exception entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackedRegisters {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32,
+}
+
+/// The fault status and address registers of the system control block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultStatus {
+    /// Configurable fault status register.
+    pub cfsr: u32,
+    /// HardFault status register.
+    pub hfsr: u32,
+    /// MemManage fault address register, valid if `cfsr` bit 7 (MMARVALID) is set.
+    pub mmfar: u32,
+    /// BusFault address register, valid if `cfsr` bit 15 (BFARVALID) is set.
+    pub bfar: u32,
+}
+
+/// The layout of the dump in the backup SRAM.
+///
+/// The handlers write the fields in place, so that the record never has to fit on a possibly
+/// overflowed stack.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Record {
+    magic: u32,
+    kind: u32,
+    registers: [u32; 8],
+    fault_status: [u32; 4],
+    stack_pointer: u32,
+    stack_len: u32,
+    stack: [u32; STACK_WORDS],
+    message_len: u32,
+    message: [u8; MESSAGE_LEN],
+    /// Must be the last field.
+    checksum: u32,
+}
+
+impl Record {
+    /// Returns the record in the backup SRAM.
+    unsafe fn get() -> &'static mut Record {
+        enable_backup_sram();
+        &mut *RECORD
+    }
+
+    /// Invalidates the record and starts a new dump.
+    fn clear(&mut self, kind: Kind) {
+        self.magic = 0;
+        self.kind = kind as u32;
+        self.registers = [0; 8];
+        self.fault_status = [0; 4];
+        self.stack_pointer = 0;
+        self.stack_len = 0;
+        self.message_len = 0;
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        let words = mem::size_of::<Record>() / 4 - 1;
+        let words = unsafe { slice::from_raw_parts(self as *const _ as *const u32, words) };
+        words
+            .iter()
+            .fold(0, |checksum: u32, word| checksum.rotate_left(1) ^ word)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC
+            && self.kind <= Kind::Panic as u32
+            && self.stack_len as usize <= STACK_WORDS
+            && self.message_len as usize <= MESSAGE_LEN
+            && self.checksum == self.compute_checksum()
+    }
+
+    /// Copies up to `STACK_WORDS` words starting at `stack_pointer` if it points to the stack.
+    fn capture_stack(&mut self, stack_pointer: u32) {
+        let stack_top = unsafe { &_stack_start as *const u32 as u32 };
+        self.stack_pointer = stack_pointer;
+        // the stack pointer may be corrupted, e.g. after a stack overflow
+        if stack_pointer < RAM_START || stack_pointer >= stack_top || stack_pointer % 4 != 0 {
+            return;
+        }
+        let len = ((stack_top - stack_pointer) as usize / 4).min(STACK_WORDS);
+        for i in 0..len {
+            self.stack[i] = unsafe { ptr::read_volatile((stack_pointer as *const u32).add(i)) };
+        }
+        self.stack_len = len as u32;
+    }
+
+    fn set_message(&mut self, args: fmt::Arguments) {
+        let mut writer = MessageWriter {
+            buffer: &mut self.message,
+            len: 0,
+        };
+        let _ = writer.write_fmt(args);
+        self.message_len = writer.len as u32;
+    }
+
+    /// Marks the record as valid.
+    fn seal(&mut self) {
+        self.magic = MAGIC;
+        self.checksum = self.compute_checksum();
+    }
+}
+
+/// Writes as much of the formatted message as fits into the buffer.
+struct MessageWriter<'a> {
+    buffer: &'a mut [u8; MESSAGE_LEN],
+    len: usize,
+}
+
+impl<'a> Write for MessageWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut end = s.len().min(MESSAGE_LEN - self.len);
+        // don't split a character
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.buffer[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        Ok(())
+    }
+}
+
+/// Records the exception frame, the fault status registers and the stack of a HardFault.
+///
+/// Should be called first in the HardFault handler. A following `record_panic` adds its
+/// message to this dump.
+pub fn record_hard_fault(frame: &ExceptionFrame) {
+    let record = unsafe { Record::get() };
+    record.clear(Kind::HardFault);
+    record.registers = [
+        frame.r0, frame.r1, frame.r2, frame.r3, frame.r12, frame.lr, frame.pc, frame.xpsr,
+    ];
+    let scb = unsafe { &*SCB::ptr() };
+    record.fault_status = [
+        scb.cfsr.read(),
+        scb.hfsr.read(),
+        scb.mmfar.read(),
+        scb.bfar.read(),
+    ];
+    // the exception frame is at the top of the stack at the time of the fault
+    record.capture_stack(frame as *const _ as u32);
+    record.seal();
+    HARD_FAULT_RECORDED.store(true, Ordering::SeqCst);
+}
+
+/// Records the panic message and the stack of the panic handler.
+pub fn record_panic(info: &PanicInfo) {
+    let record = unsafe { Record::get() };
+    if HARD_FAULT_RECORDED.load(Ordering::SeqCst) {
+        record.set_message(format_args!("{}", info));
+        record.seal();
+        return;
+    }
+
+    record.clear(Kind::Panic);
+    record.set_message(format_args!("{}", info));
+    // the address of a local variable is close to the stack pointer
+    let stack_pointer = &info as *const _ as u32;
+    record.capture_stack(stack_pointer);
+    record.seal();
+}
+
+/// Returns the dump of the last crash, if any, and removes it from the backup SRAM.
+pub fn take() -> Option<CrashDump> {
+    unsafe {
+        enable_backup_sram();
+        let record = ptr::read_volatile(RECORD);
+        if !record.is_valid() {
+            return None;
+        }
+        ptr::write_volatile(&mut (*RECORD).magic, 0);
+        Some(CrashDump { record: record })
+    }
+}
+
+/// Returns true if a debugger is connected, i.e. if a breakpoint instruction doesn't cause a
+/// HardFault.
+pub fn debugger_attached() -> bool {
+    unsafe { (*DCB::ptr()).dhcsr.read() & C_DEBUGEN != 0 }
+}
+
+/// Resets the system.
+pub fn system_reset() -> ! {
+    unsafe {
+        // VECTKEY and SYSRESETREQ
+        (*SCB::ptr()).aircr.write(0x05fa_0004);
+    }
+    loop {}
+}
+
+/// Enables the clock of the backup SRAM and write access to the backup domain.
+///
+/// Uses the peripherals directly, because it is called from fault handlers.
+unsafe fn enable_backup_sram() {
+    let rcc = &*RCC::ptr();
+    rcc.apb1enr.modify(|_, w| w.pwren().enabled());
+    rcc.ahb1enr.modify(|_, w| w.bkpsramen().enabled());
+    // delay after an RCC peripheral clock enabling
+    let _unused = rcc.ahb1enr.read();
+    let pwr = &*PWR::ptr();
+    pwr.cr1.modify(|_, w| w.dbp().set_bit());
+    while pwr.cr1.read().dbp().bit_is_clear() {}
+}
+
+/// A crash dump from a previous boot.
+pub struct CrashDump {
+    record: Record,
+}
+
+impl CrashDump {
+    pub fn kind(&self) -> Kind {
+        if self.record.kind == Kind::HardFault as u32 {
+            Kind::HardFault
+        } else {
+            Kind::Panic
+        }
+    }
+
+    /// The registers at the time of the fault, only available for HardFaults.
+    pub fn registers(&self) -> Option<StackedRegisters> {
+        let r = &self.record.registers;
+        match self.kind() {
+            Kind::HardFault => Some(StackedRegisters {
+                r0: r[0],
+                r1: r[1],
+                r2: r[2],
+                r3: r[3],
+                r12: r[4],
+                lr: r[5],
+                pc: r[6],
+                xpsr: r[7],
+            }),
+            Kind::Panic => None,
+        }
+    }
+
+    /// The fault status registers, only available for HardFaults.
+    pub fn fault_status(&self) -> Option<FaultStatus> {
+        let f = &self.record.fault_status;
+        match self.kind() {
+            Kind::HardFault => Some(FaultStatus {
+                cfsr: f[0],
+                hfsr: f[1],
+                mmfar: f[2],
+                bfar: f[3],
+            }),
+            Kind::Panic => None,
+        }
+    }
+
+    /// The address of the first word of `stack`.
+    pub fn stack_pointer(&self) -> u32 {
+        self.record.stack_pointer
+    }
+
+    /// The top of the stack, empty if the stack pointer was invalid.
+    pub fn stack(&self) -> &[u32] {
+        &self.record.stack[..self.record.stack_len as usize]
+    }
+
+    /// The panic message, which may be truncated. Empty if there was no panic.
+    pub fn message(&self) -> &str {
+        let message = &self.record.message[..self.record.message_len as usize];
+        str::from_utf8(message).unwrap_or("<invalid message>")
+    }
+
+    /// The raw dump, e.g. for storing it on the SD card or sending it over the network.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                &self.record as *const _ as *const u8,
+                mem::size_of::<Record>(),
+            )
+        }
+    }
+}
+
+impl fmt::Display for CrashDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:?}: {}", self.kind(), self.message())?;
+        if let Some(r) = self.registers() {
+            writeln!(
+                f,
+                "r0 {:08x} r1 {:08x} r2 {:08x} r3 {:08x}",
+                r.r0, r.r1, r.r2, r.r3
+            )?;
+            writeln!(
+                f,
+                "r12 {:08x} lr {:08x} pc {:08x} xpsr {:08x}",
+                r.r12, r.lr, r.pc, r.xpsr
+            )?;
+        }
+        if let Some(s) = self.fault_status() {
+            writeln!(
+                f,
+                "cfsr {:08x} hfsr {:08x} mmfar {:08x} bfar {:08x}",
+                s.cfsr, s.hfsr, s.mmfar, s.bfar
+            )?;
+        }
+        write!(f, "stack at {:08x}:", self.stack_pointer())?;
+        for (i, word) in self.stack().iter().enumerate() {
+            if i % 8 == 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:08x} ", word)?;
+        }
+        writeln!(f)
+    }
+}
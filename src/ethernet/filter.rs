@@ -0,0 +1,204 @@
+//! The MAC address filter: perfect unicast filtering, the multicast hash table and the
+//! promiscuous, pass-all-multicast and broadcast blocking modes.
+//!
+//! The MAC registers are reset together with the DMA, so `Filter` keeps the complete
+//! configuration and writes it to the registers in `apply`.
+
+use arrayvec::ArrayVec;
+use byteorder::{ByteOrder, LittleEndian};
+use smoltcp::wire::EthernetAddress;
+use stm32f7::stm32f7x6::ETHERNET_MAC;
+
+/// The number of additional perfect filter addresses (MACA1 to MACA3).
+pub const UNICAST_ADDRESSES: usize = 3;
+/// The maximum number of joined multicast groups.
+pub const MULTICAST_GROUPS: usize = 16;
+
+pub struct Filter {
+    unicast: [Option<EthernetAddress>; UNICAST_ADDRESSES],
+    /// The joined multicast groups, once per join.
+    multicast: ArrayVec<[EthernetAddress; MULTICAST_GROUPS]>,
+    promiscuous: bool,
+    pass_all_multicast: bool,
+    block_broadcast: bool,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter {
+            unicast: [None; UNICAST_ADDRESSES],
+            multicast: ArrayVec::new(),
+            promiscuous: false,
+            pass_all_multicast: false,
+            block_broadcast: false,
+        }
+    }
+
+    /// Adds the address to a free perfect filter slot.
+    ///
+    /// Returns false if all slots are in use.
+    pub fn add_unicast(&mut self, address: EthernetAddress) -> bool {
+        assert!(address.is_unicast(), "{} is not a unicast address", address);
+        if self.unicast.contains(&Some(address)) {
+            return true;
+        }
+        match self.unicast.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(address);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if the address was not added before.
+    pub fn remove_unicast(&mut self, address: EthernetAddress) -> bool {
+        match self.unicast.iter_mut().find(|slot| **slot == Some(address)) {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if `MULTICAST_GROUPS` groups are already joined.
+    pub fn join_multicast(&mut self, group: EthernetAddress) -> bool {
+        assert!(group.is_multicast(), "{} is not a multicast address", group);
+        self.multicast.try_push(group).is_ok()
+    }
+
+    /// Returns false if the group was not joined before.
+    pub fn leave_multicast(&mut self, group: EthernetAddress) -> bool {
+        match self.multicast.iter().position(|&joined| joined == group) {
+            Some(index) => {
+                self.multicast.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_promiscuous(&mut self, enabled: bool) {
+        self.promiscuous = enabled;
+    }
+
+    pub fn set_pass_all_multicast(&mut self, enabled: bool) {
+        self.pass_all_multicast = enabled;
+    }
+
+    pub fn set_block_broadcast(&mut self, enabled: bool) {
+        self.block_broadcast = enabled;
+    }
+
+    /// Writes the configuration to the MAC registers.
+    pub fn apply(&self, ethernet_mac: &mut ETHERNET_MAC) {
+        for (index, address) in self.unicast.iter().enumerate() {
+            let (enable, high, low) = match *address {
+                Some(address) => {
+                    let bytes = address.as_bytes();
+                    let high = LittleEndian::read_u16(&bytes[4..]);
+                    (true, high, LittleEndian::read_u32(&bytes[..4]))
+                }
+                None => (false, 0, 0),
+            };
+            // the low register must be written last, it latches the address
+            match index {
+                0 => {
+                    ethernet_mac.maca1hr.write(|w| {
+                        w.maca1h().bits(high); // address bytes 4 and 5
+                        w.ae().bit(enable) // address enable
+                    });
+                    ethernet_mac.maca1lr.write(|w| w.maca1l().bits(low));
+                }
+                1 => {
+                    ethernet_mac.maca2hr.write(|w| {
+                        w.maca2h().bits(high); // address bytes 4 and 5
+                        w.ae().bit(enable) // address enable
+                    });
+                    ethernet_mac.maca2lr.write(|w| w.maca2l().bits(low));
+                }
+                2 => {
+                    ethernet_mac.maca3hr.write(|w| {
+                        w.maca3h().bits(high); // address bytes 4 and 5
+                        w.ae().bit(enable) // address enable
+                    });
+                    ethernet_mac.maca3lr.write(|w| w.maca3l().bits(low));
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let hash_table = self.hash_table();
+        ethernet_mac
+            .machtlr
+            .write(|w| w.htl().bits(hash_table as u32));
+        ethernet_mac
+            .machthr
+            .write(|w| w.hth().bits((hash_table >> 32) as u32));
+
+        ethernet_mac.macffr.modify(|_, w| {
+            // multicast frames pass the hash filter, unicast frames the perfect filter
+            w.hm().set_bit(); // hash multicast
+            w.hpf().set_bit(); // hash or perfect filter
+            w.pm().bit(self.promiscuous); // promiscuous mode
+            w.ram().bit(self.pass_all_multicast); // pass all multicast
+            w.bfd().bit(self.block_broadcast) // broadcast frames disable
+        });
+    }
+
+    /// The hash table bits of the joined multicast groups.
+    fn hash_table(&self) -> u64 {
+        self.multicast
+            .iter()
+            .fold(0, |hash_table, &group| hash_table | 1 << hash(group))
+    }
+}
+
+/// The index of the address in the hash table: the upper 6 bits of the bit-reversed,
+/// complemented CRC-32 of the address.
+fn hash(address: EthernetAddress) -> usize {
+    let mut crc = 0xffff_ffff_u32;
+    for &byte in address.as_bytes() {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    // reversing the lowest 6 bits gives the upper 6 bits of the reversed value
+    let crc = !crc;
+    (0..6).fold(0, |hash, bit| hash << 1 | (crc >> bit) as usize & 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multicast_hash() {
+        let mdns = EthernetAddress([0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb]);
+        assert_eq!(hash(mdns), 48);
+        let ipv4_all_hosts = EthernetAddress([0x01, 0x00, 0x5e, 0x00, 0x00, 0x01]);
+        assert_eq!(hash(ipv4_all_hosts), 32);
+        let ipv6_all_nodes = EthernetAddress([0x33, 0x33, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(hash(ipv6_all_nodes), 1);
+    }
+
+    #[test]
+    fn leave_multicast_with_same_hash() {
+        let mdns = EthernetAddress([0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb]);
+        let same_hash = EthernetAddress([0x01, 0x00, 0x5e, 0x00, 0x00, 0xc9]);
+        assert_eq!(hash(same_hash), hash(mdns));
+
+        let mut filter = Filter::new();
+        assert!(filter.join_multicast(mdns));
+        assert!(!filter.leave_multicast(same_hash));
+        assert_eq!(filter.hash_table(), 1 << 48);
+        assert!(filter.leave_multicast(mdns));
+        assert_eq!(filter.hash_table(), 0);
+    }
+}
@@ -1,10 +1,19 @@
-use super::phy;
+use super::interrupt;
+use super::phy::{self, Link, Speed};
+use clocks::Clocks;
 use stm32f7::stm32f7x6::{ETHERNET_DMA, ETHERNET_MAC, RCC, SYSCFG};
-use system_clock;
+
+/// The number of status polls until the DMA must be stopped, roughly 100 ms at 216 MHz.
+///
+/// The timeout doesn't use `system_clock`, because `stop` may be called with disabled
+/// interrupts, e.g. from a panic handler, where the SysTick doesn't advance.
+const STOP_TIMEOUT_POLLS: usize = 2_000_000;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     PhyError(phy::Error),
+    /// The DMA did not stop in time.
+    StopTimeout,
 }
 
 impl From<phy::Error> for Error {
@@ -15,6 +24,7 @@ impl From<phy::Error> for Error {
 
 pub fn init(
     rcc: &mut RCC,
+    clocks: &Clocks,
     syscfg: &mut SYSCFG,
     ethernet_mac: &mut ETHERNET_MAC,
     ethernet_dma: &mut ETHERNET_DMA,
@@ -26,8 +36,6 @@ pub fn init(
     // delay
     let _unused = rcc.apb2enr.read();
 
-    // TODO enable interrupt
-
     // enable ethernet clocks
     rcc.ahb1enr.modify(|_, w| {
         w.ethmacen().set_bit(); // ethernet mac clock enable
@@ -43,33 +51,22 @@ pub fn init(
     ethernet_dma.dmabmr.modify(|_, w| w.sr().set_bit()); // set software reset bit
     while ethernet_dma.dmabmr.read().sr().bit_is_set() {} // wait for auto clear
 
-    // MAC init: set clock range in MAC MII address register
-    match system_clock::system_clock_speed() {
-        f if f.0 >= 150000000 => {
-            ethernet_mac.macmiiar.modify(|_, w| w.cr().cr_150_168()); // 150-168 MHz HCLK/102
-        }
-        _ => panic!("unsupported"),
-    };
-
-    // init PHY
-    let auto_neg_result = phy::init(ethernet_mac)?;
-    assert!(auto_neg_result.duplex);
-    assert_eq!(auto_neg_result.speed, phy::Speed::Speed100M);
+    // MAC init: set clock range in MAC MII address register, so that MDC is at most 2.5 MHz
+    ethernet_mac.macmiiar.modify(|_, w| match clocks.hclk().0 {
+        f if f >= 150_000_000 => w.cr().cr_150_168(), // 150-216 MHz HCLK/102
+        f if f >= 100_000_000 => w.cr().cr_100_150(), // 100-150 MHz HCLK/62
+        f if f >= 60_000_000 => w.cr().cr_60_100(),   // 60-100 MHz HCLK/42
+        f if f >= 35_000_000 => w.cr().cr_35_60(),    // 35-60 MHz HCLK/26
+        f if f >= 20_000_000 => w.cr().cr_20_35(),    // 20-35 MHz HCLK/16
+        _ => panic!("HCLK must be at least 20 MHz for ethernet"),
+    });
 
     // MAC config
-    // configuration register
+    // configuration register, the speed and duplex mode are set by `set_link` when the link
+    // comes up
     ethernet_mac.maccr.modify(|_, w| {
-        // fast ethernet speed (false = 10Mbit/s, true = 100Mbit/s)
-        match auto_neg_result.speed {
-            phy::Speed::Speed100M => w.fes().fes100(),
-            phy::Speed::Speed10M => w.fes().fes10(),
-        };
-        // duplex mode
-        if auto_neg_result.duplex {
-            w.dm().full_duplex();
-        } else {
-            w.dm().half_duplex();
-        }
+        w.fes().fes100(); // fast ethernet speed (false = 10Mbit/s, true = 100Mbit/s)
+        w.dm().full_duplex(); // duplex mode
 
         w.lm().normal(); // loopback mode
         w.apcs().strip(); // automatic pad/CRC stripping (only if length <= 1500 bytes)
@@ -80,7 +77,7 @@ pub fn init(
         // When set, this bit enables IPv4 checksum checking for received frame payloads'
         // TCP/UDP/ICMP headers. When this bit is reset, the checksum offload function in the
         // receiver is disabled.
-        w.ipco().disabled(); // IPv4 checksum offload
+        w.ipco().enabled(); // IPv4 checksum offload
 
         // When this bit is set, the MAC disables the watchdog timer on the receiver, and can
         // receive frames of up to 16 384 bytes. When this bit is reset, the MAC allows no more
@@ -151,7 +148,7 @@ pub fn init(
         w.tsf().store_forward(); // transmit store and forward
         w.dfrf().clear_bit(); // disable flushing of received frames
         w.rsf().store_forward(); // receive store and forward
-        w.dtcefd().enabled(); // dropping of TCP/IP checksum error frames disable
+        w.dtcefd().enabled(); // forward TCP/IP checksum error frames to `RxDevice::receive`
 
         w
     });
@@ -170,12 +167,7 @@ pub fn init(
         w
     });
 
-    // interrupt enable register
-    ethernet_dma.dmaier.modify(|_, w| {
-        w.nise().set_bit(); // Normal interrupt summary enable
-        w.rie().set_bit(); // Receive interrupt enable
-        w
-    });
+    // the interrupts are enabled through `EthernetDevice::listen`
 
     // Initialize MAC address in ethernet MAC
     ethernet_mac.maca0hr.modify(|_, w| {
@@ -204,3 +196,55 @@ pub fn start(ethernet_mac: &mut ETHERNET_MAC, ethernet_dma: &mut ETHERNET_DMA) {
         w
     });
 }
+
+/// Configures the MAC for the speed and duplex mode of the link.
+pub fn set_link(ethernet_mac: &mut ETHERNET_MAC, link: Link) {
+    ethernet_mac.maccr.modify(|_, w| {
+        match link.speed {
+            Speed::Speed100M => w.fes().fes100(),
+            Speed::Speed10M => w.fes().fes10(),
+        };
+        if link.full_duplex {
+            w.dm().full_duplex();
+        } else {
+            w.dm().half_duplex();
+        }
+        w
+    });
+}
+
+/// Stops the DMA and the MAC in the order given by the reference manual, so that the
+/// descriptors and buffers are not accessed afterwards.
+pub fn stop(ethernet_mac: &mut ETHERNET_MAC, ethernet_dma: &mut ETHERNET_DMA) -> Result<(), Error> {
+    // stop DMA transmission and wait until the current frame is sent
+    ethernet_dma.dmaomr.modify(|_, w| w.st().clear_bit());
+    let tx_result = wait_until(|| ethernet_dma.dmasr.read().tps().is_stopped());
+
+    // disable MAC transmission and reception
+    ethernet_mac.maccr.modify(|_, w| {
+        w.te().clear_bit();
+        w.re().clear_bit();
+        w
+    });
+
+    // stop DMA reception
+    ethernet_dma.dmaomr.modify(|_, w| w.sr().clear_bit());
+    let rx_result = wait_until(|| ethernet_dma.dmasr.read().rps().is_stopped());
+
+    // flush transmit FIFO
+    ethernet_dma.dmaomr.modify(|_, w| w.ftf().set_bit());
+    let flush_result = wait_until(|| ethernet_dma.dmaomr.read().ftf().bit_is_clear());
+
+    interrupt::disable_sources(ethernet_dma);
+
+    tx_result.and(rx_result).and(flush_result)
+}
+
+fn wait_until<F: Fn() -> bool>(condition: F) -> Result<(), Error> {
+    for _ in 0..STOP_TIMEOUT_POLLS {
+        if condition() {
+            return Ok(());
+        }
+    }
+    Err(Error::StopTimeout)
+}
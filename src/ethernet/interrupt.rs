@@ -0,0 +1,120 @@
+//! The DMA interrupts of the ethernet peripheral.
+//!
+//! The `ETH` interrupt handler must call `handle_interrupt`, which clears the status flags and
+//! collects them as `Events`, together with the link changes that `EthernetDevice` detects.
+//! The main loop fetches them through `take_events`, e.g. to poll the network stack when a
+//! frame was received instead of at a fixed rate. The interrupt also wakes the core if it
+//! waits in `wfi`.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use cortex_m::peripheral::NVIC;
+use stm32f7::stm32f7x6::{Interrupt, ETHERNET_DMA};
+
+static EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+bitflags! {
+    /// Events of the ethernet DMA, with the bits of the DMA status register.
+    pub struct Events: u32 {
+        /// A frame was transmitted.
+        const TRANSMIT                      = 1 << 0;
+        const TRANSMIT_STOPPED              = 1 << 1;
+        /// The transmit DMA reached a descriptor that is owned by the CPU and suspended.
+        const TRANSMIT_BUFFER_UNAVAILABLE   = 1 << 2;
+        const TRANSMIT_JABBER_TIMEOUT       = 1 << 3;
+        const RECEIVE_OVERFLOW              = 1 << 4;
+        const TRANSMIT_UNDERFLOW            = 1 << 5;
+        /// A frame was received.
+        const RECEIVE                       = 1 << 6;
+        /// All receive descriptors are full and the receive DMA is suspended.
+        const RECEIVE_BUFFER_UNAVAILABLE    = 1 << 7;
+        const RECEIVE_STOPPED               = 1 << 8;
+        const RECEIVE_WATCHDOG_TIMEOUT      = 1 << 9;
+        const FATAL_BUS_ERROR               = 1 << 13;
+        /// The link came up, not a DMA status bit.
+        const LINK_UP                       = 1 << 30;
+        /// The link went down, not a DMA status bit.
+        const LINK_DOWN                     = 1 << 31;
+
+        /// All events that indicate an error.
+        const ERRORS                        = 0x233a;
+    }
+}
+
+/// Enables the normal and abnormal DMA interrupts for all `Events`.
+pub fn enable(ethernet_dma: &ETHERNET_DMA, nvic: &mut NVIC) {
+    clear_status(ethernet_dma);
+    ethernet_dma.dmaier.write(|w| {
+        w.tie().set_bit(); // transmit
+        w.tpsie().set_bit(); // transmit process stopped
+        w.tbuie().set_bit(); // transmit buffer unavailable
+        w.tjtie().set_bit(); // transmit jabber timeout
+        w.roie().set_bit(); // receive overflow
+        w.tuie().set_bit(); // transmit underflow
+        w.rie().set_bit(); // receive
+        w.rbuie().set_bit(); // receive buffer unavailable
+        w.rpsie().set_bit(); // receive process stopped
+        w.rwtie().set_bit(); // receive watchdog timeout
+        w.fbeie().set_bit(); // fatal bus error
+        w.aise().set_bit(); // abnormal interrupt summary
+        w.nise().set_bit() // normal interrupt summary
+    });
+    nvic.enable(Interrupt::ETH);
+}
+
+pub fn disable(ethernet_dma: &ETHERNET_DMA, nvic: &mut NVIC) {
+    nvic.disable(Interrupt::ETH);
+    disable_sources(ethernet_dma);
+}
+
+/// Disables all DMA interrupts and clears the status flags, without touching the NVIC.
+pub fn disable_sources(ethernet_dma: &ETHERNET_DMA) {
+    ethernet_dma.dmaier.reset();
+    clear_status(ethernet_dma);
+}
+
+/// Clears all status flags, which are cleared by writing 1.
+fn clear_status(ethernet_dma: &ETHERNET_DMA) {
+    ethernet_dma.dmasr.write(|w| {
+        w.ts().set_bit();
+        w.tpss().set_bit();
+        w.tbus().set_bit();
+        w.tjts().set_bit();
+        w.ros().set_bit();
+        w.tus().set_bit();
+        w.rs().set_bit();
+        w.rbus().set_bit();
+        w.rpss().set_bit();
+        w.pwts().set_bit();
+        w.ets().set_bit();
+        w.fbes().set_bit();
+        w.ers().set_bit();
+        w.ais().set_bit();
+        w.nis().set_bit()
+    });
+}
+
+/// Clears the DMA status flags and records them for `take_events`.
+///
+/// Must be called by the `ETH` interrupt handler.
+pub fn handle_interrupt() {
+    let ethernet_dma = unsafe { &*ETHERNET_DMA::ptr() };
+    let events = Events::from_bits_truncate(ethernet_dma.dmasr.read().bits());
+    // the event bits are the status flags, the summary flags are cleared with them
+    ethernet_dma.dmasr.write(|w| {
+        unsafe { w.bits(events.bits()) };
+        w.ais().set_bit();
+        w.nis().set_bit()
+    });
+
+    report(events);
+}
+
+/// Records the events for `take_events`.
+pub fn report(events: Events) {
+    EVENTS.fetch_or(events.bits() as usize, Ordering::SeqCst);
+}
+
+/// Returns and clears the events since the last call.
+pub fn take_events() -> Events {
+    Events::from_bits_truncate(EVENTS.swap(0, Ordering::SeqCst) as u32)
+}
@@ -1,16 +1,28 @@
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use clocks::Clocks;
 use core::fmt;
+use cortex_m::asm;
+use cortex_m::peripheral::NVIC;
 
 use stm32f7::stm32f7x6::{ETHERNET_DMA, ETHERNET_MAC, RCC, SYSCFG};
 use volatile::Volatile;
 
 use smoltcp::iface::{EthernetInterface, EthernetInterfaceBuilder};
-use smoltcp::phy::{Device, DeviceCapabilities};
+use smoltcp::phy::{Checksum, Device, DeviceCapabilities};
 use smoltcp::time::Instant;
 use smoltcp::wire::{EthernetAddress, IpCidr, Ipv4Address, Ipv4Cidr};
 
+pub use self::filter::{MULTICAST_GROUPS, UNICAST_ADDRESSES};
+pub use self::interrupt::{handle_interrupt, take_events, Events};
+pub use self::phy::{Link, LinkMode, LinkState, Speed};
+
+use self::filter::Filter;
+use self::phy::Phy;
+
+mod filter;
 mod init;
+mod interrupt;
 mod phy;
 mod rx;
 mod tx;
@@ -42,6 +54,9 @@ pub const MTU: usize = 1536;
 pub struct EthernetDevice<'d> {
     rx: RxDevice,
     tx: TxDevice,
+    phy: Phy,
+    filter: Filter,
+    ethernet_mac: &'d mut ETHERNET_MAC,
     ethernet_dma: &'d mut ETHERNET_DMA,
     ethernet_address: EthernetAddress,
 }
@@ -51,42 +66,185 @@ impl<'d> EthernetDevice<'d> {
         rx_config: RxConfig,
         tx_config: TxConfig,
         rcc: &mut RCC,
+        clocks: &Clocks,
         syscfg: &mut SYSCFG,
-        ethernet_mac: &mut ETHERNET_MAC,
+        ethernet_mac: &'d mut ETHERNET_MAC,
         ethernet_dma: &'d mut ETHERNET_DMA,
         ethernet_address: EthernetAddress,
     ) -> Result<Self, Error> {
-        use byteorder::{ByteOrder, LittleEndian};
-
-        init::init(rcc, syscfg, ethernet_mac, ethernet_dma)?;
+        init::init(rcc, clocks, syscfg, ethernet_mac, ethernet_dma)?;
+        let phy = Phy::new(ethernet_mac, LinkMode::default()).map_err(init::Error::from)?;
 
         let rx_device = RxDevice::new(rx_config)?;
         let tx_device = TxDevice::new(tx_config);
 
-        ethernet_dma.dmardlar.write(|w| {
-            w.srl()
-                .bits(&rx_device.descriptors[0] as *const Volatile<_> as u32)
-        });
-        ethernet_dma.dmatdlar.write(|w| {
-            w.stl()
-                .bits(tx_device.front_of_queue() as *const Volatile<_> as u32)
-        });
+        let mut device = EthernetDevice {
+            rx: rx_device,
+            tx: tx_device,
+            phy: phy,
+            filter: Filter::new(),
+            ethernet_mac: ethernet_mac,
+            ethernet_dma: ethernet_dma,
+            ethernet_address: ethernet_address,
+        };
+        device.start();
+        Ok(device)
+    }
+
+    /// Stops the device, initializes the peripherals and the PHY again and restarts it with
+    /// empty descriptor rings, e.g. after an error.
+    ///
+    /// The interrupts must be enabled again through `listen`. The link mode is kept.
+    ///
+    /// If an error is returned, the MAC and the DMA are stopped or not started again and the
+    /// PHY state is stale, so the device must be dropped.
+    pub fn restart(
+        &mut self,
+        rcc: &mut RCC,
+        clocks: &Clocks,
+        syscfg: &mut SYSCFG,
+    ) -> Result<(), Error> {
+        init::stop(self.ethernet_mac, self.ethernet_dma)?;
+        init::init(rcc, clocks, syscfg, self.ethernet_mac, self.ethernet_dma)?;
+        if let LinkState::Up(_) = self.phy.state() {
+            interrupt::report(Events::LINK_DOWN);
+        }
+        self.phy = Phy::new(self.ethernet_mac, self.phy.mode()).map_err(init::Error::from)?;
+        self.rx.reset();
+        self.tx.reset();
+        self.start();
+        Ok(())
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        self.phy.state()
+    }
+
+    pub fn link_mode(&self) -> LinkMode {
+        self.phy.mode()
+    }
+
+    /// Changes the link mode, e.g. to a forced 10 Mbit/s half-duplex mode. The link is
+    /// established again in the following calls of `poll_link`.
+    pub fn set_link_mode(&mut self, mode: LinkMode) {
+        let change = self.phy.set_mode(self.ethernet_mac, mode);
+        self.handle_link_change(change);
+    }
+
+    /// Checks the link and configures the MAC for the new speed and duplex mode when the link
+    /// comes up. Link changes are also reported through `take_events`.
+    ///
+    /// This is called on every poll of the network interface, but the PHY is only queried
+    /// every 100 ms.
+    pub fn poll_link(&mut self) -> Option<LinkState> {
+        let change = self.phy.poll(self.ethernet_mac);
+        self.handle_link_change(change);
+        change
+    }
+
+    fn handle_link_change(&mut self, change: Option<LinkState>) {
+        match change {
+            Some(LinkState::Up(link)) => {
+                init::set_link(self.ethernet_mac, link);
+                interrupt::report(Events::LINK_UP);
+            }
+            Some(_) => interrupt::report(Events::LINK_DOWN),
+            None => {}
+        }
+    }
+
+    /// Adds a unicast address to the perfect filter, so that frames to it are received in
+    /// addition to frames to the address of the device.
+    ///
+    /// Returns `Error::Exhausted` if all `UNICAST_ADDRESSES` filter slots are in use.
+    pub fn add_unicast_address(&mut self, address: EthernetAddress) -> Result<(), Error> {
+        if !self.filter.add_unicast(address) {
+            return Err(Error::Exhausted);
+        }
+        self.filter.apply(self.ethernet_mac);
+        Ok(())
+    }
+
+    /// Returns false if the address was not added before.
+    pub fn remove_unicast_address(&mut self, address: EthernetAddress) -> bool {
+        let removed = self.filter.remove_unicast(address);
+        self.filter.apply(self.ethernet_mac);
+        removed
+    }
+
+    /// Receives frames to the multicast group address, e.g. `01:00:5e:00:00:fb` for mDNS.
+    ///
+    /// The groups are filtered through a 64 bit hash table, so frames to other groups with the
+    /// same hash pass the filter as well. Every join must be matched by a `leave_multicast_group`.
+    ///
+    /// Returns false if `MULTICAST_GROUPS` groups are already joined.
+    pub fn join_multicast_group(&mut self, group: EthernetAddress) -> bool {
+        let joined = self.filter.join_multicast(group);
+        self.filter.apply(self.ethernet_mac);
+        joined
+    }
+
+    /// Returns false if the group was not joined before.
+    pub fn leave_multicast_group(&mut self, group: EthernetAddress) -> bool {
+        let left = self.filter.leave_multicast(group);
+        self.filter.apply(self.ethernet_mac);
+        left
+    }
+
+    /// Receives all multicast frames, regardless of the joined groups.
+    pub fn set_pass_all_multicast(&mut self, enabled: bool) {
+        self.filter.set_pass_all_multicast(enabled);
+        self.filter.apply(self.ethernet_mac);
+    }
+
+    /// Drops all broadcast frames. Note that ARP requests are broadcasts.
+    pub fn set_broadcast_blocked(&mut self, blocked: bool) {
+        self.filter.set_block_broadcast(blocked);
+        self.filter.apply(self.ethernet_mac);
+    }
+
+    /// Receives all frames, regardless of their destination address.
+    pub fn set_promiscuous(&mut self, enabled: bool) {
+        self.filter.set_promiscuous(enabled);
+        self.filter.apply(self.ethernet_mac);
+    }
 
-        let eth_bytes = ethernet_address.as_bytes();
-        ethernet_mac
+    /// Sets the descriptor lists, the MAC address and the address filter and starts the MAC
+    /// and the DMA.
+    fn start(&mut self) {
+        use byteorder::{ByteOrder, LittleEndian};
+
+        let rx_descriptors = &self.rx.descriptors[0] as *const Volatile<_> as u32;
+        let tx_descriptors = self.tx.front_of_queue() as *const Volatile<_> as u32;
+        self.ethernet_dma
+            .dmardlar
+            .write(|w| w.srl().bits(rx_descriptors));
+        self.ethernet_dma
+            .dmatdlar
+            .write(|w| w.stl().bits(tx_descriptors));
+
+        let eth_bytes = self.ethernet_address.as_bytes();
+        self.ethernet_mac
             .maca0lr
             .write(|w| w.maca0l().bits(LittleEndian::read_u32(&eth_bytes[..4])));
-        ethernet_mac
+        self.ethernet_mac
             .maca0hr
             .write(|w| w.maca0h().bits(LittleEndian::read_u16(&eth_bytes[4..])));
+        self.filter.apply(self.ethernet_mac);
 
-        init::start(ethernet_mac, ethernet_dma);
-        Ok(EthernetDevice {
-            rx: rx_device,
-            tx: tx_device,
-            ethernet_dma: ethernet_dma,
-            ethernet_address: ethernet_address,
-        })
+        init::start(self.ethernet_mac, self.ethernet_dma);
+    }
+
+    /// Enables the DMA interrupts, so that received and transmitted frames and errors are
+    /// reported through `take_events`.
+    ///
+    /// The `ETH` interrupt handler must call `handle_interrupt`.
+    pub fn listen(&mut self, nvic: &mut NVIC) {
+        interrupt::enable(self.ethernet_dma, nvic);
+    }
+
+    pub fn unlisten(&mut self, nvic: &mut NVIC) {
+        interrupt::disable(self.ethernet_dma, nvic);
     }
 
     pub fn into_interface<'a>(
@@ -108,9 +266,12 @@ impl<'d> EthernetDevice<'d> {
 }
 
 impl<'d> Drop for EthernetDevice<'d> {
+    /// Stops the DMA and the MAC and powers the PHY down. The descriptor rings and buffers are
+    /// freed afterwards, so the DMA must not access them anymore.
     fn drop(&mut self) {
-        // TODO stop ethernet device and wait for idle
-        unimplemented!();
+        // there is nothing to do about a timeout, the device is reset on the next initialization
+        let _ = init::stop(self.ethernet_mac, self.ethernet_dma);
+        phy::power_down(self.ethernet_mac);
     }
 }
 
@@ -119,13 +280,17 @@ impl<'a, 'd> Device<'a> for EthernetDevice<'d> {
     type TxToken = TxToken<'a>;
 
     fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        self.poll_link();
         if !self.rx.new_data_received() {
             return None;
         }
-        let rx = RxToken { rx: &mut self.rx };
+        let rx = RxToken {
+            rx: &mut self.rx,
+            ethernet_dma: self.ethernet_dma,
+        };
         let tx = TxToken {
             tx: &mut self.tx,
-            ethernet_dma: &mut self.ethernet_dma,
+            ethernet_dma: self.ethernet_dma,
         };
         Some((rx, tx))
     }
@@ -136,19 +301,26 @@ impl<'a, 'd> Device<'a> for EthernetDevice<'d> {
         }
         Some(TxToken {
             tx: &mut self.tx,
-            ethernet_dma: &mut self.ethernet_dma,
+            ethernet_dma: self.ethernet_dma,
         })
     }
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut capabilities = DeviceCapabilities::default();
         capabilities.max_transmission_unit = MTU;
+        // the MAC verifies the checksums of received frames and inserts them into transmitted
+        // frames
+        capabilities.checksum.ipv4 = Checksum::None;
+        capabilities.checksum.tcp = Checksum::None;
+        capabilities.checksum.udp = Checksum::None;
+        capabilities.checksum.icmpv4 = Checksum::None;
         capabilities
     }
 }
 
 pub struct RxToken<'a> {
     rx: &'a mut RxDevice,
+    ethernet_dma: &'a ETHERNET_DMA,
 }
 
 impl<'a> ::smoltcp::phy::RxToken for RxToken<'a> {
@@ -156,16 +328,29 @@ impl<'a> ::smoltcp::phy::RxToken for RxToken<'a> {
     where
         F: FnOnce(&[u8]) -> ::smoltcp::Result<R>,
     {
-        self.rx.receive(f).map_err(|err| match err {
+        let ret = self.rx.receive(f).map_err(|err| match err {
             ReceiveError::Processing(e) => e,
             _ => ::smoltcp::Error::Truncated,
-        })
+        });
+        self.resume_reception();
+        ret
+    }
+}
+
+impl<'a> RxToken<'a> {
+    /// Restarts the receive DMA if it was suspended because all descriptors were full.
+    fn resume_reception(&self) {
+        // read receive process state
+        if self.ethernet_dma.dmasr.read().rps().is_suspended() {
+            // write poll demand register
+            self.ethernet_dma.dmarpdr.write(|w| w.rpd().poll());
+        }
     }
 }
 
 pub struct TxToken<'a> {
     tx: &'a mut TxDevice,
-    ethernet_dma: &'a mut ETHERNET_DMA,
+    ethernet_dma: &'a ETHERNET_DMA,
 }
 
 impl<'a> ::smoltcp::phy::TxToken for TxToken<'a> {
@@ -173,9 +358,7 @@ impl<'a> ::smoltcp::phy::TxToken for TxToken<'a> {
     where
         F: FnOnce(&mut [u8]) -> ::smoltcp::Result<R>,
     {
-        let mut data = vec![0; len].into_boxed_slice();
-        let ret = f(&mut data)?;
-        self.tx.insert(data);
+        let ret = self.tx.transmit(len, f)?;
         self.start_send();
         Ok(ret)
     }
@@ -272,6 +455,14 @@ impl RxDevice {
         })
     }
 
+    /// Returns all descriptors to the hardware and discards their content.
+    fn reset(&mut self) {
+        for descriptor in self.descriptors.iter_mut() {
+            descriptor.update(|d| d.reset());
+        }
+        self.next_descriptor = 0;
+    }
+
     fn new_data_received(&self) -> bool {
         let descriptor = self.descriptors[self.next_descriptor].read();
         !descriptor.own() && descriptor.is_first_descriptor()
@@ -287,9 +478,6 @@ impl RxDevice {
         if descriptor.own() || !descriptor.is_first_descriptor() {
             return Err(ReceiveError::Processing(::smoltcp::Error::Exhausted));
         }
-        if let rx::ChecksumResult::Error(_, _) = descriptor.checksum_result() {
-            return Err(ReceiveError::Processing(::smoltcp::Error::Checksum));
-        }
 
         // find the last descriptor belonging to the received packet
         let mut last_descriptor = descriptor;
@@ -335,6 +523,12 @@ impl RxDevice {
                 error = Some(ReceiveError::Descriptor);
             }
         }
+        // the MAC forwards frames with IP header or payload checksum errors, they are dropped here
+        if error.is_none() {
+            if let rx::ChecksumResult::Error(_, _) = last_descriptor.checksum_result() {
+                error = Some(ReceiveError::Processing(::smoltcp::Error::Checksum));
+            }
+        }
 
         let ret = match error {
             Some(error) => Err(error),
@@ -364,8 +558,15 @@ impl RxDevice {
 }
 
 struct TxDevice {
+    /// One buffer of `MTU` bytes per descriptor.
+    buffer: Box<[u8]>,
     descriptors: Box<[Volatile<tx::TxDescriptor>]>,
+    /// The next descriptor to fill.
     next_descriptor: usize,
+    /// The oldest descriptor that was handed to the hardware and is not reclaimed yet.
+    oldest_descriptor: usize,
+    /// The number of descriptors that were handed to the hardware and are not reclaimed yet.
+    in_flight: usize,
 }
 
 impl TxDevice {
@@ -373,10 +574,11 @@ impl TxDevice {
         use self::tx::TxDescriptor;
 
         let descriptor_num = config.number_of_descriptors;
+        let buffer = vec![0; descriptor_num * MTU].into_boxed_slice();
         let mut descriptors = Vec::with_capacity(descriptor_num);
 
         for i in 0..descriptor_num {
-            let mut descriptor = TxDescriptor::empty();
+            let mut descriptor = TxDescriptor::new(&buffer[i * MTU]);
             if i == descriptor_num - 1 {
                 descriptor.set_end_of_ring(true);
             }
@@ -384,38 +586,62 @@ impl TxDevice {
         }
 
         TxDevice {
+            buffer: buffer,
             descriptors: descriptors.into_boxed_slice(),
             next_descriptor: 0,
+            oldest_descriptor: 0,
+            in_flight: 0,
         }
     }
 
-    fn descriptor_available(&self) -> bool {
-        !self.descriptors[self.next_descriptor].read().own()
+    /// Reclaims all descriptors and discards the frames that were not sent yet.
+    fn reset(&mut self) {
+        for descriptor in self.descriptors.iter_mut() {
+            descriptor.update(|d| d.reset());
+        }
+        self.next_descriptor = 0;
+        self.oldest_descriptor = 0;
+        self.in_flight = 0;
     }
 
-    pub fn insert(&mut self, data: Box<[u8]>) {
-        while self.descriptors[self.next_descriptor].read().own() {}
-        self.descriptors[self.next_descriptor].update(|d| d.set_data(data));
-        self.next_descriptor = (self.next_descriptor + 1) % self.descriptors.len();
-
+    fn descriptor_available(&mut self) -> bool {
         self.cleanup();
+        self.in_flight < self.descriptors.len()
+    }
+
+    /// Lets `f` write a frame of `len` bytes directly into the buffer of the next descriptor
+    /// and hands it to the hardware.
+    ///
+    /// Waits until a descriptor is available.
+    fn transmit<R, F>(&mut self, len: usize, f: F) -> ::smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> ::smoltcp::Result<R>,
+    {
+        assert!(len <= MTU, "frame is larger than the MTU");
+        while !self.descriptor_available() {}
+
+        let index = self.next_descriptor;
+        let offset = index * MTU;
+        let ret = f(&mut self.buffer[offset..(offset + len)])?;
+
+        // the frame must be in memory before the hardware owns the descriptor
+        asm::dmb();
+        self.descriptors[index].update(|d| d.transmit(len));
+        self.next_descriptor = (index + 1) % self.descriptors.len();
+        self.in_flight += 1;
+
+        Ok(ret)
     }
 
     pub fn front_of_queue(&self) -> &Volatile<tx::TxDescriptor> {
         self.descriptors.first().unwrap()
     }
 
+    /// Reclaims the descriptors of transmitted frames.
     pub fn cleanup(&mut self) {
-        let mut c = 0;
-        for descriptor in self.descriptors.iter_mut() {
-            descriptor.update(|d| {
-                if !d.own() && d.buffer().is_some() {
-                    c += 1;
-                }
-            });
-        }
-        if c > 0 {
-            // println!("cleaned up {} packets", c);
+        while self.in_flight > 0 && !self.descriptors[self.oldest_descriptor].read().own() {
+            self.oldest_descriptor = (self.oldest_descriptor + 1) % self.descriptors.len();
+            self.in_flight -= 1;
         }
     }
 }
@@ -453,6 +679,7 @@ impl Default for RxConfig {
     }
 }
 
+/// The transmit buffers are allocated up front, `MTU` bytes per descriptor.
 pub struct TxConfig {
     number_of_descriptors: usize,
 }
@@ -460,7 +687,7 @@ pub struct TxConfig {
 impl Default for TxConfig {
     fn default() -> TxConfig {
         TxConfig {
-            number_of_descriptors: 64,
+            number_of_descriptors: 8,
         }
     }
 }
@@ -8,83 +8,241 @@ const BASIC_CONTROL_REG: u8 = 0;
 const BASIC_STATUS_REG: u8 = 1; // basic status register
 const SPECIAL_STATUS_REG: u8 = 31; // special status register
 
+// basic control register
 const PHY_RESET: u16 = 1 << 15;
+const SPEED_100M: u16 = 1 << 13;
 const AUTONEGOTIATION_ENABLE: u16 = 1 << 12;
+const POWER_DOWN: u16 = 1 << 11;
 const AUTONEGOTIATION_RESTART: u16 = 1 << 9;
+const FULL_DUPLEX: u16 = 1 << 8;
+// basic status register bits
+const LINK_STATUS: usize = 2;
+const AUTONEGOTIATION_COMPLETE: usize = 5;
+// special status register bits
+const AUTONEGOTIATION_DONE: usize = 12;
 
 const TIMEOUT_MS: usize = 5000;
+/// The minimal time between two link checks, each check needs two MDIO reads.
+const POLL_INTERVAL_MS: usize = 100;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
-    LinkTimeout,
-    AutoNegotiationTimeout,
+    ResetTimeout,
 }
 
-pub struct AutoNegotiationResult {
-    pub duplex: bool,
-    pub speed: Speed,
-}
-
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Speed {
     Speed10M,
     Speed100M,
 }
 
-pub fn init(ethernet_mac: &mut ETHERNET_MAC) -> Result<AutoNegotiationResult, Error> {
-    // reset PHY
-    phy_write(
-        ethernet_mac,
-        LAN8742A_PHY_ADDRESS,
-        BASIC_CONTROL_REG,
-        PHY_RESET,
-    );
-    // wait 0.5s
-    system_clock::wait_ms(500);
-    // wait for reset bit auto clear
-    while phy_read(ethernet_mac, LAN8742A_PHY_ADDRESS, BASIC_CONTROL_REG) & PHY_RESET != 0 {}
-
-    // wait for link bit
-    let timeout_ticks = system_clock::ms_to_ticks(TIMEOUT_MS);
-    let ticks = system_clock::ticks();
-    while !phy_read(ethernet_mac, LAN8742A_PHY_ADDRESS, BASIC_STATUS_REG).get_bit(2) {
-        if system_clock::ticks() - ticks > timeout_ticks {
-            return Err(Error::LinkTimeout); // timeout
+/// The speed and duplex mode of a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    pub speed: Speed,
+    pub full_duplex: bool,
+}
+
+/// How the speed and duplex mode of the link are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    /// Negotiate the best mode that both sides support.
+    AutoNegotiation,
+    /// Use a fixed mode, e.g. for link partners without auto-negotiation.
+    Forced(Link),
+}
+
+impl Default for LinkMode {
+    fn default() -> LinkMode {
+        LinkMode::AutoNegotiation
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// No cable is connected or the link partner is down.
+    Down,
+    /// A link partner was detected and auto-negotiation is in progress.
+    Negotiating,
+    Up(Link),
+}
+
+/// The link state machine of the LAN8742A PHY.
+pub struct Phy {
+    mode: LinkMode,
+    state: LinkState,
+    last_poll: usize,
+    negotiation_start: usize,
+}
+
+impl Phy {
+    /// Resets the PHY and configures the link mode.
+    ///
+    /// Doesn't wait for a link, so this also succeeds without a cable. The link comes up in a
+    /// later call of `poll`.
+    pub fn new(ethernet_mac: &mut ETHERNET_MAC, mode: LinkMode) -> Result<Phy, Error> {
+        // reset PHY
+        phy_write(
+            ethernet_mac,
+            LAN8742A_PHY_ADDRESS,
+            BASIC_CONTROL_REG,
+            PHY_RESET,
+        );
+        // wait 0.5s
+        system_clock::wait_ms(500);
+        // wait for reset bit auto clear
+        let ticks = system_clock::ticks();
+        while phy_read(ethernet_mac, LAN8742A_PHY_ADDRESS, BASIC_CONTROL_REG) & PHY_RESET != 0 {
+            if system_clock::ticks().wrapping_sub(ticks) > system_clock::ms_to_ticks(TIMEOUT_MS) {
+                return Err(Error::ResetTimeout);
+            }
         }
+
+        let mut phy = Phy {
+            mode: mode,
+            state: LinkState::Down,
+            last_poll: system_clock::ms(),
+            negotiation_start: 0,
+        };
+        phy.configure(ethernet_mac);
+        Ok(phy)
     }
 
-    // enable auto-negotiation
-    phy_write(
-        ethernet_mac,
-        LAN8742A_PHY_ADDRESS,
-        BASIC_CONTROL_REG,
-        AUTONEGOTIATION_ENABLE | AUTONEGOTIATION_RESTART,
-    );
+    pub fn mode(&self) -> LinkMode {
+        self.mode
+    }
+
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    /// Changes the link mode, which takes the link down until it is established again.
+    ///
+    /// Returns `LinkState::Down` if the link was up before.
+    pub fn set_mode(
+        &mut self,
+        ethernet_mac: &mut ETHERNET_MAC,
+        mode: LinkMode,
+    ) -> Option<LinkState> {
+        let was_up = self.is_up();
+        self.mode = mode;
+        self.state = LinkState::Down;
+        self.configure(ethernet_mac);
+        if was_up {
+            Some(LinkState::Down)
+        } else {
+            None
+        }
+    }
+
+    /// Checks the link status, at most every `POLL_INTERVAL_MS` milliseconds.
+    ///
+    /// Returns the new state if the link went up or down. Auto-negotiation is restarted if it
+    /// doesn't complete in time.
+    pub fn poll(&mut self, ethernet_mac: &mut ETHERNET_MAC) -> Option<LinkState> {
+        let now = system_clock::ms();
+        if now.wrapping_sub(self.last_poll) < POLL_INTERVAL_MS {
+            return None;
+        }
+        self.last_poll = now;
+
+        // the link status bit latches low, so the second read returns the current status
+        phy_read(ethernet_mac, LAN8742A_PHY_ADDRESS, BASIC_STATUS_REG);
+        let bsr = phy_read(ethernet_mac, LAN8742A_PHY_ADDRESS, BASIC_STATUS_REG);
 
-    // wait until auto-negotiation complete bit is set
-    let ticks = system_clock::ticks();
-    while !phy_read(ethernet_mac, LAN8742A_PHY_ADDRESS, BASIC_STATUS_REG).get_bit(5) {
-        if system_clock::ticks() - ticks > timeout_ticks {
-            return Err(Error::AutoNegotiationTimeout); // timeout
+        if !bsr.get_bit(LINK_STATUS) {
+            let was_up = self.is_up();
+            self.state = LinkState::Down;
+            return if was_up { Some(self.state) } else { None };
         }
+        if self.is_up() {
+            return None;
+        }
+
+        let link = match self.mode {
+            LinkMode::Forced(link) => Some(link),
+            LinkMode::AutoNegotiation => {
+                if self.state == LinkState::Down {
+                    self.state = LinkState::Negotiating;
+                    self.negotiation_start = now;
+                }
+                if bsr.get_bit(AUTONEGOTIATION_COMPLETE) {
+                    negotiated_link(ethernet_mac)
+                } else {
+                    if now.wrapping_sub(self.negotiation_start) > TIMEOUT_MS {
+                        self.negotiation_start = now;
+                        self.configure(ethernet_mac);
+                    }
+                    None
+                }
+            }
+        };
+        link.map(|link| {
+            self.state = LinkState::Up(link);
+            self.state
+        })
     }
 
+    fn is_up(&self) -> bool {
+        match self.state {
+            LinkState::Up(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Writes the link mode to the PHY and restarts auto-negotiation.
+    fn configure(&mut self, ethernet_mac: &mut ETHERNET_MAC) {
+        let control = match self.mode {
+            LinkMode::AutoNegotiation => AUTONEGOTIATION_ENABLE | AUTONEGOTIATION_RESTART,
+            LinkMode::Forced(link) => {
+                let mut control = 0;
+                if link.speed == Speed::Speed100M {
+                    control |= SPEED_100M;
+                }
+                if link.full_duplex {
+                    control |= FULL_DUPLEX;
+                }
+                control
+            }
+        };
+        phy_write(
+            ethernet_mac,
+            LAN8742A_PHY_ADDRESS,
+            BASIC_CONTROL_REG,
+            control,
+        );
+    }
+}
+
+/// Reads the result of a completed auto-negotiation.
+fn negotiated_link(ethernet_mac: &mut ETHERNET_MAC) -> Option<Link> {
     let ssr = phy_read(ethernet_mac, LAN8742A_PHY_ADDRESS, SPECIAL_STATUS_REG);
-    // auto-negotiation done bit should be set
-    assert!(ssr.get_bit(12));
-    let (duplex, speed) = match ssr.get_bits(2..5) {
+    if !ssr.get_bit(AUTONEGOTIATION_DONE) {
+        return None;
+    }
+    let (full_duplex, speed) = match ssr.get_bits(2..5) {
         0b001 => (false, Speed::Speed10M),  // 10BASE-T half-duplex
         0b101 => (true, Speed::Speed10M),   // 10BASE-T full-duplex
         0b010 => (false, Speed::Speed100M), // 100BASE-TX half-duplex
         0b110 => (true, Speed::Speed100M),  // 100BASE-TX full-duplex
-        other => unreachable!("invalid auto-negotiation value: {:#b}", other),
+        _ => return None,
     };
-    Ok(AutoNegotiationResult {
-        duplex: duplex,
+    Some(Link {
         speed: speed,
+        full_duplex: full_duplex,
     })
 }
 
+/// Powers the PHY down. The reset in `Phy::new` powers it up again.
+pub fn power_down(ethernet_mac: &mut ETHERNET_MAC) {
+    phy_write(
+        ethernet_mac,
+        LAN8742A_PHY_ADDRESS,
+        BASIC_CONTROL_REG,
+        POWER_DOWN,
+    );
+}
+
 fn phy_read(ethernet_mac: &mut ETHERNET_MAC, phy_address: u8, register: u8) -> u16 {
     // set the MII address register
     ethernet_mac.macmiiar.modify(|r, w| {
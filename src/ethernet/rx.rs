@@ -130,7 +130,8 @@ impl RxDescriptor {
             (true, true, true) => ChecksumResult::Error(true, true),
             (false, false, true) => ChecksumResult::Passed(false, true),
             (false, true, true) => ChecksumResult::Passed(true, true),
-            (false, true, false) => unreachable!(),
+            // reserved, treated like a header checksum error
+            (false, true, false) => ChecksumResult::Error(true, false),
         }
     }
 }
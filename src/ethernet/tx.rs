@@ -1,6 +1,4 @@
-use alloc::boxed::Box;
 use bit_field::BitField;
-use core::{mem, slice};
 
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -21,16 +19,29 @@ impl TxDescriptor {
         }
     }
 
+    pub fn new(buffer_start: *const u8) -> TxDescriptor {
+        let mut descriptor = TxDescriptor::empty();
+        descriptor.set_buffer_1_address(buffer_start as usize);
+        descriptor
+    }
+
     pub fn set_end_of_ring(&mut self, value: bool) {
         self.word_0.set_bit(21, value);
     }
 
-    pub fn set_data(&mut self, data: Box<[u8]>) {
-        assert!(!self.own(), "descriptor is still owned by the hardware");
+    /// Takes the descriptor back from the hardware, which must be stopped.
+    pub fn reset(&mut self) {
+        let end_of_ring = self.word_0.get_bit(21);
+        self.word_0 = 0;
+        self.set_end_of_ring(end_of_ring);
+    }
 
-        mem::drop(self.buffer()); // drop old buffer if not already dropped
+    /// Hands the first `len` bytes of the buffer to the hardware as a complete frame.
+    pub fn transmit(&mut self, len: usize) {
+        assert!(!self.own(), "descriptor is still owned by the hardware");
 
-        self.set_buffer(data);
+        self.set_buffer_1_size(len);
+        self.set_checksum_insertion(ChecksumInsertion::Full);
         self.set_first_segment(true);
         self.set_last_segment(true);
         self.set_own(true);
@@ -40,26 +51,14 @@ impl TxDescriptor {
         self.word_0.get_bit(31)
     }
 
-    pub fn buffer(&mut self) -> Option<Box<[u8]>> {
-        assert!(!self.own(), "descriptor is still owned by the hardware");
-        match self.buffer_1_address() {
-            0 => None,
-            ptr => {
-                self.set_buffer_1_address(0);
-                Some(unsafe {
-                    Box::from_raw(slice::from_raw_parts_mut(
-                        ptr as *mut u8,
-                        self.buffer_1_size(),
-                    ))
-                })
-            }
-        }
-    }
-
     fn set_own(&mut self, value: bool) {
         self.word_0.set_bit(31, value);
     }
 
+    fn set_checksum_insertion(&mut self, value: ChecksumInsertion) {
+        self.word_0.set_bits(22..24, value as u32);
+    }
+
     fn set_first_segment(&mut self, value: bool) {
         self.word_0.set_bit(28, value);
     }
@@ -68,31 +67,29 @@ impl TxDescriptor {
         self.word_0.set_bit(29, value);
     }
 
-    fn set_buffer(&mut self, buffer: Box<[u8]>) {
-        assert_eq!(self.buffer_1_address(), 0);
-        self.set_buffer_1_address(buffer.as_ptr() as usize);
-        self.set_buffer_1_size(buffer.len());
-        mem::forget(buffer);
-    }
-
-    fn buffer_1_address(&self) -> usize {
-        assert_eq!(self.word_2 as usize as u32, self.word_2);
-        self.word_2 as usize
-    }
-
     fn set_buffer_1_address(&mut self, buffer_address: usize) {
         assert_eq!(buffer_address as u32 as usize, buffer_address);
         self.word_2 = buffer_address as u32;
     }
 
-    fn buffer_1_size(&self) -> usize {
-        let val = self.word_1.get_bits(0..13);
-        assert_eq!(val as usize as u32, val);
-        val as usize
-    }
-
     fn set_buffer_1_size(&mut self, size: usize) {
         assert_eq!(size as u32 as usize, size);
         self.word_1.set_bits(0..13, size as u32);
     }
 }
+
+/// The checksum insertion control (CIC) field. The hardware ignores it for non-IP frames and
+/// requires transmit store and forward mode.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ChecksumInsertion {
+    Disabled = 0b00,
+    /// Only the IPv4 header checksum.
+    IpHeader = 0b01,
+    /// The IPv4 header checksum and the TCP/UDP/ICMP checksum, with the pseudo-header checksum
+    /// already in the checksum field.
+    IpHeaderAndPayload = 0b10,
+    /// The IPv4 header checksum and the TCP/UDP/ICMP checksum including the pseudo-header.
+    Full = 0b11,
+}
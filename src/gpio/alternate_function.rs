@@ -0,0 +1,229 @@
+//! The alternate functions that are available on each pin of the STM32F74x, as listed in the
+//! alternate function mapping table of the datasheet.
+
+use super::{AlternateFunction, PinNumber, Port};
+
+/// Returns true if the pin has a peripheral function mapped to the given alternate function.
+pub fn alternate_function_supported(
+    port: Port,
+    pin: PinNumber,
+    alternate_fn: AlternateFunction,
+) -> bool {
+    let table = match port {
+        Port::PortA => &PORT_A,
+        Port::PortB => &PORT_B,
+        Port::PortC => &PORT_C,
+        Port::PortD => &PORT_D,
+        Port::PortE => &PORT_E,
+        Port::PortF => &PORT_F,
+        Port::PortG => &PORT_G,
+        Port::PortH => &PORT_H,
+        Port::PortI => &PORT_I,
+        Port::PortJ => &PORT_J,
+        Port::PortK => &PORT_K,
+    };
+    table[pin as usize] & (1 << alternate_fn as u16) != 0
+}
+
+/// Creates a bit mask of the given alternate function numbers. EVENTOUT (AF15) is available on
+/// all existing pins, so it is always included.
+macro_rules! af {
+    ($($af:expr),*) => {
+        (1 << 15) $(| (1 << $af))*
+    };
+}
+
+/// Port K only has the pins 0 to 7.
+const NONE: u16 = 0;
+
+const PORT_A: [u16; 16] = [
+    af!(1, 2, 3, 7, 8, 11),         // PA0
+    af!(1, 2, 7, 8, 9, 10, 11, 14), // PA1
+    af!(1, 2, 3, 7, 8, 11, 14),     // PA2
+    af!(1, 2, 3, 7, 10, 11, 14),    // PA3
+    af!(5, 6, 7, 12, 13, 14),       // PA4
+    af!(1, 3, 5, 10, 14),           // PA5
+    af!(1, 2, 3, 5, 9, 13, 14),     // PA6
+    af!(1, 2, 3, 5, 9, 11, 12),     // PA7
+    af!(0, 1, 3, 4, 7, 10, 14),     // PA8
+    af!(1, 4, 5, 7, 13),            // PA9
+    af!(1, 7, 10, 13),              // PA10
+    af!(1, 7, 9, 10, 14),           // PA11
+    af!(1, 7, 8, 9, 10, 14),        // PA12
+    af!(0),                         // PA13
+    af!(0),                         // PA14
+    af!(0, 1, 4, 5, 6, 8),          // PA15
+];
+
+const PORT_B: [u16; 16] = [
+    af!(1, 2, 3, 8, 9, 10, 11),         // PB0
+    af!(1, 2, 3, 9, 10, 11),            // PB1
+    af!(6, 7, 9),                       // PB2
+    af!(0, 1, 5, 6),                    // PB3
+    af!(0, 2, 5, 6, 7),                 // PB4
+    af!(2, 4, 5, 6, 9, 10, 11, 12, 13), // PB5
+    af!(2, 3, 4, 7, 9, 10, 12, 13),     // PB6
+    af!(2, 4, 7, 12, 13),               // PB7
+    af!(2, 3, 4, 9, 11, 12, 13, 14),    // PB8
+    af!(2, 3, 4, 5, 9, 12, 13, 14),     // PB9
+    af!(1, 4, 5, 7, 10, 11, 14),        // PB10
+    af!(1, 4, 7, 10, 11, 14),           // PB11
+    af!(1, 4, 5, 7, 9, 10, 11, 12),     // PB12
+    af!(1, 5, 7, 9, 10, 11),            // PB13
+    af!(1, 3, 5, 7, 9, 12),             // PB14
+    af!(0, 1, 3, 5, 9, 12),             // PB15
+];
+
+const PORT_C: [u16; 16] = [
+    af!(8, 10, 12, 14),               // PC0
+    af!(5, 6, 11),                    // PC1
+    af!(5, 10, 11, 12),               // PC2
+    af!(5, 10, 11, 12),               // PC3
+    af!(5, 8, 11, 12),                // PC4
+    af!(8, 11, 12),                   // PC5
+    af!(2, 3, 5, 8, 12, 13, 14),      // PC6
+    af!(2, 3, 6, 8, 12, 13, 14),      // PC7
+    af!(0, 2, 3, 7, 8, 12, 13),       // PC8
+    af!(0, 2, 3, 4, 5, 7, 9, 12, 13), // PC9
+    af!(6, 7, 8, 9, 12, 13, 14),      // PC10
+    af!(6, 7, 8, 9, 12, 13),          // PC11
+    af!(0, 6, 7, 8, 12, 13),          // PC12
+    af!(),                            // PC13
+    af!(),                            // PC14
+    af!(),                            // PC15
+];
+
+const PORT_D: [u16; 16] = [
+    af!(9, 12),                 // PD0
+    af!(9, 12),                 // PD1
+    af!(0, 2, 8, 12, 13),       // PD2
+    af!(5, 7, 12, 13, 14),      // PD3
+    af!(7, 12),                 // PD4
+    af!(7, 12),                 // PD5
+    af!(5, 6, 7, 12, 13, 14),   // PD6
+    af!(7, 8, 12),              // PD7
+    af!(7, 8, 12),              // PD8
+    af!(7, 12),                 // PD9
+    af!(7, 12, 14),             // PD10
+    af!(4, 7, 9, 10, 12),       // PD11
+    af!(2, 3, 4, 7, 9, 10, 12), // PD12
+    af!(2, 3, 4, 9, 10, 12),    // PD13
+    af!(2, 8, 12),              // PD14
+    af!(2, 8, 12),              // PD15
+];
+
+const PORT_E: [u16; 16] = [
+    af!(2, 3, 8, 10, 12, 13),           // PE0
+    af!(3, 8, 12, 13),                  // PE1
+    af!(0, 5, 6, 9, 11, 12),            // PE2
+    af!(0, 6, 12),                      // PE3
+    af!(0, 5, 6, 12, 13, 14),           // PE4
+    af!(0, 3, 5, 6, 12, 13, 14),        // PE5
+    af!(0, 1, 3, 5, 6, 10, 12, 13, 14), // PE6
+    af!(1, 8, 10, 12),                  // PE7
+    af!(1, 8, 10, 12),                  // PE8
+    af!(1, 8, 10, 12),                  // PE9
+    af!(1, 8, 10, 12),                  // PE10
+    af!(1, 5, 10, 12, 14),              // PE11
+    af!(1, 5, 10, 12, 14),              // PE12
+    af!(1, 5, 10, 12, 14),              // PE13
+    af!(1, 5, 10, 12, 14),              // PE14
+    af!(1, 12, 14),                     // PE15
+];
+
+const PORT_F: [u16; 16] = [
+    af!(4, 12),          // PF0
+    af!(4, 12),          // PF1
+    af!(4, 12),          // PF2
+    af!(12),             // PF3
+    af!(12),             // PF4
+    af!(12),             // PF5
+    af!(3, 5, 6, 8, 9),  // PF6
+    af!(3, 5, 6, 8, 9),  // PF7
+    af!(5, 6, 8, 9, 10), // PF8
+    af!(5, 6, 8, 9, 10), // PF9
+    af!(13, 14),         // PF10
+    af!(5, 10, 12, 13),  // PF11
+    af!(12),             // PF12
+    af!(4, 12),          // PF13
+    af!(4, 12),          // PF14
+    af!(4, 12),          // PF15
+];
+
+const PORT_G: [u16; 16] = [
+    af!(12),                        // PG0
+    af!(12),                        // PG1
+    af!(12),                        // PG2
+    af!(12),                        // PG3
+    af!(12),                        // PG4
+    af!(12),                        // PG5
+    af!(13, 14),                    // PG6
+    af!(8, 12, 13, 14),             // PG7
+    af!(5, 7, 8, 11, 12),           // PG8
+    af!(7, 8, 9, 10, 12, 13),       // PG9
+    af!(9, 10, 12, 13, 14),         // PG10
+    af!(7, 11, 13, 14),             // PG11
+    af!(3, 5, 7, 8, 9, 12, 14),     // PG12
+    af!(0, 3, 5, 8, 11, 12, 14),    // PG13
+    af!(0, 3, 5, 8, 9, 11, 12, 14), // PG14
+    af!(8, 12, 13),                 // PG15
+];
+
+const PORT_H: [u16; 16] = [
+    af!(),                     // PH0
+    af!(),                     // PH1
+    af!(3, 9, 10, 11, 12, 14), // PH2
+    af!(9, 10, 11, 12, 14),    // PH3
+    af!(4, 10),                // PH4
+    af!(4, 5, 12),             // PH5
+    af!(4, 5, 9, 11, 12, 13),  // PH6
+    af!(4, 5, 11, 12, 13),     // PH7
+    af!(4, 12, 13, 14),        // PH8
+    af!(4, 9, 12, 13, 14),     // PH9
+    af!(2, 4, 12, 13, 14),     // PH10
+    af!(2, 4, 12, 13, 14),     // PH11
+    af!(2, 4, 12, 13, 14),     // PH12
+    af!(3, 9, 12, 14),         // PH13
+    af!(3, 12, 13, 14),        // PH14
+    af!(3, 12, 13, 14),        // PH15
+];
+
+const PORT_I: [u16; 16] = [
+    af!(2, 5, 12, 13, 14),  // PI0
+    af!(3, 5, 12, 13, 14),  // PI1
+    af!(3, 5, 12, 13, 14),  // PI2
+    af!(3, 5, 12, 13),      // PI3
+    af!(3, 10, 12, 13, 14), // PI4
+    af!(3, 10, 12, 13, 14), // PI5
+    af!(3, 10, 12, 13, 14), // PI6
+    af!(3, 10, 12, 13, 14), // PI7
+    af!(),                  // PI8
+    af!(8, 9, 12, 14),      // PI9
+    af!(11, 12, 14),        // PI10
+    af!(10),                // PI11
+    af!(14),                // PI12
+    af!(14),                // PI13
+    af!(14),                // PI14
+    af!(14),                // PI15
+];
+
+const PORT_J: [u16; 16] = [af!(14); 16];
+
+const PORT_K: [u16; 16] = [
+    af!(14), // PK0
+    af!(14), // PK1
+    af!(14), // PK2
+    af!(14), // PK3
+    af!(14), // PK4
+    af!(14), // PK5
+    af!(14), // PK6
+    af!(14), // PK7
+    NONE,
+    NONE,
+    NONE,
+    NONE,
+    NONE,
+    NONE,
+    NONE,
+    NONE,
+];
@@ -0,0 +1,240 @@
+//! Parallel buses on multiple pins of a single port.
+//!
+//! A `PortBus` reads and writes all of its pins at once through a single access to the input
+//! data or bit set/reset register, so that no intermediate states appear on the bus. The bus
+//! value is a masked port word: bit `n` belongs to pin `n` of the port and bits of pins that are
+//! not part of the bus are ignored.
+//!
+//! Example
+//!
+//! ```ignore
+//! let data_pins = [Pin0, Pin1, Pin2, Pin3, Pin4, Pin5, Pin6, Pin7];
+//! let mut bus = gpio_j
+//!     .to_bus(&data_pins, OutputType::PushPull, OutputSpeed::High, Resistor::NoPull)
+//!     .expect("bus pins already in use");
+//! bus.write(0x5a);
+//! ```
+
+use super::*;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use arrayvec::ArrayVec;
+use core::marker::PhantomData;
+use stm32f7::stm32f7x6::{DMA2, RCC};
+
+pub struct PortBus<'a, T: RegisterBlockTrait<'a>> {
+    register_block: T,
+    pins: ArrayVec<[PinNumber; 16]>,
+    mask: u16,
+    phantom: PhantomData<&'a ()>,
+}
+
+impl<'a, T: RegisterBlockTrait<'a> + Copy> GpioPort<T> {
+    /// Claims the pins and configures them as outputs of a bus.
+    pub fn to_bus(
+        &mut self,
+        pins: &[PinNumber],
+        out_type: OutputType,
+        out_speed: OutputSpeed,
+        resistor: Resistor,
+    ) -> Result<PortBus<'a, T>, Error> {
+        self.use_pins(pins)?;
+
+        let mut bus = PortBus {
+            register_block: self.register_block,
+            pins: pins.iter().cloned().collect(),
+            mask: pins.iter().fold(0, |mask, &pin| mask | 1 << pin as u16),
+            phantom: PhantomData,
+        };
+        bus.set_output(out_type, out_speed, resistor);
+        Ok(bus)
+    }
+}
+
+impl<'a, T: RegisterBlockTrait<'a>> PortBus<'a, T> {
+    /// The pins of the bus as port word mask.
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    /// Switches all pins of the bus to output, e.g. for the write phase of a bidirectional bus.
+    pub fn set_output(&mut self, out_type: OutputType, out_speed: OutputSpeed, resistor: Resistor) {
+        self.register_block.set_out_type(&self.pins, out_type);
+        self.register_block.set_out_speed(&self.pins, out_speed);
+        self.register_block.set_resistor(&self.pins, resistor);
+        self.register_block.set_mode(&self.pins, Mode::Output);
+    }
+
+    /// Switches all pins of the bus to input, e.g. for the read phase of a bidirectional bus.
+    pub fn set_input(&mut self, resistor: Resistor) {
+        self.register_block.set_mode(&self.pins, Mode::Input);
+        self.register_block.set_resistor(&self.pins, resistor);
+    }
+
+    /// Sets all bus pins to the corresponding bits of `value` at the same time.
+    pub fn write(&mut self, value: u16) {
+        let bsrr = BsrrRef {
+            register: self.register_block.bsrr() as *const _ as *mut _,
+            phantom: PhantomData,
+        };
+        bsrr.set_bits(self.bsrr_word(value));
+    }
+
+    /// Reads the input state of all bus pins at the same time.
+    pub fn read(&self) -> u16 {
+        self.register_block.idr().read().bits() & self.mask
+    }
+
+    /// Returns the bit set/reset register value that sets the bus to `value`.
+    pub fn bsrr_word(&self, value: u16) -> u32 {
+        let set = value & self.mask;
+        let reset = !value & self.mask;
+        u32::from(set) | u32::from(reset) << 16
+    }
+
+    /// Resets the bus pins and gives them back to the port.
+    pub fn release(mut self, port: &mut GpioPort<T>) {
+        self.register_block.reset(&self.pins);
+        for &pin in &self.pins {
+            port.pin_in_use[pin as usize] = false;
+        }
+    }
+}
+
+/// The timer update event that paces a DMA pattern output.
+///
+/// The timer must be configured by the application to generate a DMA request on every update
+/// (UDE bit in the DIER register). The DMA2 streams used for the two timers must not be used by
+/// anything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternTrigger {
+    /// TIM1 update, DMA2 stream 5 channel 6.
+    Tim1Update,
+    /// TIM8 update, DMA2 stream 1 channel 7.
+    Tim8Update,
+}
+
+/// A bus that outputs a sequence of values from memory through DMA.
+///
+/// The DMA transfer is stopped when the pattern output is dropped.
+pub struct PatternOutput<'a, T: RegisterBlockTrait<'a>> {
+    bus: Option<PortBus<'a, T>>,
+    dma: &'a DMA2,
+    trigger: PatternTrigger,
+    /// The bit set/reset words that are read by the DMA, must live until the transfer is stopped.
+    words: Box<[u32]>,
+}
+
+// the transfer complete flag and all other flags of stream 1 (in LISR) and stream 5 (in HISR)
+const TRANSFER_COMPLETE_FLAG: u32 = 1 << 11;
+const STREAM_FLAGS: u32 = 0b1111_0100_0000;
+
+impl<'a, T: RegisterBlockTrait<'a>> PortBus<'a, T> {
+    /// Starts writing `values` to the bus, one value per trigger event.
+    ///
+    /// If `circular` is true, the pattern is repeated until `PatternOutput::stop` is called.
+    pub fn start_pattern(
+        self,
+        values: &[u16],
+        circular: bool,
+        trigger: PatternTrigger,
+        dma: &'a DMA2,
+        rcc: &mut RCC,
+    ) -> PatternOutput<'a, T> {
+        assert!(!values.is_empty(), "pattern is empty");
+        assert!(
+            values.len() <= 0xffff,
+            "pattern is too long for a single DMA transfer"
+        );
+
+        let words: Vec<u32> = values.iter().map(|&v| self.bsrr_word(v)).collect();
+        let words = words.into_boxed_slice();
+        let peripheral_address = self.register_block.bsrr() as *const _ as u32;
+        let memory_address = words.as_ptr() as u32;
+        let len = words.len() as u16;
+
+        rcc.ahb1enr.modify(|_, w| w.dma2en().enabled());
+
+        macro_rules! start_stream {
+            ($cr:ident, $ndtr:ident, $par:ident, $m0ar:ident, $fcr:ident, $ifcr:ident, $chsel:expr) => {{
+                dma.$cr.modify(|_, w| w.en().clear_bit()); // stream_enable
+                while dma.$cr.read().en().bit_is_set() {}
+                dma.$ifcr.write(|w| unsafe { w.bits(STREAM_FLAGS) });
+
+                // peripheral_address, memory_0_address and number_of_data_items
+                dma.$par
+                    .write(|w| unsafe { w.pa().bits(peripheral_address) });
+                dma.$m0ar.write(|w| unsafe { w.m0a().bits(memory_address) });
+                dma.$ndtr.write(|w| unsafe { w.ndt().bits(len) });
+                dma.$fcr.write(|w| w.dmdis().clear_bit()); // direct_mode_disable
+                dma.$cr.write(|w| unsafe {
+                    w.chsel().bits($chsel); // channel_selection
+                    w.pl().bits(0b11); // priority_level = very high
+                    w.msize().bits(0b10); // memory_data_size = word
+                    w.psize().bits(0b10); // peripheral_data_size = word
+                    w.minc().set_bit(); // memory_increment_mode
+                    w.pinc().clear_bit(); // peripheral_increment_mode
+                    w.circ().bit(circular); // circular_mode
+                    w.dir().bits(0b01); // data_transfer_direction = memory-to-peripheral
+                    w
+                });
+                dma.$cr.modify(|_, w| w.en().set_bit()); // stream_enable
+            }};
+        }
+
+        match trigger {
+            PatternTrigger::Tim1Update => {
+                start_stream!(s5cr, s5ndtr, s5par, s5m0ar, s5fcr, hifcr, 6)
+            }
+            PatternTrigger::Tim8Update => {
+                start_stream!(s1cr, s1ndtr, s1par, s1m0ar, s1fcr, lifcr, 7)
+            }
+        }
+
+        PatternOutput {
+            bus: Some(self),
+            dma: dma,
+            trigger: trigger,
+            words: words,
+        }
+    }
+}
+
+impl<'a, T: RegisterBlockTrait<'a>> PatternOutput<'a, T> {
+    /// Returns true if a non-circular pattern was written completely.
+    pub fn is_complete(&self) -> bool {
+        match self.trigger {
+            PatternTrigger::Tim1Update => self.dma.hisr.read().bits() & TRANSFER_COMPLETE_FLAG != 0,
+            PatternTrigger::Tim8Update => self.dma.lisr.read().bits() & TRANSFER_COMPLETE_FLAG != 0,
+        }
+    }
+
+    /// Stops the DMA transfer and returns the bus.
+    pub fn stop(mut self) -> PortBus<'a, T> {
+        // the transfer is stopped when `self` is dropped at the end of this function
+        self.bus.take().expect("pattern output already stopped")
+    }
+
+    fn disable_stream(&self) {
+        let dma = self.dma;
+        match self.trigger {
+            PatternTrigger::Tim1Update => {
+                dma.s5cr.modify(|_, w| w.en().clear_bit()); // stream_enable
+                while dma.s5cr.read().en().bit_is_set() {}
+                dma.hifcr.write(|w| unsafe { w.bits(STREAM_FLAGS) });
+            }
+            PatternTrigger::Tim8Update => {
+                dma.s1cr.modify(|_, w| w.en().clear_bit()); // stream_enable
+                while dma.s1cr.read().en().bit_is_set() {}
+                dma.lifcr.write(|w| unsafe { w.bits(STREAM_FLAGS) });
+            }
+        }
+    }
+}
+
+impl<'a, T: RegisterBlockTrait<'a>> Drop for PatternOutput<'a, T> {
+    fn drop(&mut self) {
+        // the DMA must not access the buffer anymore when it is freed
+        self.disable_stream();
+    }
+}
@@ -0,0 +1,229 @@
+//! External interrupts (EXTI) for gpio input pins.
+//!
+//! Each pin number has a single EXTI line that is shared between all ports, so only one of the
+//! pins `PA0`, `PB0`, …, `PK0` can be routed to EXTI line 0 at a time. Lines 0 to 4 have their
+//! own interrupt vector, lines 5 to 9 share `EXTI9_5` and lines 10 to 15 share `EXTI15_10`. All
+//! these vectors should call `handle_interrupt`, which clears the pending flags and calls the
+//! registered handlers.
+//!
+//! Example
+//!
+//! ```ignore
+//! let mut exti = Exti::new(&peripherals.EXTI, &mut rcc);
+//! let button = exti
+//!     .listen(pins.button, Edge::Rising, button_pressed, &mut syscfg, &mut nvic)
+//!     .expect("EXTI line of the button already in use");
+//!
+//! interrupt!(EXTI15_10, exti15_10);
+//!
+//! fn exti15_10() {
+//!     exti::handle_interrupt();
+//! }
+//! ```
+
+use super::{Error, InputPin, PinNumber, Port};
+use cortex_m::interrupt;
+use cortex_m::peripheral::NVIC;
+use spin::Mutex;
+use stm32f7::stm32f7x6::{exti, Interrupt, EXTI, RCC, SYSCFG};
+
+/// Called in interrupt context when the configured edge occurs on the pin.
+pub type Handler = fn();
+
+static HANDLERS: Mutex<[Option<Handler>; 16]> = Mutex::new([None; 16]);
+
+/// The signal edges that trigger an interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+pub struct Exti<'a> {
+    exti: &'a EXTI,
+    lines_in_use: [bool; 16],
+}
+
+impl<'a> Exti<'a> {
+    /// Enables the SYSCFG clock, which is needed for selecting the port of the EXTI lines.
+    pub fn new(exti: &'a EXTI, rcc: &mut RCC) -> Self {
+        rcc.apb2enr.modify(|_, w| w.syscfgen().set_bit());
+        // delay after an RCC peripheral clock enabling
+        let _unused = rcc.apb2enr.read();
+
+        Exti {
+            exti: exti,
+            lines_in_use: [false; 16],
+        }
+    }
+
+    /// Routes the pin to its EXTI line and enables the interrupt for the given edges.
+    ///
+    /// The `handler` is called from `handle_interrupt` whenever the edge occurs.
+    pub fn listen<P: InputPin>(
+        &mut self,
+        pin: P,
+        edge: Edge,
+        handler: Handler,
+        syscfg: &mut SYSCFG,
+        nvic: &mut NVIC,
+    ) -> Result<ExtiPin<P>, Error> {
+        let line = pin.pin();
+        if self.lines_in_use[line as usize] {
+            return Err(Error::ExtiLineInUse(line));
+        }
+        self.lines_in_use[line as usize] = true;
+
+        select_port(syscfg, pin.port(), line);
+
+        interrupt::free(|_| HANDLERS.lock()[line as usize] = Some(handler));
+
+        let mask = 1 << line as u32;
+        let (rising, falling) = match edge {
+            Edge::Rising => (true, false),
+            Edge::Falling => (false, true),
+            Edge::Both => (true, true),
+        };
+        self.exti.rtsr.modify(|r, w| unsafe {
+            w.bits(if rising {
+                r.bits() | mask
+            } else {
+                r.bits() & !mask
+            })
+        });
+        self.exti.ftsr.modify(|r, w| unsafe {
+            w.bits(if falling {
+                r.bits() | mask
+            } else {
+                r.bits() & !mask
+            })
+        });
+
+        // clear a stale pending flag before unmasking the line
+        self.exti.pr.write(|w| unsafe { w.bits(mask) });
+        self.exti
+            .imr
+            .modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+
+        nvic.enable(interrupt_for_line(line));
+
+        Ok(ExtiPin { pin: pin })
+    }
+
+    /// Disables the interrupt of the pin and frees its EXTI line.
+    ///
+    /// The NVIC vector stays enabled since it might be shared with other lines.
+    pub fn unlisten<P: InputPin>(&mut self, exti_pin: ExtiPin<P>) -> P {
+        let line = exti_pin.pin.pin();
+        let mask = 1 << line as u32;
+
+        self.exti
+            .imr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+        self.exti
+            .rtsr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+        self.exti
+            .ftsr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+        self.exti.pr.write(|w| unsafe { w.bits(mask) });
+
+        interrupt::free(|_| HANDLERS.lock()[line as usize] = None);
+        self.lines_in_use[line as usize] = false;
+
+        exti_pin.pin
+    }
+}
+
+/// An input pin whose EXTI line is enabled.
+pub struct ExtiPin<P: InputPin> {
+    pin: P,
+}
+
+impl<P: InputPin> ExtiPin<P> {
+    /// Returns true if the configured edge occurred and the pending flag was not cleared yet.
+    pub fn is_pending(&self) -> bool {
+        let mask = 1 << self.pin.pin() as u32;
+        registers().pr.read().bits() & mask != 0
+    }
+
+    /// Clears the pending flag of the EXTI line.
+    pub fn clear_pending(&mut self) {
+        let mask = 1 << self.pin.pin() as u32;
+        registers().pr.write(|w| unsafe { w.bits(mask) });
+    }
+}
+
+impl<P: InputPin> InputPin for ExtiPin<P> {
+    fn get(&self) -> bool {
+        self.pin.get()
+    }
+
+    fn port(&self) -> Port {
+        self.pin.port()
+    }
+
+    fn pin(&self) -> PinNumber {
+        self.pin.pin()
+    }
+}
+
+/// Clears the pending flags of all enabled EXTI lines and calls their handlers.
+///
+/// Call this from the `EXTI0` to `EXTI4`, `EXTI9_5` and `EXTI15_10` interrupt handlers.
+pub fn handle_interrupt() {
+    let exti = registers();
+    let pending = exti.pr.read().bits() & exti.imr.read().bits() & 0xffff;
+    // the pending flags are cleared by writing 1, so other lines are not affected
+    exti.pr.write(|w| unsafe { w.bits(pending) });
+
+    let handlers = interrupt::free(|_| *HANDLERS.lock());
+    for line in 0..16 {
+        if pending & (1 << line) != 0 {
+            if let Some(handler) = handlers[line] {
+                handler();
+            }
+        }
+    }
+}
+
+fn registers() -> &'static exti::RegisterBlock {
+    // only the write-1-to-clear pending register and the read-only mask register are accessed
+    // through this reference, so there are no read-modify-write races with the `Exti` owner
+    unsafe { &*EXTI::ptr() }
+}
+
+fn select_port(syscfg: &mut SYSCFG, port: Port, line: PinNumber) {
+    // each EXTICR register selects the port of four lines, using four bits per line
+    let shift = (line as u32 % 4) * 4;
+    let bits = |old: u32| old & !(0xf << shift) | (port as u32) << shift;
+    match line as u8 / 4 {
+        0 => syscfg
+            .exticr1
+            .modify(|r, w| unsafe { w.bits(bits(r.bits())) }),
+        1 => syscfg
+            .exticr2
+            .modify(|r, w| unsafe { w.bits(bits(r.bits())) }),
+        2 => syscfg
+            .exticr3
+            .modify(|r, w| unsafe { w.bits(bits(r.bits())) }),
+        3 => syscfg
+            .exticr4
+            .modify(|r, w| unsafe { w.bits(bits(r.bits())) }),
+        _ => unreachable!(),
+    }
+}
+
+fn interrupt_for_line(line: PinNumber) -> Interrupt {
+    use super::PinNumber::*;
+    match line {
+        Pin0 => Interrupt::EXTI0,
+        Pin1 => Interrupt::EXTI1,
+        Pin2 => Interrupt::EXTI2,
+        Pin3 => Interrupt::EXTI3,
+        Pin4 => Interrupt::EXTI4,
+        Pin5 | Pin6 | Pin7 | Pin8 | Pin9 => Interrupt::EXTI9_5,
+        Pin10 | Pin11 | Pin12 | Pin13 | Pin14 | Pin15 => Interrupt::EXTI15_10,
+    }
+}
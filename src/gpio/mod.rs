@@ -1,14 +1,40 @@
 use core::marker::PhantomData;
 
+pub use self::alternate_function::alternate_function_supported;
 pub use self::port::*;
 pub use self::traits::*;
 
+mod alternate_function;
+pub mod bus;
+pub mod exti;
+pub mod pin;
 mod port;
 mod traits;
 
 #[derive(Debug)]
 pub enum Error {
     PinAlreadyInUse(PinNumber),
+    /// The EXTI line of the pin is already used by the same pin number of another port.
+    ExtiLineInUse(PinNumber),
+    /// The pin has no peripheral function mapped to the alternate function.
+    AlternateFunctionNotSupported(PinNumber, AlternateFunction),
+}
+
+/// The GPIO ports of the microcontroller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Port {
+    PortA = 0,
+    PortB,
+    PortC,
+    PortD,
+    PortE,
+    PortF,
+    PortG,
+    PortH,
+    PortI,
+    PortJ,
+    PortK,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -83,9 +109,14 @@ pub enum PinNumber {
 
 pub trait InputPin: Sized {
     fn get(&self) -> bool;
+
+    fn port(&self) -> Port;
+
+    fn pin(&self) -> PinNumber;
 }
 
 pub struct InputPinImpl<'a, IDR: IdrTrait + 'a> {
+    port: Port,
     pin: PinNumber,
     input_data: &'a IDR,
 }
@@ -98,6 +129,14 @@ where
         let value = self.input_data.read();
         value.get(self.pin)
     }
+
+    fn port(&self) -> Port {
+        self.port
+    }
+
+    fn pin(&self) -> PinNumber {
+        self.pin
+    }
 }
 
 pub trait OutputPin: Sized {
@@ -147,4 +186,8 @@ where
     fn set(&self, pin: PinNumber, value: bool) {
         unsafe { (&mut *self.register) }.write(|w| if value { w.set(pin) } else { w.reset(pin) });
     }
+
+    fn set_bits(&self, bits: u32) {
+        unsafe { (&mut *self.register) }.write(|w| w.bits(bits));
+    }
 }
@@ -0,0 +1,310 @@
+//! Type-state GPIO pins.
+//!
+//! The pins of a `GpioPort` are taken as `Pin`s that encode the port, the pin number and the
+//! current mode in their type. `split` consumes the port and returns all of its pins, so each
+//! pin exists only once and a second use is a compile error. The `into_*` methods reconfigure a
+//! pin and return it with its new mode, and `release` restores the reset configuration and
+//! returns the pin as `Unused`, so that it can be configured again later.
+//!
+//! Ports that are also used through the pin number based methods of `GpioPort`, like in
+//! `init::pins`, hand out single pins through `take_pin` instead, which is checked at runtime.
+//! Such pins can be handed back to the port with `GpioPort::return_pin`.
+//!
+//! Example
+//!
+//! ```ignore
+//! let pins_i = gpio_i.split();
+//!
+//! let mut led = pins_i
+//!     .pin_1
+//!     .into_output(OutputType::PushPull, OutputSpeed::Low, Resistor::NoPull);
+//! led.set(true);
+//!
+//! // reuse the pin as input later
+//! let input = led.release().into_input(Resistor::PullDown);
+//! ```
+
+use super::*;
+use core::marker::PhantomData;
+
+/// A GPIO port at the type level.
+pub trait PortId<'a> {
+    const PORT: Port;
+
+    type Registers: RegisterBlockTrait<'a> + Copy;
+}
+
+/// A pin number at the type level.
+pub trait PinId {
+    const PIN: PinNumber;
+}
+
+macro_rules! port_ids {
+    ($($name:ident: $port:ident, $registers:ident;)*) => {
+        $(
+            pub struct $name;
+
+            impl<'a> PortId<'a> for $name {
+                const PORT: Port = Port::$port;
+
+                type Registers = $registers<'a>;
+            }
+        )*
+    };
+}
+
+port_ids! {
+    PA: PortA, RegisterBlockA;
+    PB: PortB, RegisterBlockB;
+    PC: PortC, RegisterBlockC;
+    PD: PortD, RegisterBlockD;
+    PE: PortE, RegisterBlockE;
+    PF: PortF, RegisterBlockF;
+    PG: PortG, RegisterBlockG;
+    PH: PortH, RegisterBlockH;
+    PI: PortI, RegisterBlockI;
+    PJ: PortJ, RegisterBlockJ;
+    PK: PortK, RegisterBlockK;
+}
+
+macro_rules! pin_ids {
+    ($($name:ident: $pin:ident,)*) => {
+        $(
+            pub struct $name;
+
+            impl PinId for $name {
+                const PIN: PinNumber = PinNumber::$pin;
+            }
+        )*
+    };
+}
+
+pin_ids! {
+    P0: Pin0,
+    P1: Pin1,
+    P2: Pin2,
+    P3: Pin3,
+    P4: Pin4,
+    P5: Pin5,
+    P6: Pin6,
+    P7: Pin7,
+    P8: Pin8,
+    P9: Pin9,
+    P10: Pin10,
+    P11: Pin11,
+    P12: Pin12,
+    P13: Pin13,
+    P14: Pin14,
+    P15: Pin15,
+}
+
+/// The pin is in its reset state and not used by anyone.
+pub struct Unused;
+/// The pin is configured as input.
+pub struct Input;
+/// The pin is configured as output.
+pub struct Output;
+/// The pin is connected to a peripheral.
+pub struct Alternate;
+/// The pin is configured as analog input or output.
+pub struct Analog;
+
+pub struct Pin<'a, P: PortId<'a>, N: PinId, MODE> {
+    registers: P::Registers,
+    phantom: PhantomData<(N, MODE)>,
+}
+
+impl<'a, P: PortId<'a>, N: PinId, MODE> Pin<'a, P, N, MODE> {
+    fn new(registers: P::Registers) -> Self {
+        Pin {
+            registers: registers,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn port(&self) -> Port {
+        P::PORT
+    }
+
+    pub fn pin(&self) -> PinNumber {
+        N::PIN
+    }
+
+    pub fn into_input(mut self, resistor: Resistor) -> Pin<'a, P, N, Input> {
+        self.registers.set_mode(&[N::PIN], Mode::Input);
+        self.registers.set_resistor(&[N::PIN], resistor);
+        Pin::new(self.registers)
+    }
+
+    pub fn into_output(
+        mut self,
+        out_type: OutputType,
+        out_speed: OutputSpeed,
+        resistor: Resistor,
+    ) -> Pin<'a, P, N, Output> {
+        self.registers.set_mode(&[N::PIN], Mode::Output);
+        self.registers.set_out_type(&[N::PIN], out_type);
+        self.registers.set_out_speed(&[N::PIN], out_speed);
+        self.registers.set_resistor(&[N::PIN], resistor);
+        Pin::new(self.registers)
+    }
+
+    /// Panics if the pin does not support the alternate function.
+    pub fn into_alternate_function(
+        mut self,
+        alternate_fn: AlternateFunction,
+        typ: OutputType,
+        speed: OutputSpeed,
+        resistor: Resistor,
+    ) -> Pin<'a, P, N, Alternate> {
+        assert!(
+            alternate_function_supported(P::PORT, N::PIN, alternate_fn),
+            "{:?} of {:?} does not support {:?}",
+            N::PIN,
+            P::PORT,
+            alternate_fn
+        );
+
+        self.registers.set_mode(&[N::PIN], Mode::Alternate);
+        self.registers.set_resistor(&[N::PIN], resistor);
+        self.registers.set_out_type(&[N::PIN], typ);
+        self.registers.set_out_speed(&[N::PIN], speed);
+        self.registers.set_alternate_fn(&[N::PIN], alternate_fn);
+        Pin::new(self.registers)
+    }
+
+    pub fn into_analog(mut self) -> Pin<'a, P, N, Analog> {
+        self.registers.set_mode(&[N::PIN], Mode::Analog);
+        self.registers.set_resistor(&[N::PIN], Resistor::NoPull);
+        Pin::new(self.registers)
+    }
+
+    /// Restores the reset configuration of the pin.
+    pub fn release(mut self) -> Pin<'a, P, N, Unused> {
+        self.registers.reset(&[N::PIN]);
+        Pin::new(self.registers)
+    }
+}
+
+impl<'a, P: PortId<'a>, N: PinId> InputPin for Pin<'a, P, N, Input> {
+    fn get(&self) -> bool {
+        let value = self.registers.idr().read();
+        value.get(N::PIN)
+    }
+
+    fn port(&self) -> Port {
+        P::PORT
+    }
+
+    fn pin(&self) -> PinNumber {
+        N::PIN
+    }
+}
+
+impl<'a, P: PortId<'a>, N: PinId> OutputPin for Pin<'a, P, N, Output> {
+    fn get(&self) -> bool {
+        let value = self.registers.odr().read();
+        value.get(N::PIN)
+    }
+
+    fn set(&mut self, value: bool) {
+        let bit_set_reset = BsrrRef {
+            register: self.registers.bsrr() as *const _ as *mut _,
+            phantom: PhantomData,
+        };
+        bit_set_reset.set(N::PIN, value);
+    }
+}
+
+macro_rules! parts {
+    ($(#[$attr:meta])* $parts:ident { $($field:ident: $pin:ident,)* }
+     $($registers:ident: $port:ident,)*) => {
+        $(#[$attr])*
+        pub struct $parts<'a, P: PortId<'a>> {
+            $(pub $field: Pin<'a, P, $pin, Unused>,)*
+        }
+
+        $(
+            impl<'a> GpioPort<$registers<'a>> {
+                /// Consumes the port and returns all of its pins as type-state pins.
+                ///
+                /// Panics if a pin of the port is already in use.
+                pub fn split(self) -> $parts<'a, $port> {
+                    assert!(
+                        self.pin_in_use.iter().all(|&in_use| !in_use),
+                        "only a port without used pins can be split"
+                    );
+                    $parts {
+                        $($field: Pin::new(self.register_block),)*
+                    }
+                }
+            }
+        )*
+    };
+}
+
+parts! {
+    /// The pins of a port, created by `GpioPort::split`.
+    Parts {
+        pin_0: P0,
+        pin_1: P1,
+        pin_2: P2,
+        pin_3: P3,
+        pin_4: P4,
+        pin_5: P5,
+        pin_6: P6,
+        pin_7: P7,
+        pin_8: P8,
+        pin_9: P9,
+        pin_10: P10,
+        pin_11: P11,
+        pin_12: P12,
+        pin_13: P13,
+        pin_14: P14,
+        pin_15: P15,
+    }
+    RegisterBlockA: PA,
+    RegisterBlockB: PB,
+    RegisterBlockC: PC,
+    RegisterBlockD: PD,
+    RegisterBlockE: PE,
+    RegisterBlockF: PF,
+    RegisterBlockG: PG,
+    RegisterBlockH: PH,
+    RegisterBlockI: PI,
+    RegisterBlockJ: PJ,
+}
+
+parts! {
+    /// The pins of port K, which only has the pins 0 to 7.
+    PartsK {
+        pin_0: P0,
+        pin_1: P1,
+        pin_2: P2,
+        pin_3: P3,
+        pin_4: P4,
+        pin_5: P5,
+        pin_6: P6,
+        pin_7: P7,
+    }
+    RegisterBlockK: PK,
+}
+
+impl<'a, T: RegisterBlockTrait<'a> + Copy> GpioPort<T>
+where
+    T::Id: PortId<'a, Registers = T>,
+{
+    /// Takes a single type-state pin out of a port that is also used through its pin number
+    /// based methods.
+    ///
+    /// Returns an error if the pin is already in use.
+    pub fn take_pin<N: PinId>(&mut self) -> Result<Pin<'a, T::Id, N, Unused>, Error> {
+        self.use_pin(N::PIN)?;
+        Ok(Pin::new(self.register_block))
+    }
+
+    /// Hands a pin that was taken through `take_pin` back to the port.
+    pub fn return_pin<N: PinId>(&mut self, _pin: Pin<'a, T::Id, N, Unused>) {
+        self.pin_in_use[N::PIN as usize] = false;
+    }
+}
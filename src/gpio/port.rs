@@ -1,13 +1,18 @@
+use super::pin::{PA, PB, PC, PD, PE, PF, PG, PH, PI, PJ, PK};
 use super::*;
 use core::marker::PhantomData;
-use stm32f7::stm32f7x6::{gpioa, gpiob, gpiod};
+use cortex_m::interrupt;
+use stm32f7::stm32f7x6::{
+    gpioa, gpiob, gpiod, GPIOA, GPIOB, GPIOC, GPIOD, GPIOE, GPIOF, GPIOG, GPIOH, GPIOI, GPIOJ,
+    GPIOK,
+};
 
 pub struct GpioPort<T> {
     pub(super) pin_in_use: [bool; 16],
-    register_block: T,
+    pub(super) register_block: T,
 }
 
-pub struct RegisterBlock<'a, I: 'a, O: 'a, M: 'a, P: 'a, B: 'a, T: 'a, S: 'a, AH: 'a, AL: 'a> {
+pub struct RegisterBlock<'a, I: 'a, O: 'a, M: 'a, P: 'a, B: 'a, T: 'a, S: 'a, AH: 'a, AL: 'a, ID> {
     idr: &'a I,
     odr: &'a O,
     moder: &'a M,
@@ -17,44 +22,54 @@ pub struct RegisterBlock<'a, I: 'a, O: 'a, M: 'a, P: 'a, B: 'a, T: 'a, S: 'a, AH
     ospeedr: &'a S,
     afrh: &'a AH,
     afrl: &'a AL,
+    port: PhantomData<ID>,
 }
 
-pub type RegisterBlockA<'a> = RegisterBlock<
-    'a,
-    gpioa::IDR,
-    gpioa::ODR,
-    gpioa::MODER,
-    gpioa::PUPDR,
-    gpioa::BSRR,
-    gpioa::OTYPER,
-    gpioa::OSPEEDR,
-    gpioa::AFRH,
-    gpioa::AFRL,
->;
-pub type RegisterBlockB<'a> = RegisterBlock<
-    'a,
-    gpiob::IDR,
-    gpiob::ODR,
-    gpiob::MODER,
-    gpiob::PUPDR,
-    gpiob::BSRR,
-    gpiob::OTYPER,
-    gpiob::OSPEEDR,
-    gpiob::AFRH,
-    gpiob::AFRL,
->;
-pub type RegisterBlockD<'a> = RegisterBlock<
-    'a,
-    gpiod::IDR,
-    gpiod::ODR,
-    gpiod::MODER,
-    gpiod::PUPDR,
-    gpiod::BSRR,
-    gpiod::OTYPER,
-    gpiod::OSPEEDR,
-    gpiod::AFRH,
-    gpiod::AFRL,
->;
+// implemented manually because the derives would require the register types to be `Clone`
+impl<'a, I, O, M, P, B, T, S, AH, AL, ID> Clone
+    for RegisterBlock<'a, I, O, M, P, B, T, S, AH, AL, ID>
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, I, O, M, P, B, T, S, AH, AL, ID> Copy
+    for RegisterBlock<'a, I, O, M, P, B, T, S, AH, AL, ID>
+{
+}
+
+macro_rules! register_block_type {
+    ($name:ident, $gpio:ident, $id:ident) => {
+        pub type $name<'a> = RegisterBlock<
+            'a,
+            $gpio::IDR,
+            $gpio::ODR,
+            $gpio::MODER,
+            $gpio::PUPDR,
+            $gpio::BSRR,
+            $gpio::OTYPER,
+            $gpio::OSPEEDR,
+            $gpio::AFRH,
+            $gpio::AFRL,
+            $id,
+        >;
+    };
+}
+
+// Ports C to K have the same register layout as port D, so the register types of port D are
+// used for them. Each port gets its own marker type to keep them apart at the type level.
+register_block_type!(RegisterBlockA, gpioa, PA);
+register_block_type!(RegisterBlockB, gpiob, PB);
+register_block_type!(RegisterBlockC, gpiod, PC);
+register_block_type!(RegisterBlockD, gpiod, PD);
+register_block_type!(RegisterBlockE, gpiod, PE);
+register_block_type!(RegisterBlockF, gpiod, PF);
+register_block_type!(RegisterBlockG, gpiod, PG);
+register_block_type!(RegisterBlockH, gpiod, PH);
+register_block_type!(RegisterBlockI, gpiod, PI);
+register_block_type!(RegisterBlockJ, gpiod, PJ);
+register_block_type!(RegisterBlockK, gpiod, PK);
 
 macro_rules! new_gpio_port {
     ($register_block:expr) => {
@@ -70,37 +85,53 @@ macro_rules! new_gpio_port {
                 ospeedr: &$register_block.ospeedr,
                 afrh: &$register_block.afrh,
                 afrl: &$register_block.afrl,
+                port: PhantomData,
             },
         }
     };
 }
 
-impl<'a> GpioPort<RegisterBlockA<'a>> {
-    pub fn new_a(register_block: &'a gpioa::RegisterBlock) -> Self {
-        new_gpio_port!(register_block)
-    }
-}
-
-impl<'a> GpioPort<RegisterBlockB<'a>> {
-    pub fn new_b(register_block: &'a gpiob::RegisterBlock) -> Self {
-        new_gpio_port!(register_block)
-    }
+macro_rules! impl_new {
+    ($($register_block:ident, $peripheral:ident, $new:ident;)*) => {
+        $(
+            impl<'a> GpioPort<$register_block<'a>> {
+                pub fn $new(register_block: &'a $peripheral) -> Self {
+                    new_gpio_port!(register_block)
+                }
+            }
+        )*
+    };
 }
 
-impl<'a> GpioPort<RegisterBlockD<'a>> {
-    pub fn new(register_block: &'a gpiod::RegisterBlock) -> Self {
-        new_gpio_port!(register_block)
-    }
+impl_new! {
+    RegisterBlockA, GPIOA, new_a;
+    RegisterBlockB, GPIOB, new_b;
+    RegisterBlockC, GPIOC, new_c;
+    RegisterBlockD, GPIOD, new_d;
+    RegisterBlockE, GPIOE, new_e;
+    RegisterBlockF, GPIOF, new_f;
+    RegisterBlockG, GPIOG, new_g;
+    RegisterBlockH, GPIOH, new_h;
+    RegisterBlockI, GPIOI, new_i;
+    RegisterBlockJ, GPIOJ, new_j;
+    RegisterBlockK, GPIOK, new_k;
 }
 
+/// The configuration methods modify the shared registers of the port in a critical section, so
+/// that different pins of a port can be reconfigured from the main loop and interrupt handlers.
 pub trait RegisterBlockTrait<'a> {
     type Idr: IdrTrait + 'a;
     type Odr: OdrTrait + 'a;
     type Bsrr: BsrrTrait + 'a;
+    /// The marker type of the port, see the `pin` module.
+    type Id;
 
     fn idr(&self) -> &'a Self::Idr;
     fn odr(&self) -> &'a Self::Odr;
     fn bsrr(&self) -> &'a Self::Bsrr;
+    fn port(&self) -> Port;
+    /// Restores the reset configuration of the given pins.
+    fn reset(&mut self, pins: &[PinNumber]);
     fn set_mode(&mut self, pins: &[PinNumber], mode: Mode);
     fn set_resistor(&mut self, pins: &[PinNumber], resistor: Resistor);
     fn set_out_type(&mut self, pins: &[PinNumber], out_type: OutputType);
@@ -120,6 +151,7 @@ impl<'a, T: RegisterBlockTrait<'a>> GpioPort<T> {
         self.register_block.set_resistor(&[pin], resistor);
 
         Ok(InputPinImpl {
+            port: self.register_block.port(),
             pin: pin,
             input_data: self.register_block.idr(),
         })
@@ -169,6 +201,12 @@ impl<'a, T: RegisterBlockTrait<'a>> GpioPort<T> {
         speed: OutputSpeed,
         resistor: Resistor,
     ) -> Result<(), Error> {
+        let port = self.register_block.port();
+        for &pin in pins {
+            if !alternate_function_supported(port, pin, alternate_fn) {
+                return Err(Error::AlternateFunctionNotSupported(pin, alternate_fn));
+            }
+        }
         self.use_pins(pins)?;
 
         self.register_block.set_mode(pins, Mode::Alternate);
@@ -180,7 +218,7 @@ impl<'a, T: RegisterBlockTrait<'a>> GpioPort<T> {
         Ok(())
     }
 
-    fn use_pin(&mut self, pin: PinNumber) -> Result<(), Error> {
+    pub(super) fn use_pin(&mut self, pin: PinNumber) -> Result<(), Error> {
         if self.pin_in_use[pin as usize] {
             Err(Error::PinAlreadyInUse(pin))
         } else {
@@ -189,7 +227,7 @@ impl<'a, T: RegisterBlockTrait<'a>> GpioPort<T> {
         }
     }
 
-    fn use_pins(&mut self, pins: &[PinNumber]) -> Result<(), Error> {
+    pub(super) fn use_pins(&mut self, pins: &[PinNumber]) -> Result<(), Error> {
         // create a copy of the pin_in_use array since we only want to modify it in case of success
         let mut pin_in_use = self.pin_in_use;
 
@@ -209,11 +247,20 @@ impl<'a, T: RegisterBlockTrait<'a>> GpioPort<T> {
 }
 
 macro_rules! impl_register_block_trait {
-    ($register_block:tt, $gpio:tt) => {
+    (
+        $register_block:tt,
+        $gpio:tt,
+        $id:ident,
+        $port:ident,
+        $moder_reset:expr,
+        $pupdr_reset:expr,
+        $ospeedr_reset:expr
+    ) => {
         impl<'a> RegisterBlockTrait<'a> for $register_block<'a> {
             type Idr = $gpio::IDR;
             type Odr = $gpio::ODR;
             type Bsrr = $gpio::BSRR;
+            type Id = $id;
 
             fn idr(&self) -> &'a Self::Idr {
                 self.idr
@@ -227,6 +274,46 @@ macro_rules! impl_register_block_trait {
                 self.bsrr
             }
 
+            fn port(&self) -> Port {
+                Port::$port
+            }
+
+            fn reset(&mut self, pins: &[PinNumber]) {
+                let mut mask_1 = 0u32; // one bit per pin
+                let mut mask_2 = 0u32; // two bits per pin
+                let mut mask_afrl = 0u32; // four bits per pin
+                let mut mask_afrh = 0u32; // four bits per pin
+                for &pin in pins {
+                    let pin = pin as u32;
+                    mask_1 |= 0b1 << pin;
+                    mask_2 |= 0b11 << (pin * 2);
+                    if pin < 8 {
+                        mask_afrl |= 0b1111 << (pin * 4);
+                    } else {
+                        mask_afrh |= 0b1111 << ((pin - 8) * 4);
+                    }
+                }
+
+                // the alternate function registers and the output type register reset to 0
+                interrupt::free(|_| {
+                    self.afrl
+                        .modify(|r, w| unsafe { w.bits(r.bits() & !mask_afrl) });
+                    self.afrh
+                        .modify(|r, w| unsafe { w.bits(r.bits() & !mask_afrh) });
+                    self.otyper
+                        .modify(|r, w| unsafe { w.bits(r.bits() & !mask_1) });
+                    self.ospeedr.modify(|r, w| unsafe {
+                        w.bits(r.bits() & !mask_2 | $ospeedr_reset & mask_2)
+                    });
+                    self.pupdr.modify(|r, w| unsafe {
+                        w.bits(r.bits() & !mask_2 | $pupdr_reset & mask_2)
+                    });
+                    self.moder.modify(|r, w| unsafe {
+                        w.bits(r.bits() & !mask_2 | $moder_reset & mask_2)
+                    });
+                });
+            }
+
             fn set_mode(&mut self, pins: &[PinNumber], mode: Mode) {
                 use self::PinNumber::*;
                 use stm32f7::stm32f7x6::$gpio::moder::MODER15W;
@@ -238,28 +325,30 @@ macro_rules! impl_register_block_trait {
                     Mode::Analog => MODER15W::ANALOG,
                 };
 
-                self.moder.modify(|_, w| {
-                    for pin in pins {
-                        match pin {
-                            Pin0 => w.moder0().variant(variant()),
-                            Pin1 => w.moder1().variant(variant()),
-                            Pin2 => w.moder2().variant(variant()),
-                            Pin3 => w.moder3().variant(variant()),
-                            Pin4 => w.moder4().variant(variant()),
-                            Pin5 => w.moder5().variant(variant()),
-                            Pin6 => w.moder6().variant(variant()),
-                            Pin7 => w.moder7().variant(variant()),
-                            Pin8 => w.moder8().variant(variant()),
-                            Pin9 => w.moder9().variant(variant()),
-                            Pin10 => w.moder10().variant(variant()),
-                            Pin11 => w.moder11().variant(variant()),
-                            Pin12 => w.moder12().variant(variant()),
-                            Pin13 => w.moder13().variant(variant()),
-                            Pin14 => w.moder14().variant(variant()),
-                            Pin15 => w.moder15().variant(variant()),
-                        };
-                    }
-                    w
+                interrupt::free(|_| {
+                    self.moder.modify(|_, w| {
+                        for pin in pins {
+                            match pin {
+                                Pin0 => w.moder0().variant(variant()),
+                                Pin1 => w.moder1().variant(variant()),
+                                Pin2 => w.moder2().variant(variant()),
+                                Pin3 => w.moder3().variant(variant()),
+                                Pin4 => w.moder4().variant(variant()),
+                                Pin5 => w.moder5().variant(variant()),
+                                Pin6 => w.moder6().variant(variant()),
+                                Pin7 => w.moder7().variant(variant()),
+                                Pin8 => w.moder8().variant(variant()),
+                                Pin9 => w.moder9().variant(variant()),
+                                Pin10 => w.moder10().variant(variant()),
+                                Pin11 => w.moder11().variant(variant()),
+                                Pin12 => w.moder12().variant(variant()),
+                                Pin13 => w.moder13().variant(variant()),
+                                Pin14 => w.moder14().variant(variant()),
+                                Pin15 => w.moder15().variant(variant()),
+                            };
+                        }
+                        w
+                    });
                 })
             }
 
@@ -273,29 +362,31 @@ macro_rules! impl_register_block_trait {
                     Resistor::PullDown => PUPDR15W::PULLDOWN,
                 };
 
-                self.pupdr.modify(|_, w| {
-                    for pin in pins {
-                        match pin {
-                            Pin0 => w.pupdr0().variant(variant()),
-                            Pin1 => w.pupdr1().variant(variant()),
-                            Pin2 => w.pupdr2().variant(variant()),
-                            Pin3 => w.pupdr3().variant(variant()),
-                            Pin4 => w.pupdr4().variant(variant()),
-                            Pin5 => w.pupdr5().variant(variant()),
-                            Pin6 => w.pupdr6().variant(variant()),
-                            Pin7 => w.pupdr7().variant(variant()),
-                            Pin8 => w.pupdr8().variant(variant()),
-                            Pin9 => w.pupdr9().variant(variant()),
-                            Pin10 => w.pupdr10().variant(variant()),
-                            Pin11 => w.pupdr11().variant(variant()),
-                            Pin12 => w.pupdr12().variant(variant()),
-                            Pin13 => w.pupdr13().variant(variant()),
-                            Pin14 => w.pupdr14().variant(variant()),
-                            Pin15 => w.pupdr15().variant(variant()),
-                        };
-                    }
-                    w
-                });
+                interrupt::free(|_| {
+                    self.pupdr.modify(|_, w| {
+                        for pin in pins {
+                            match pin {
+                                Pin0 => w.pupdr0().variant(variant()),
+                                Pin1 => w.pupdr1().variant(variant()),
+                                Pin2 => w.pupdr2().variant(variant()),
+                                Pin3 => w.pupdr3().variant(variant()),
+                                Pin4 => w.pupdr4().variant(variant()),
+                                Pin5 => w.pupdr5().variant(variant()),
+                                Pin6 => w.pupdr6().variant(variant()),
+                                Pin7 => w.pupdr7().variant(variant()),
+                                Pin8 => w.pupdr8().variant(variant()),
+                                Pin9 => w.pupdr9().variant(variant()),
+                                Pin10 => w.pupdr10().variant(variant()),
+                                Pin11 => w.pupdr11().variant(variant()),
+                                Pin12 => w.pupdr12().variant(variant()),
+                                Pin13 => w.pupdr13().variant(variant()),
+                                Pin14 => w.pupdr14().variant(variant()),
+                                Pin15 => w.pupdr15().variant(variant()),
+                            };
+                        }
+                        w
+                    });
+                })
             }
 
             fn set_out_type(&mut self, pins: &[PinNumber], out_type: OutputType) {
@@ -307,28 +398,30 @@ macro_rules! impl_register_block_trait {
                     OutputType::PushPull => OT15W::PUSHPULL,
                 };
 
-                self.otyper.modify(|_, w| {
-                    for pin in pins {
-                        match pin {
-                            Pin0 => w.ot0().variant(variant()),
-                            Pin1 => w.ot1().variant(variant()),
-                            Pin2 => w.ot2().variant(variant()),
-                            Pin3 => w.ot3().variant(variant()),
-                            Pin4 => w.ot4().variant(variant()),
-                            Pin5 => w.ot5().variant(variant()),
-                            Pin6 => w.ot6().variant(variant()),
-                            Pin7 => w.ot7().variant(variant()),
-                            Pin8 => w.ot8().variant(variant()),
-                            Pin9 => w.ot9().variant(variant()),
-                            Pin10 => w.ot10().variant(variant()),
-                            Pin11 => w.ot11().variant(variant()),
-                            Pin12 => w.ot12().variant(variant()),
-                            Pin13 => w.ot13().variant(variant()),
-                            Pin14 => w.ot14().variant(variant()),
-                            Pin15 => w.ot15().variant(variant()),
-                        };
-                    }
-                    w
+                interrupt::free(|_| {
+                    self.otyper.modify(|_, w| {
+                        for pin in pins {
+                            match pin {
+                                Pin0 => w.ot0().variant(variant()),
+                                Pin1 => w.ot1().variant(variant()),
+                                Pin2 => w.ot2().variant(variant()),
+                                Pin3 => w.ot3().variant(variant()),
+                                Pin4 => w.ot4().variant(variant()),
+                                Pin5 => w.ot5().variant(variant()),
+                                Pin6 => w.ot6().variant(variant()),
+                                Pin7 => w.ot7().variant(variant()),
+                                Pin8 => w.ot8().variant(variant()),
+                                Pin9 => w.ot9().variant(variant()),
+                                Pin10 => w.ot10().variant(variant()),
+                                Pin11 => w.ot11().variant(variant()),
+                                Pin12 => w.ot12().variant(variant()),
+                                Pin13 => w.ot13().variant(variant()),
+                                Pin14 => w.ot14().variant(variant()),
+                                Pin15 => w.ot15().variant(variant()),
+                            };
+                        }
+                        w
+                    });
                 })
             }
 
@@ -343,28 +436,30 @@ macro_rules! impl_register_block_trait {
                     OutputSpeed::VeryHigh => OSPEEDR15W::VERYHIGHSPEED,
                 };
 
-                self.ospeedr.modify(|_, w| {
-                    for pin in pins {
-                        match pin {
-                            Pin0 => w.ospeedr0().variant(variant()),
-                            Pin1 => w.ospeedr1().variant(variant()),
-                            Pin2 => w.ospeedr2().variant(variant()),
-                            Pin3 => w.ospeedr3().variant(variant()),
-                            Pin4 => w.ospeedr4().variant(variant()),
-                            Pin5 => w.ospeedr5().variant(variant()),
-                            Pin6 => w.ospeedr6().variant(variant()),
-                            Pin7 => w.ospeedr7().variant(variant()),
-                            Pin8 => w.ospeedr8().variant(variant()),
-                            Pin9 => w.ospeedr9().variant(variant()),
-                            Pin10 => w.ospeedr10().variant(variant()),
-                            Pin11 => w.ospeedr11().variant(variant()),
-                            Pin12 => w.ospeedr12().variant(variant()),
-                            Pin13 => w.ospeedr13().variant(variant()),
-                            Pin14 => w.ospeedr14().variant(variant()),
-                            Pin15 => w.ospeedr15().variant(variant()),
-                        };
-                    }
-                    w
+                interrupt::free(|_| {
+                    self.ospeedr.modify(|_, w| {
+                        for pin in pins {
+                            match pin {
+                                Pin0 => w.ospeedr0().variant(variant()),
+                                Pin1 => w.ospeedr1().variant(variant()),
+                                Pin2 => w.ospeedr2().variant(variant()),
+                                Pin3 => w.ospeedr3().variant(variant()),
+                                Pin4 => w.ospeedr4().variant(variant()),
+                                Pin5 => w.ospeedr5().variant(variant()),
+                                Pin6 => w.ospeedr6().variant(variant()),
+                                Pin7 => w.ospeedr7().variant(variant()),
+                                Pin8 => w.ospeedr8().variant(variant()),
+                                Pin9 => w.ospeedr9().variant(variant()),
+                                Pin10 => w.ospeedr10().variant(variant()),
+                                Pin11 => w.ospeedr11().variant(variant()),
+                                Pin12 => w.ospeedr12().variant(variant()),
+                                Pin13 => w.ospeedr13().variant(variant()),
+                                Pin14 => w.ospeedr14().variant(variant()),
+                                Pin15 => w.ospeedr15().variant(variant()),
+                            };
+                        }
+                        w
+                    });
                 })
             }
 
@@ -392,69 +487,97 @@ macro_rules! impl_register_block_trait {
                     AlternateFunction::AF15 => (AFRL7W::AF15, AFRH15W::AF15),
                 };
 
-                self.afrh.modify(|_, wh| {
-                    self.afrl.modify(|_, wl| {
-                        for pin in pins {
-                            match pin {
-                                Pin0 => {
-                                    wl.afrl0().variant(variant().0);
-                                }
-                                Pin1 => {
-                                    wl.afrl1().variant(variant().0);
-                                }
-                                Pin2 => {
-                                    wl.afrl2().variant(variant().0);
-                                }
-                                Pin3 => {
-                                    wl.afrl3().variant(variant().0);
-                                }
-                                Pin4 => {
-                                    wl.afrl4().variant(variant().0);
-                                }
-                                Pin5 => {
-                                    wl.afrl5().variant(variant().0);
-                                }
-                                Pin6 => {
-                                    wl.afrl6().variant(variant().0);
-                                }
-                                Pin7 => {
-                                    wl.afrl7().variant(variant().0);
-                                }
-                                Pin8 => {
-                                    wh.afrh8().variant(variant().1);
-                                }
-                                Pin9 => {
-                                    wh.afrh9().variant(variant().1);
-                                }
-                                Pin10 => {
-                                    wh.afrh10().variant(variant().1);
-                                }
-                                Pin11 => {
-                                    wh.afrh11().variant(variant().1);
-                                }
-                                Pin12 => {
-                                    wh.afrh12().variant(variant().1);
-                                }
-                                Pin13 => {
-                                    wh.afrh13().variant(variant().1);
-                                }
-                                Pin14 => {
-                                    wh.afrh14().variant(variant().1);
-                                }
-                                Pin15 => {
-                                    wh.afrh15().variant(variant().1);
-                                }
-                            };
-                        }
-                        wl
+                interrupt::free(|_| {
+                    self.afrh.modify(|_, wh| {
+                        self.afrl.modify(|_, wl| {
+                            for pin in pins {
+                                match pin {
+                                    Pin0 => {
+                                        wl.afrl0().variant(variant().0);
+                                    }
+                                    Pin1 => {
+                                        wl.afrl1().variant(variant().0);
+                                    }
+                                    Pin2 => {
+                                        wl.afrl2().variant(variant().0);
+                                    }
+                                    Pin3 => {
+                                        wl.afrl3().variant(variant().0);
+                                    }
+                                    Pin4 => {
+                                        wl.afrl4().variant(variant().0);
+                                    }
+                                    Pin5 => {
+                                        wl.afrl5().variant(variant().0);
+                                    }
+                                    Pin6 => {
+                                        wl.afrl6().variant(variant().0);
+                                    }
+                                    Pin7 => {
+                                        wl.afrl7().variant(variant().0);
+                                    }
+                                    Pin8 => {
+                                        wh.afrh8().variant(variant().1);
+                                    }
+                                    Pin9 => {
+                                        wh.afrh9().variant(variant().1);
+                                    }
+                                    Pin10 => {
+                                        wh.afrh10().variant(variant().1);
+                                    }
+                                    Pin11 => {
+                                        wh.afrh11().variant(variant().1);
+                                    }
+                                    Pin12 => {
+                                        wh.afrh12().variant(variant().1);
+                                    }
+                                    Pin13 => {
+                                        wh.afrh13().variant(variant().1);
+                                    }
+                                    Pin14 => {
+                                        wh.afrh14().variant(variant().1);
+                                    }
+                                    Pin15 => {
+                                        wh.afrh15().variant(variant().1);
+                                    }
+                                };
+                            }
+                            wl
+                        });
+                        wh
                     });
-                    wh
                 })
             }
         }
     };
 }
 
-impl_register_block_trait!(RegisterBlockA, gpioa);
-impl_register_block_trait!(RegisterBlockB, gpiob);
-impl_register_block_trait!(RegisterBlockD, gpiod);
+// the reset values of the mode, pull-up/pull-down and speed registers, which differ for the debug
+// pins on ports A and B
+impl_register_block_trait!(
+    RegisterBlockA,
+    gpioa,
+    PA,
+    PortA,
+    0xa800_0000,
+    0x6400_0000,
+    0x0c00_0000
+);
+impl_register_block_trait!(
+    RegisterBlockB,
+    gpiob,
+    PB,
+    PortB,
+    0x0000_0280,
+    0x0000_0100,
+    0x0000_00c0
+);
+impl_register_block_trait!(RegisterBlockC, gpiod, PC, PortC, 0, 0, 0);
+impl_register_block_trait!(RegisterBlockD, gpiod, PD, PortD, 0, 0, 0);
+impl_register_block_trait!(RegisterBlockE, gpiod, PE, PortE, 0, 0, 0);
+impl_register_block_trait!(RegisterBlockF, gpiod, PF, PortF, 0, 0, 0);
+impl_register_block_trait!(RegisterBlockG, gpiod, PG, PortG, 0, 0, 0);
+impl_register_block_trait!(RegisterBlockH, gpiod, PH, PortH, 0, 0, 0);
+impl_register_block_trait!(RegisterBlockI, gpiod, PI, PortI, 0, 0, 0);
+impl_register_block_trait!(RegisterBlockJ, gpiod, PJ, PortJ, 0, 0, 0);
+impl_register_block_trait!(RegisterBlockK, gpiod, PK, PortK, 0, 0, 0);
@@ -9,6 +9,9 @@ pub trait IdrTrait {
 
 pub trait IdrR {
     fn get(&self, pin: PinNumber) -> bool;
+
+    /// Returns the input data of all pins.
+    fn bits(&self) -> u16;
 }
 
 pub trait OdrTrait {
@@ -33,6 +36,9 @@ pub trait BsrrW {
     fn set(&mut self, pin: PinNumber) -> &mut Self;
 
     fn reset(&mut self, pin: PinNumber) -> &mut Self;
+
+    /// Sets the pins of the lower 16 bits and resets the pins of the upper 16 bits.
+    fn bits(&mut self, bits: u32) -> &mut Self;
 }
 
 macro_rules! impl_traits_for {
@@ -67,6 +73,10 @@ macro_rules! impl_traits_for {
                     Pin15 => self.idr15().bit_is_set(),
                 }
             }
+
+            fn bits(&self) -> u16 {
+                $gpio::idr::R::bits(self) as u16
+            }
         }
 
         impl OdrTrait for $gpio::ODR {
@@ -156,6 +166,10 @@ macro_rules! impl_traits_for {
                     Pin15 => self.br15().reset(),
                 }
             }
+
+            fn bits(&mut self, bits: u32) -> &mut Self {
+                unsafe { $gpio::bsrr::W::bits(self, bits) }
+            }
         }
     };
 }
@@ -0,0 +1,165 @@
+//! A scripted I2C bus for testing device drivers without hardware.
+//!
+//! The mock is created with the list of transactions that the driver is expected to perform.
+//! Every bus access is checked against the next expected transaction and recorded, so that
+//! register sequences can be verified on the host.
+//!
+//! Example
+//!
+//! ```ignore
+//! let address = i2c::Address::bits_7(0b0111000);
+//! let mut bus = MockI2c::new(vec![
+//!     Transaction::write(address, &[0xA8]),
+//!     Transaction::read(address, &[0x51]),
+//!     Transaction::stop(),
+//! ]);
+//! touch::check_family_id(&mut bus).unwrap();
+//! bus.done();
+//! ```
+
+use super::{Address, Error, I2cBus};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Write,
+    Read,
+    Stop,
+}
+
+/// A single bus access.
+///
+/// For writes, `data` contains the bytes sent to the device. For reads, it contains the bytes
+/// that the device responds with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub kind: Kind,
+    pub address: Option<Address>,
+    pub data: Vec<u8>,
+    pub result: Result<(), Error>,
+}
+
+impl Transaction {
+    pub fn write(address: Address, bytes: &[u8]) -> Transaction {
+        Transaction {
+            kind: Kind::Write,
+            address: Some(address),
+            data: Vec::from(bytes),
+            result: Ok(()),
+        }
+    }
+
+    pub fn read(address: Address, response: &[u8]) -> Transaction {
+        Transaction {
+            kind: Kind::Read,
+            address: Some(address),
+            data: Vec::from(response),
+            result: Ok(()),
+        }
+    }
+
+    pub fn stop() -> Transaction {
+        Transaction {
+            kind: Kind::Stop,
+            address: None,
+            data: Vec::new(),
+            result: Ok(()),
+        }
+    }
+
+    /// Makes the bus access fail with `error` (e.g. to simulate a NACK).
+    pub fn with_error(mut self, error: Error) -> Transaction {
+        self.result = Err(error);
+        self
+    }
+}
+
+pub struct MockI2c {
+    expected: VecDeque<Transaction>,
+    recorded: Vec<Transaction>,
+}
+
+impl MockI2c {
+    pub fn new(expected: Vec<Transaction>) -> MockI2c {
+        MockI2c {
+            expected: expected.into_iter().collect(),
+            recorded: Vec::new(),
+        }
+    }
+
+    /// Appends further expected transactions to the script.
+    pub fn expect(&mut self, transactions: &[Transaction]) {
+        self.expected.extend(transactions.iter().cloned());
+    }
+
+    /// Returns all transactions performed so far.
+    pub fn recorded(&self) -> &[Transaction] {
+        &self.recorded
+    }
+
+    /// Panics if there are expected transactions that were not performed.
+    pub fn done(&self) {
+        assert!(
+            self.expected.is_empty(),
+            "{} expected I2C transaction(s) not performed, next: {:?}",
+            self.expected.len(),
+            self.expected.front()
+        );
+    }
+
+    fn next(&mut self, kind: Kind, address: Option<Address>) -> Transaction {
+        let expected = match self.expected.pop_front() {
+            Some(transaction) => transaction,
+            None => panic!(
+                "unexpected I2C {:?} at {:?} after {} transaction(s)",
+                kind,
+                address,
+                self.recorded.len()
+            ),
+        };
+        assert_eq!(
+            (expected.kind, expected.address),
+            (kind, address),
+            "I2C transaction {} does not match",
+            self.recorded.len()
+        );
+        expected
+    }
+}
+
+impl I2cBus for MockI2c {
+    fn write_bytes(&mut self, device_address: Address, bytes: &[u8]) -> Result<(), Error> {
+        let expected = self.next(Kind::Write, Some(device_address));
+        assert_eq!(
+            &expected.data[..],
+            bytes,
+            "I2C write {} sent unexpected data",
+            self.recorded.len()
+        );
+        let result = expected.result;
+        self.recorded.push(expected);
+        result
+    }
+
+    fn read_bytes(&mut self, device_address: Address, buffer: &mut [u8]) -> Result<(), Error> {
+        let expected = self.next(Kind::Read, Some(device_address));
+        assert_eq!(
+            expected.data.len(),
+            buffer.len(),
+            "I2C read {} has unexpected length",
+            self.recorded.len()
+        );
+        buffer.copy_from_slice(&expected.data);
+        let result = expected.result;
+        self.recorded.push(expected);
+        result
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        let expected = self.next(Kind::Stop, None);
+        let result = expected.result;
+        self.recorded.push(expected);
+        result
+    }
+}
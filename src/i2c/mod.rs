@@ -0,0 +1,576 @@
+use clocks::Clocks;
+use core::marker::PhantomData;
+use embedded_hal::blocking::i2c::{self as i2c_hal, Operation};
+use stm32f7::stm32f7x6::{
+    i2c1::{self, RegisterBlock},
+    RCC,
+};
+
+// TODO use &mut when svd2rust API has changed (modification should require &mut)
+//pub struct I2C<'a>(&'a mut RegisterBlock);
+pub struct I2C<'a>(&'a RegisterBlock);
+
+pub mod mock;
+pub mod nonblocking;
+pub mod scan;
+pub mod target;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Nack,
+    BusError,
+    ArbitrationLoss,
+    Overrun,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address(u16);
+
+impl Address {
+    pub const fn bits_7(addr: u8) -> Address {
+        Address((addr as u16) << 1)
+    }
+}
+
+fn icr_clear_all(w: &mut i2c1::icr::W) -> &mut i2c1::icr::W {
+    w.alertcf().set_bit(); // alert clear flag
+    w.timoutcf().set_bit(); // timeout detection clear flag
+    w.peccf().set_bit(); // PEC error clear flag
+    w.ovrcf().set_bit(); // overrun/underrun clear flag
+    w.arlocf().set_bit(); // arbitration loss clear flag
+    w.berrcf().set_bit(); // bus error clear flag
+    w.stopcf().set_bit(); // stop detection clear flag
+    w.nackcf().set_bit(); // not acknowledge clear flag
+    w.addrcf().set_bit(); // address matched clear flag
+    w
+}
+
+/// The maximum number of bytes that fit into the `NBYTES` field of `CR2`.
+const MAX_NBYTES: usize = 255;
+
+fn chunk_len(remaining: usize) -> u8 {
+    if remaining > MAX_NBYTES {
+        MAX_NBYTES as u8
+    } else {
+        remaining as u8
+    }
+}
+
+/// A bus that transfers raw bytes to and from devices.
+///
+/// This is implemented by the `I2C` peripheral and by the `mock::MockI2c` test bus, so that
+/// device drivers written against `I2cBus` can run on both.
+pub trait I2cBus {
+    /// Writes `bytes` to the device, starting with a (repeated) start condition.
+    ///
+    /// Does not generate a stop condition.
+    fn write_bytes(&mut self, device_address: Address, bytes: &[u8]) -> Result<(), Error>;
+
+    /// Fills `buffer` with bytes read from the device, starting with a (repeated) start
+    /// condition.
+    ///
+    /// Does not generate a stop condition.
+    fn read_bytes(&mut self, device_address: Address, buffer: &mut [u8]) -> Result<(), Error>;
+
+    /// Generates a stop condition.
+    fn stop(&mut self) -> Result<(), Error>;
+
+    fn connect<T, F>(&mut self, device_address: Address, f: F) -> Result<(), Error>
+    where
+        Self: Sized,
+        T: RegisterType,
+        F: FnOnce(I2cConnection<Self, T>) -> Result<(), Error>,
+    {
+        {
+            let conn = I2cConnection {
+                bus: self,
+                device_address: device_address,
+                register_type: PhantomData,
+            };
+            f(conn)?;
+        }
+        self.stop()
+    }
+
+    fn update<F>(
+        &mut self,
+        device_address: Address,
+        register_address: u16,
+        f: F,
+    ) -> Result<(), Error>
+    where
+        Self: Sized,
+        F: FnOnce(&mut u16),
+    {
+        self.connect(device_address, |mut conn| {
+            let mut value = conn.read(register_address)?;
+            f(&mut value);
+            conn.write(register_address, value)
+        })
+    }
+}
+
+pub struct I2cConnection<'a, B: I2cBus + 'a, T: RegisterType> {
+    bus: &'a mut B,
+    device_address: Address,
+    register_type: PhantomData<T>,
+}
+
+pub trait RegisterType: Sized {
+    fn write<F>(&self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&[u8]) -> Result<(), Error>;
+    fn read<F>(f: F) -> Result<Self, Error>
+    where
+        F: FnOnce(&mut [u8]) -> Result<(), Error>;
+}
+
+impl RegisterType for u8 {
+    fn write<F>(&self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&[u8]) -> Result<(), Error>,
+    {
+        f(&[*self])
+    }
+
+    fn read<F>(f: F) -> Result<Self, Error>
+    where
+        F: FnOnce(&mut [u8]) -> Result<(), Error>,
+    {
+        let mut buf = [0];
+        f(&mut buf)?;
+        Ok(buf[0])
+    }
+}
+
+impl RegisterType for u16 {
+    fn write<F>(&self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&[u8]) -> Result<(), Error>,
+    {
+        f(&[(*self >> 8) as u8, *self as u8])
+    }
+
+    fn read<F>(f: F) -> Result<Self, Error>
+    where
+        F: FnOnce(&mut [u8]) -> Result<(), Error>,
+    {
+        let mut buf = [0, 0];
+        f(&mut buf)?;
+        Ok((buf[0] as u16) << 8 | buf[1] as u16)
+    }
+}
+
+impl<'a, B: I2cBus, T: RegisterType> I2cConnection<'a, B, T> {
+    pub fn read(&mut self, register_address: T) -> Result<T, Error> {
+        let device_address = self.device_address;
+        let bus = &mut self.bus;
+
+        register_address.write(|addr_bytes| bus.write_bytes(device_address, addr_bytes))?;
+
+        T::read(|val_bytes| bus.read_bytes(device_address, val_bytes))
+    }
+
+    pub fn read_bytes(&mut self, register_address: T, bytes: &mut [u8]) -> Result<(), Error> {
+        let device_address = self.device_address;
+        let bus = &mut self.bus;
+
+        register_address.write(|addr_bytes| bus.write_bytes(device_address, addr_bytes))?;
+
+        bus.read_bytes(device_address, bytes)
+    }
+
+    pub fn write(&mut self, register_address: T, value: T) -> Result<(), Error> {
+        let device_address = self.device_address;
+        let bus = &mut self.bus;
+
+        register_address.write(|addr_bytes| {
+            value.write(|val_bytes| {
+                // register address and value must be sent in a single transfer
+                let mut buffer = [0; 8];
+                let len = addr_bytes.len() + val_bytes.len();
+                assert!(len <= buffer.len(), "register type is too large");
+                buffer[..addr_bytes.len()].copy_from_slice(addr_bytes);
+                buffer[addr_bytes.len()..len].copy_from_slice(val_bytes);
+                bus.write_bytes(device_address, &buffer[..len])
+            })
+        })
+    }
+}
+
+impl<'a> I2cBus for I2C<'a> {
+    fn write_bytes(&mut self, device_address: Address, bytes: &[u8]) -> Result<(), Error> {
+        self.pre();
+        self.write_bytes_raw(device_address, bytes.len(), bytes.iter().cloned())
+    }
+
+    fn read_bytes(&mut self, device_address: Address, buffer: &mut [u8]) -> Result<(), Error> {
+        self.pre();
+        self.read_bytes_raw(device_address, buffer.len(), buffer.iter_mut())
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        self.0.cr2.modify(|_, w| w.stop().set_bit());
+
+        // reset cr2
+        self.0.cr2.write(|w| w);
+
+        self.wait_for_stop()
+    }
+}
+
+impl<'a> I2C<'a> {
+    fn pre(&mut self) {
+        self.clear_status_flags();
+        // flush transmit data register
+        self.0.isr.modify(|_, w| w.txe().set_bit()); // flush_txdr
+    }
+
+    fn clear_status_flags(&mut self) {
+        self.0.icr.write(|w| icr_clear_all(w));
+    }
+
+    /// Runs `f` as a single transaction that is terminated by a stop condition.
+    ///
+    /// If `f` fails, the stop condition was already generated by the hardware after the NACK,
+    /// so we only wait until the bus is free again and clear the status flags.
+    fn transaction<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        self.pre();
+        match f(self) {
+            Ok(()) => self.stop(),
+            Err(err) => {
+                while self.0.isr.read().busy().bit_is_set() {}
+                self.clear_status_flags();
+                // reset cr2
+                self.0.cr2.write(|w| w);
+                Err(err)
+            }
+        }
+    }
+
+    fn start(&mut self, device_address: Address, read: bool, bytes: usize) {
+        self.0.cr2.write(|w| unsafe {
+            w.sadd().bits(device_address.0); // slave_address
+            w.start().set_bit(); // start_generation
+            w.rd_wrn().bit(read); // read_transfer
+            w.nbytes().bits(chunk_len(bytes)); // number_of_bytes
+            w.reload().bit(bytes > MAX_NBYTES); // nbytes_reload_mode
+            w.autoend().clear_bit(); // automatic_end_mode
+            w
+        })
+    }
+
+    /// Continues a transfer of more than 255 bytes with the next chunk of `remaining` bytes.
+    fn reload(&mut self, remaining: usize) -> Result<(), Error> {
+        self.wait_for_transfer_complete_reload()?;
+        self.0.cr2.modify(|_, w| unsafe {
+            w.nbytes().bits(chunk_len(remaining)); // number_of_bytes
+            w.reload().bit(remaining > MAX_NBYTES); // nbytes_reload_mode
+            w
+        });
+        Ok(())
+    }
+
+    /// Writes `len` bytes to the device. Starts with a (repeated) start condition, but does
+    /// not generate a stop condition.
+    fn write_bytes_raw<ITER>(
+        &mut self,
+        device_address: Address,
+        len: usize,
+        bytes: ITER,
+    ) -> Result<(), Error>
+    where
+        ITER: Iterator<Item = u8>,
+    {
+        self.start(device_address, false, len);
+
+        for (i, b) in bytes.enumerate() {
+            if i > 0 && i % MAX_NBYTES == 0 {
+                self.reload(len - i)?;
+            }
+            self.wait_for_txis()?;
+            self.0.txdr.modify(|_, w| unsafe { w.txdata().bits(b) }); // transmit_data
+        }
+
+        self.wait_for_transfer_complete()?;
+
+        self.clear_status_flags();
+
+        // reset cr2
+        self.0.cr2.write(|w| w);
+
+        Ok(())
+    }
+
+    /// Reads `len` bytes from the device. Starts with a (repeated) start condition, but does
+    /// not generate a stop condition.
+    fn read_bytes_raw<'b, ITER>(
+        &mut self,
+        device_address: Address,
+        len: usize,
+        buffer: ITER,
+    ) -> Result<(), Error>
+    where
+        ITER: Iterator<Item = &'b mut u8>,
+    {
+        self.start(device_address, true, len);
+
+        // read data from receive data register
+        for (i, b) in buffer.enumerate() {
+            if i > 0 && i % MAX_NBYTES == 0 {
+                self.reload(len - i)?;
+            }
+            self.wait_for_rxne()?;
+            *b = self.0.rxdr.read().rxdata().bits(); // receive_data
+        }
+
+        self.wait_for_transfer_complete()?;
+
+        self.clear_status_flags();
+
+        // reset cr2
+        self.0.cr2.write(|w| w);
+
+        Ok(())
+    }
+
+    /// Wait for “transmit interrupt status” flag
+    fn wait_for_txis(&self) -> Result<(), Error> {
+        loop {
+            let isr = self.0.isr.read();
+            if isr.nackf().bit_is_set() {
+                // nack_received
+                return Err(Error::Nack);
+            }
+            if isr.txis().bit_is_set() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Wait for "receive data register not empty" flag
+    fn wait_for_rxne(&self) -> Result<(), Error> {
+        loop {
+            let isr = self.0.isr.read();
+            if isr.nackf().bit_is_set() {
+                // nack_received
+                return Err(Error::Nack);
+            }
+            if isr.rxne().bit_is_set() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Wait for “transfer complete” flag
+    fn wait_for_transfer_complete(&self) -> Result<(), Error> {
+        loop {
+            let isr = self.0.isr.read();
+            if isr.nackf().bit_is_set() {
+                // nack_received
+                return Err(Error::Nack);
+            }
+            if isr.tc().bit_is_set() {
+                // transfer_complete
+                return Ok(());
+            }
+        }
+    }
+
+    /// Wait for “transfer complete reload” flag
+    fn wait_for_transfer_complete_reload(&self) -> Result<(), Error> {
+        loop {
+            let isr = self.0.isr.read();
+            if isr.nackf().bit_is_set() {
+                // nack_received
+                return Err(Error::Nack);
+            }
+            if isr.tcr().bit_is_set() {
+                // transfer_complete_reload
+                return Ok(());
+            }
+        }
+    }
+
+    /// Wait for automatically generated stop flag
+    fn wait_for_stop(&self) -> Result<(), Error> {
+        loop {
+            let isr = self.0.isr.read();
+            if isr.nackf().bit_is_set() {
+                // nack_received
+                return Err(Error::Nack);
+            }
+            if isr.stopf().bit_is_set() {
+                // stop_detected
+                return Ok(());
+            }
+        }
+    }
+
+    // provokes a NACK
+    pub fn test_1(&mut self) {
+        let i2c = &mut self.0;
+
+        i2c.cr2.modify(|_, w| unsafe {
+            w.sadd().bits(Address::bits_7(0b1010101).0); // slave_address
+            w.start().set_bit(); // start_generation
+            w.nbytes().bits(0); // number_of_bytes
+            w.autoend().set_bit(); // automatic_end_mode
+            w
+        });
+
+        loop {
+            let isr = i2c.isr.read();
+            if isr.nackf().bit_is_set() {
+                // nack_received
+                break;
+            }
+            assert!(isr.stopf().bit_is_clear()); // stop_detected
+        }
+
+        // clear status flags
+        i2c.icr.write(|w| icr_clear_all(w));
+    }
+}
+
+impl<'a> i2c_hal::Write for I2C<'a> {
+    type Error = Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        let address = Address::bits_7(address);
+        self.transaction(|i2c| i2c.write_bytes_raw(address, bytes.len(), bytes.iter().cloned()))
+    }
+}
+
+impl<'a> i2c_hal::Read for I2C<'a> {
+    type Error = Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        let address = Address::bits_7(address);
+        self.transaction(|i2c| i2c.read_bytes_raw(address, buffer.len(), buffer.iter_mut()))
+    }
+}
+
+impl<'a> i2c_hal::WriteRead for I2C<'a> {
+    type Error = Error;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        let address = Address::bits_7(address);
+        self.transaction(|i2c| {
+            i2c.write_bytes_raw(address, bytes.len(), bytes.iter().cloned())?;
+            i2c.read_bytes_raw(address, buffer.len(), buffer.iter_mut())
+        })
+    }
+}
+
+impl<'a> i2c_hal::Transactional for I2C<'a> {
+    type Error = Error;
+
+    fn exec<'b>(&mut self, address: u8, operations: &mut [Operation<'b>]) -> Result<(), Error> {
+        fn is_read(operation: &Operation) -> bool {
+            match operation {
+                Operation::Read(_) => true,
+                Operation::Write(_) => false,
+            }
+        }
+
+        fn len(operation: &Operation) -> usize {
+            match operation {
+                Operation::Read(buffer) => buffer.len(),
+                Operation::Write(bytes) => bytes.len(),
+            }
+        }
+
+        let address = Address::bits_7(address);
+        self.transaction(|i2c| {
+            let mut start = 0;
+            while start < operations.len() {
+                // adjacent operations of the same type are sent without a repeated start
+                let read = is_read(&operations[start]);
+                let group_size = operations[start..]
+                    .iter()
+                    .take_while(|op| is_read(op) == read)
+                    .count();
+                let end = start + group_size;
+                let group = &mut operations[start..end];
+                let group_len: usize = group.iter().map(len).sum();
+
+                if read {
+                    let buffer = group.iter_mut().flat_map(|op| match op {
+                        Operation::Read(buffer) => buffer.iter_mut(),
+                        Operation::Write(_) => unreachable!(),
+                    });
+                    i2c.read_bytes_raw(address, group_len, buffer)?;
+                } else {
+                    let bytes = group.iter().flat_map(|op| match op {
+                        Operation::Write(bytes) => bytes.iter().cloned(),
+                        Operation::Read(_) => unreachable!(),
+                    });
+                    i2c.write_bytes_raw(address, group_len, bytes)?;
+                }
+
+                start = end;
+            }
+            Ok(())
+        })
+    }
+}
+
+pub fn init<'a>(i2c: &'a RegisterBlock, rcc: &mut RCC, clocks: &Clocks) -> I2C<'a> {
+    // enable clocks
+    rcc.apb1enr.modify(|_, w| w.i2c3en().enabled());
+
+    // disable I2C peripheral
+    i2c.cr1.modify(|_, w| w.pe().clear_bit()); // peripheral_enable register
+
+    // The I2C kernel clock is PCLK1. The prescaler divides it to a period of about 100 ns
+    // (92.6 ns at 54 MHz), the periods below are multiples of it for ~100 kHz standard mode.
+    // The 4 bit prescaler divides by 1 to 16.
+    let presc = ((clocks.pclk1().0 + 5_000_000) / 10_000_000).max(1).min(16) - 1;
+
+    // configure timing register TODO: check/understand values
+    i2c.timingr.modify(|_, w| unsafe {
+        w.presc().bits(presc as u8); // timing_prescaler
+        w.scldel().bits(0x9); // data_setup_time
+        w.sdadel().bits(0x1); // data_hold_time
+        w.sclh().bits(0x27); // scl_high_period
+        w.scll().bits(0x32); // scl_low_period
+        w
+    });
+
+    // configure oar1
+    i2c.oar1.modify(|_, w| w.oa1en().clear_bit()); // own_address_1_enable register
+    i2c.oar1.modify(|_, w| {
+        unsafe { w.oa1().bits(0x00) }; // own_address_1
+        w.oa1mode().clear_bit(); // 10 bit mode
+        w.oa1en().clear_bit(); // TODO
+        w
+    });
+
+    // configure cr2
+    i2c.cr2.modify(|_, w| {
+        w.add10().clear_bit(); // 10_bit_addressing mode
+        w.autoend().clear_bit(); // automatic_end_mode
+        w
+    });
+
+    // configure oar2
+    i2c.oar2.modify(|_, w| {
+        w.oa2en().clear_bit() // own_address_2_enable
+    });
+
+    // configure cr1
+    i2c.cr1.modify(|_, w| {
+        w.gcen().clear_bit(); // general_call
+        w.nostretch().clear_bit(); // clock_stretching_disable
+        w.pe().set_bit(); // peripheral_enable
+        w
+    });
+    // wait that init can finish
+    ::system_clock::wait_ms(50);
+
+    I2C(i2c)
+}
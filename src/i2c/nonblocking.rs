@@ -0,0 +1,360 @@
+//! Interrupt-driven, non-blocking I2C transactions.
+//!
+//! Transactions are queued with `I2cQueue::submit` and executed one after another from the I2C
+//! event and error interrupts, which must be forwarded to `I2cQueue::handle_event` and
+//! `I2cQueue::handle_error`. Completion is signalled through an optional callback (called in
+//! interrupt context) or can be polled with `I2cQueue::take_result`.
+//!
+//! Since the queue is accessed from both the main loop and the interrupt handlers, it should be
+//! stored in a `spin::Mutex` that is only locked inside `cortex_m::interrupt::free`. Callbacks
+//! run while the interrupt handler holds that lock, so they must not lock the queue, e.g. to
+//! submit a follow-up transaction.
+//!
+//! Example
+//!
+//! ```ignore
+//! let mut queue = i2c_3.into_queue();
+//! let id = queue.submit(Transaction::new(FT5336_ADDRESS).read_register(0xA8u8));
+//!
+//! // later, in the main loop
+//! if let Some(result) = queue.take_result(id) {
+//!     let family_id: u8 = nonblocking::decode_register(&result.unwrap());
+//! }
+//! ```
+
+use super::{chunk_len, icr_clear_all, Address, Error, RegisterType, I2C, MAX_NBYTES};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::mem;
+use stm32f7::stm32f7x6::i2c1::RegisterBlock;
+
+/// The number of results of transactions without callback that are kept for `take_result`.
+const MAX_COMPLETED: usize = 16;
+
+/// Identifies a submitted transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionId(usize);
+
+/// Called in interrupt context when a transaction is completed. On success, the result contains
+/// the data of all read operations of the transaction.
+pub type Callback = fn(TransactionId, &Result<Vec<u8>, Error>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operation {
+    Write(Vec<u8>),
+    Read(usize),
+}
+
+impl Operation {
+    fn len(&self) -> usize {
+        match self {
+            Operation::Write(bytes) => bytes.len(),
+            Operation::Read(len) => *len,
+        }
+    }
+}
+
+/// A sequence of write and read operations to a single device.
+///
+/// Every operation starts with a (repeated) start condition. The transaction is terminated by
+/// a stop condition.
+pub struct Transaction {
+    address: Address,
+    operations: Vec<Operation>,
+    callback: Option<Callback>,
+}
+
+impl Transaction {
+    pub fn new(address: Address) -> Transaction {
+        Transaction {
+            address: address,
+            operations: Vec::new(),
+            callback: None,
+        }
+    }
+
+    pub fn write(mut self, bytes: &[u8]) -> Transaction {
+        self.operations.push(Operation::Write(Vec::from(bytes)));
+        self
+    }
+
+    pub fn read(mut self, len: usize) -> Transaction {
+        self.operations.push(Operation::Read(len));
+        self
+    }
+
+    /// Writes `value` to the register at `register_address`.
+    pub fn write_register<T: RegisterType>(self, register_address: T, value: T) -> Transaction {
+        let mut bytes = register_bytes(register_address);
+        bytes.extend(register_bytes(value));
+        self.write(&bytes)
+    }
+
+    /// Reads the register at `register_address`. The value can be extracted from the result
+    /// with `decode_register`.
+    pub fn read_register<T: RegisterType>(self, register_address: T) -> Transaction {
+        let len = mem::size_of::<T>();
+        let bytes = register_bytes(register_address);
+        self.write(&bytes).read(len)
+    }
+
+    /// Sets the callback that is called in interrupt context when the transaction is completed.
+    ///
+    /// The callback runs inside `I2cQueue::handle_event` or `I2cQueue::handle_error`, so it
+    /// must not access the queue. Locking the `spin::Mutex` of the queue deadlocks.
+    pub fn on_complete(mut self, callback: Callback) -> Transaction {
+        self.callback = Some(callback);
+        self
+    }
+}
+
+fn register_bytes<T: RegisterType>(value: T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    value
+        .write(|b| {
+            bytes.extend_from_slice(b);
+            Ok(())
+        }).expect("writing register bytes into a buffer can't fail");
+    bytes
+}
+
+/// Decodes a register value from the bytes returned by a transaction.
+pub fn decode_register<T: RegisterType>(data: &[u8]) -> T {
+    T::read(|buffer| {
+        buffer.copy_from_slice(&data[..buffer.len()]);
+        Ok(())
+    }).expect("reading register bytes from a buffer can't fail")
+}
+
+struct Active {
+    id: TransactionId,
+    transaction: Transaction,
+    operation: usize,
+    position: usize,
+    data: Vec<u8>,
+    error: Option<Error>,
+}
+
+pub struct I2cQueue<'a> {
+    i2c: I2C<'a>,
+    pending: VecDeque<(TransactionId, Transaction)>,
+    active: Option<Active>,
+    completed: VecDeque<(TransactionId, Result<Vec<u8>, Error>)>,
+    next_id: usize,
+}
+
+impl<'a> I2C<'a> {
+    /// Enables the I2C interrupts and returns a queue for non-blocking transactions.
+    pub fn into_queue(self) -> I2cQueue<'a> {
+        self.0.icr.write(|w| icr_clear_all(w));
+        self.0.cr1.modify(|_, w| {
+            w.txie().set_bit(); // transmit_interrupt_enable
+            w.rxie().set_bit(); // receive_interrupt_enable
+            w.tcie().set_bit(); // transfer_complete_interrupt_enable
+            w.nackie().set_bit(); // not_acknowledge_received_interrupt_enable
+            w.stopie().set_bit(); // stop_detection_interrupt_enable
+            w.errie().set_bit(); // error_interrupts_enable
+            w
+        });
+
+        I2cQueue {
+            i2c: self,
+            pending: VecDeque::new(),
+            active: None,
+            completed: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<'a> I2cQueue<'a> {
+    /// Queues the transaction and starts it immediately if the bus is idle.
+    pub fn submit(&mut self, transaction: Transaction) -> TransactionId {
+        assert!(
+            !transaction.operations.is_empty(),
+            "transaction has no operations"
+        );
+
+        let id = TransactionId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending.push_back((id, transaction));
+        self.start_next();
+        id
+    }
+
+    /// Returns the result of a completed transaction that has no callback.
+    ///
+    /// Returns `None` if the transaction is not completed yet or its result was already taken.
+    /// Only the results of the last 16 completed transactions are kept, older results are
+    /// dropped.
+    pub fn take_result(&mut self, id: TransactionId) -> Option<Result<Vec<u8>, Error>> {
+        let index = self.completed.iter().position(|&(i, _)| i == id)?;
+        self.completed.remove(index).map(|(_, result)| result)
+    }
+
+    /// Returns true if no transaction is active or pending.
+    pub fn is_idle(&self) -> bool {
+        self.active.is_none() && self.pending.is_empty()
+    }
+
+    /// Disables the I2C interrupts and returns the blocking I2C driver.
+    ///
+    /// Panics if there are active or pending transactions.
+    pub fn release(self) -> I2C<'a> {
+        assert!(self.is_idle(), "I2C queue still has pending transactions");
+        self.i2c.0.cr1.modify(|_, w| {
+            w.txie().clear_bit(); // transmit_interrupt_enable
+            w.rxie().clear_bit(); // receive_interrupt_enable
+            w.tcie().clear_bit(); // transfer_complete_interrupt_enable
+            w.nackie().clear_bit(); // not_acknowledge_received_interrupt_enable
+            w.stopie().clear_bit(); // stop_detection_interrupt_enable
+            w.errie().clear_bit(); // error_interrupts_enable
+            w
+        });
+        self.i2c
+    }
+
+    /// Advances the active transaction. Call this from the I2C event interrupt.
+    pub fn handle_event(&mut self) {
+        let i2c = self.i2c.0;
+        let isr = i2c.isr.read();
+
+        if isr.nackf().bit_is_set() {
+            // nack_received, the hardware generates a stop condition automatically
+            i2c.icr.write(|w| w.nackcf().set_bit()); // not_acknowledge_clear_flag
+            if let Some(ref mut active) = self.active {
+                active.error = Some(Error::Nack);
+            }
+        }
+
+        if let Some(ref mut active) = self.active {
+            if isr.txis().bit_is_set() {
+                // transmit_interrupt_status
+                let byte = match active.transaction.operations[active.operation] {
+                    Operation::Write(ref bytes) => bytes[active.position],
+                    Operation::Read(_) => unreachable!(),
+                };
+                active.position += 1;
+                i2c.txdr.write(|w| unsafe { w.txdata().bits(byte) }); // transmit_data
+            }
+            if isr.rxne().bit_is_set() {
+                // receive_data_register_not_empty
+                active.data.push(i2c.rxdr.read().rxdata().bits()); // receive_data
+                active.position += 1;
+            }
+            if isr.tcr().bit_is_set() {
+                // transfer_complete_reload, continue with the next chunk
+                let remaining =
+                    active.transaction.operations[active.operation].len() - active.position;
+                i2c.cr2.modify(|_, w| unsafe {
+                    w.nbytes().bits(chunk_len(remaining)); // number_of_bytes
+                    w.reload().bit(remaining > MAX_NBYTES); // nbytes_reload_mode
+                    w
+                });
+            }
+            if isr.tc().bit_is_set() {
+                // transfer_complete, continue with the next operation or stop
+                active.operation += 1;
+                active.position = 0;
+                match active.transaction.operations.get(active.operation) {
+                    Some(operation) => start(i2c, active.transaction.address, operation),
+                    None => i2c.cr2.modify(|_, w| w.stop().set_bit()), // stop_generation
+                }
+            }
+        }
+
+        if isr.stopf().bit_is_set() {
+            // stop_detected
+            i2c.icr.write(|w| w.stopcf().set_bit()); // stop_detection_clear_flag
+            i2c.cr2.write(|w| w); // reset cr2
+            self.finish();
+        }
+    }
+
+    /// Aborts the active transaction on bus errors, arbitration loss and overruns. Call this
+    /// from the I2C error interrupt.
+    pub fn handle_error(&mut self) {
+        let i2c = self.i2c.0;
+        let isr = i2c.isr.read();
+
+        let error = if isr.berr().bit_is_set() {
+            Error::BusError
+        } else if isr.arlo().bit_is_set() {
+            Error::ArbitrationLoss
+        } else if isr.ovr().bit_is_set() {
+            Error::Overrun
+        } else {
+            return;
+        };
+
+        i2c.icr.write(|w| icr_clear_all(w));
+        // reset cr2
+        i2c.cr2.write(|w| w);
+
+        if let Some(ref mut active) = self.active {
+            active.error = Some(error);
+        }
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        if let Some(active) = self.active.take() {
+            let result = match active.error {
+                Some(error) => Err(error),
+                None => Ok(active.data),
+            };
+            match active.transaction.callback {
+                Some(callback) => callback(active.id, &result),
+                None => {
+                    if self.completed.len() == MAX_COMPLETED {
+                        self.completed.pop_front();
+                    }
+                    self.completed.push_back((active.id, result));
+                }
+            }
+        }
+        self.start_next();
+    }
+
+    fn start_next(&mut self) {
+        if self.active.is_some() {
+            return;
+        }
+        let (id, transaction) = match self.pending.pop_front() {
+            Some(next) => next,
+            None => return,
+        };
+
+        let i2c = self.i2c.0;
+        i2c.icr.write(|w| icr_clear_all(w));
+        // flush transmit data register
+        i2c.isr.modify(|_, w| w.txe().set_bit()); // flush_txdr
+
+        start(i2c, transaction.address, &transaction.operations[0]);
+
+        self.active = Some(Active {
+            id: id,
+            transaction: transaction,
+            operation: 0,
+            position: 0,
+            data: Vec::new(),
+            error: None,
+        });
+    }
+}
+
+fn start(i2c: &RegisterBlock, device_address: Address, operation: &Operation) {
+    let (read, len) = match operation {
+        Operation::Write(bytes) => (false, bytes.len()),
+        Operation::Read(len) => (true, *len),
+    };
+    i2c.cr2.write(|w| unsafe {
+        w.sadd().bits(device_address.0); // slave_address
+        w.start().set_bit(); // start_generation
+        w.rd_wrn().bit(read); // read_transfer
+        w.nbytes().bits(chunk_len(len)); // number_of_bytes
+        w.reload().bit(len > MAX_NBYTES); // nbytes_reload_mode
+        w.autoend().clear_bit(); // automatic_end_mode
+        w
+    });
+}
@@ -0,0 +1,231 @@
+//! Bus scanning and device identification for diagnosing I2C wiring.
+//!
+//! Example
+//!
+//! ```ignore
+//! println!("{}", scan::report(&mut i2c_3));
+//! ```
+
+use super::{icr_clear_all, Address, Error, I2cBus, I2C};
+use core::fmt;
+
+/// The first 7-bit address that is not reserved by the I2C specification.
+pub const FIRST_ADDRESS: u8 = 0x08;
+/// The last 7-bit address that is not reserved by the I2C specification.
+pub const LAST_ADDRESS: u8 = 0x77;
+
+/// A set of 7-bit device addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AddressSet(u128);
+
+impl AddressSet {
+    pub fn new() -> AddressSet {
+        AddressSet(0)
+    }
+
+    pub fn insert(&mut self, address: u8) {
+        assert!(address < 0x80, "invalid 7 bit address");
+        self.0 |= 1u128 << address;
+    }
+
+    pub fn contains(&self, address: u8) -> bool {
+        address < 0x80 && self.0 & (1u128 << address) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Iterates over the addresses in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> {
+        let set = *self;
+        (0..0x80).filter(move |&address| set.contains(address))
+    }
+}
+
+impl<'a> I2C<'a> {
+    /// Returns true if a device acknowledges the given address.
+    pub fn probe(&mut self, device_address: Address) -> bool {
+        self.pre();
+
+        self.0.cr2.write(|w| unsafe {
+            w.sadd().bits(device_address.0); // slave_address
+            w.start().set_bit(); // start_generation
+            w.nbytes().bits(0); // number_of_bytes
+            w.autoend().set_bit(); // automatic_end_mode
+            w
+        });
+
+        // a stop condition is generated automatically after both ACK and NACK
+        let acknowledged = loop {
+            let isr = self.0.isr.read();
+            if isr.nackf().bit_is_set() {
+                // nack_received
+                break false;
+            }
+            if isr.stopf().bit_is_set() {
+                // stop_detected
+                break true;
+            }
+        };
+
+        while self.0.isr.read().busy().bit_is_set() {}
+        // clear status flags
+        self.0.icr.write(|w| icr_clear_all(w));
+        // reset cr2
+        self.0.cr2.write(|w| w);
+
+        acknowledged
+    }
+
+    /// Probes all non-reserved 7-bit addresses and returns the responding ones.
+    pub fn scan(&mut self) -> AddressSet {
+        let mut responding = AddressSet::new();
+        for address in FIRST_ADDRESS..=LAST_ADDRESS {
+            if self.probe(Address::bits_7(address)) {
+                responding.insert(address);
+            }
+        }
+        responding
+    }
+}
+
+/// Devices on the Discovery board that can be identified by their ID registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KnownDevice {
+    /// FT5336 touch controller.
+    Ft5336,
+    /// WM8994 audio codec.
+    Wm8994,
+}
+
+pub static KNOWN_DEVICES: [KnownDevice; 2] = [KnownDevice::Ft5336, KnownDevice::Wm8994];
+
+impl KnownDevice {
+    /// The 7-bit address of the device.
+    pub fn address(&self) -> u8 {
+        match self {
+            KnownDevice::Ft5336 => 0x38,
+            KnownDevice::Wm8994 => 0x1A,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            KnownDevice::Ft5336 => "FT5336 touch controller",
+            KnownDevice::Wm8994 => "WM8994 audio codec",
+        }
+    }
+
+    /// Reads the ID register of the device and checks it against the expected value.
+    pub fn identify<B: I2cBus>(&self, bus: &mut B) -> Result<bool, Error> {
+        let address = Address::bits_7(self.address());
+        let mut matches = false;
+        match self {
+            KnownDevice::Ft5336 => bus.connect::<u8, _>(address, |mut conn| {
+                matches = conn.read(0xA8)? == 0x51; // family ID register
+                Ok(())
+            })?,
+            KnownDevice::Wm8994 => bus.connect::<u16, _>(address, |mut conn| {
+                matches = conn.read(0)? == 0x8994; // software reset / chip ID register
+                Ok(())
+            })?,
+        }
+        Ok(matches)
+    }
+}
+
+/// The result of probing a single address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// A known device responded with the expected ID.
+    Identified(KnownDevice),
+    /// A device responded at the address of a known device, but its ID did not match or could
+    /// not be read.
+    Mismatch(KnownDevice),
+    /// A device responded at an address that does not belong to a known device.
+    Unknown,
+}
+
+/// Identifies the device at the given 7-bit address.
+pub fn probe_device<B: I2cBus>(bus: &mut B, address: u8) -> Probe {
+    match KNOWN_DEVICES.iter().find(|d| d.address() == address) {
+        Some(&device) => match device.identify(bus) {
+            Ok(true) => Probe::Identified(device),
+            Ok(false) | Err(_) => Probe::Mismatch(device),
+        },
+        None => Probe::Unknown,
+    }
+}
+
+/// A formatted overview of all devices on the bus.
+pub struct Report {
+    responding: AddressSet,
+    probes: [Probe; 0x80],
+}
+
+impl Report {
+    pub fn responding(&self) -> AddressSet {
+        self.responding
+    }
+
+    /// Returns the probe result for the given address, or `None` if no device responded.
+    pub fn probe(&self, address: u8) -> Option<Probe> {
+        if self.responding.contains(address) {
+            Some(self.probes[usize::from(address)])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the known devices that did not respond at their address.
+    pub fn missing(&self) -> impl Iterator<Item = KnownDevice> {
+        let responding = self.responding;
+        KNOWN_DEVICES
+            .iter()
+            .cloned()
+            .filter(move |d| !responding.contains(d.address()))
+    }
+}
+
+/// Scans the bus and identifies all responding devices.
+pub fn report(i2c: &mut I2C) -> Report {
+    let responding = i2c.scan();
+    let mut probes = [Probe::Unknown; 0x80];
+    for address in responding.iter() {
+        probes[usize::from(address)] = probe_device(i2c, address);
+    }
+    Report {
+        responding: responding,
+        probes: probes,
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "I2C scan: {} device(s) found", self.responding.len())?;
+        for address in self.responding.iter() {
+            write!(f, "  {:#04x}: ", address)?;
+            match self.probes[usize::from(address)] {
+                Probe::Identified(device) => writeln!(f, "{}", device.description())?,
+                Probe::Mismatch(device) => {
+                    writeln!(f, "unexpected ID (expected {})", device.description())?
+                }
+                Probe::Unknown => writeln!(f, "unknown device")?,
+            }
+        }
+        for device in self.missing() {
+            writeln!(
+                f,
+                "  {:#04x}: {} missing",
+                device.address(),
+                device.description()
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+//! I2C target (slave) mode.
+//!
+//! In target mode the peripheral answers to its own addresses instead of initiating transfers.
+//! All bus events are handled in the I2C event and error interrupts, which must be forwarded to
+//! `I2cTarget::handle_event` and `I2cTarget::handle_error`.
+//!
+//! Example
+//!
+//! ```ignore
+//! let config = TargetConfig {
+//!     own_address_1: Some(OwnAddress::Bits7(0x42)),
+//!     ..TargetConfig::default()
+//! };
+//! let target = i2c_3.into_target(config, BufferHandler::new());
+//! nvic.enable(Interrupt::I2C3_EV);
+//! nvic.enable(Interrupt::I2C3_ER);
+//!
+//! // in the I2C3_EV interrupt handler:
+//! target.handle_event();
+//! // in the I2C3_ER interrupt handler:
+//! target.handle_error();
+//! ```
+
+use super::{icr_clear_all, Error, I2C};
+use arrayvec::ArrayVec;
+use core::mem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OwnAddress {
+    Bits7(u8),
+    Bits10(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetConfig {
+    /// Own address 1, either 7 or 10 bit.
+    pub own_address_1: Option<OwnAddress>,
+    /// Own address 2 (always 7 bit) and the number of least significant bits (0 to 7) that
+    /// are ignored when comparing it.
+    pub own_address_2: Option<(u8, u8)>,
+    /// Acknowledge the general call address `0x00`.
+    pub general_call: bool,
+}
+
+impl Default for TargetConfig {
+    fn default() -> TargetConfig {
+        TargetConfig {
+            own_address_1: None,
+            own_address_2: None,
+            general_call: false,
+        }
+    }
+}
+
+/// The address that the controller used to address us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchedAddress {
+    OwnAddress1,
+    /// Contains the full received address since own address 2 might be masked.
+    OwnAddress2(u8),
+    GeneralCall,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The controller writes data to us.
+    Receive,
+    /// The controller reads data from us.
+    Transmit,
+}
+
+/// Callbacks for target mode events. They are called from interrupt context.
+pub trait TargetHandler {
+    /// Called when one of our addresses was received.
+    fn address_match(&mut self, address: MatchedAddress, direction: Direction);
+
+    /// Called for every byte written by the controller.
+    fn receive(&mut self, byte: u8);
+
+    /// Called whenever the controller wants to read a byte.
+    fn transmit(&mut self) -> u8;
+
+    /// Called when the transfer is terminated by a stop condition.
+    fn stop(&mut self);
+
+    /// Called on bus errors, arbitration loss and overruns.
+    fn error(&mut self, _error: Error) {}
+}
+
+pub struct I2cTarget<'a, H: TargetHandler> {
+    i2c: I2C<'a>,
+    config: TargetConfig,
+    handler: H,
+}
+
+impl<'a> I2C<'a> {
+    /// Configures the own addresses and enables the target mode interrupts.
+    pub fn into_target<H: TargetHandler>(
+        self,
+        config: TargetConfig,
+        handler: H,
+    ) -> I2cTarget<'a, H> {
+        let i2c = self.0;
+
+        // own addresses can only be changed while they are disabled
+        i2c.oar1.modify(|_, w| w.oa1en().clear_bit()); // own_address_1_enable
+        i2c.oar2.modify(|_, w| w.oa2en().clear_bit()); // own_address_2_enable
+
+        match config.own_address_1 {
+            Some(OwnAddress::Bits7(addr)) => i2c.oar1.write(|w| {
+                unsafe { w.oa1().bits(u16::from(addr) << 1) }; // own_address_1
+                w.oa1mode().clear_bit(); // 7 bit mode
+                w.oa1en().set_bit(); // own_address_1_enable
+                w
+            }),
+            Some(OwnAddress::Bits10(addr)) => i2c.oar1.write(|w| {
+                assert!(addr < 1 << 10, "invalid 10 bit address");
+                unsafe { w.oa1().bits(addr) }; // own_address_1
+                w.oa1mode().set_bit(); // 10 bit mode
+                w.oa1en().set_bit(); // own_address_1_enable
+                w
+            }),
+            None => {}
+        }
+
+        if let Some((addr, mask)) = config.own_address_2 {
+            assert!(addr < 1 << 7, "invalid 7 bit address");
+            assert!(mask <= 7, "own address 2 mask must be between 0 and 7");
+            i2c.oar2.write(|w| unsafe {
+                w.oa2().bits(addr); // own_address_2
+                w.oa2msk().bits(mask); // own_address_2_mask
+                w.oa2en().set_bit(); // own_address_2_enable
+                w
+            });
+        }
+
+        i2c.icr.write(|w| icr_clear_all(w));
+
+        i2c.cr1.modify(|_, w| {
+            w.gcen().bit(config.general_call); // general_call
+            w.sbc().clear_bit(); // slave_byte_control
+            w.addrie().set_bit(); // address_match_interrupt_enable
+            w.rxie().set_bit(); // receive_interrupt_enable
+            w.txie().set_bit(); // transmit_interrupt_enable
+            w.stopie().set_bit(); // stop_detection_interrupt_enable
+            w.nackie().set_bit(); // not_acknowledge_received_interrupt_enable
+            w.errie().set_bit(); // error_interrupts_enable
+            w
+        });
+
+        I2cTarget {
+            i2c: self,
+            config: config,
+            handler: handler,
+        }
+    }
+}
+
+impl<'a, H: TargetHandler> I2cTarget<'a, H> {
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Handles address match, receive, transmit and stop events. Call this from the I2C event
+    /// interrupt.
+    pub fn handle_event(&mut self) {
+        let i2c = self.i2c.0;
+        let isr = i2c.isr.read();
+
+        if isr.addr().bit_is_set() {
+            // address_matched
+            let direction = if isr.dir().bit_is_set() {
+                // flush transmit data register so that the first byte comes from the handler
+                i2c.isr.modify(|_, w| w.txe().set_bit()); // flush_txdr
+                Direction::Transmit
+            } else {
+                Direction::Receive
+            };
+            let address = self.matched_address(isr.addcode().bits());
+            self.handler.address_match(address, direction);
+            i2c.icr.write(|w| w.addrcf().set_bit()); // address_matched_clear_flag
+        }
+        if isr.rxne().bit_is_set() {
+            // receive_data_register_not_empty
+            let byte = i2c.rxdr.read().rxdata().bits(); // receive_data
+            self.handler.receive(byte);
+        }
+        if isr.txis().bit_is_set() {
+            // transmit_interrupt_status
+            let byte = self.handler.transmit();
+            i2c.txdr.write(|w| unsafe { w.txdata().bits(byte) }); // transmit_data
+        }
+        if isr.nackf().bit_is_set() {
+            // the controller does not want more data
+            i2c.icr.write(|w| w.nackcf().set_bit()); // not_acknowledge_clear_flag
+        }
+        if isr.stopf().bit_is_set() {
+            // stop_detected, discard a byte that was prepared but not read by the controller
+            i2c.isr.modify(|_, w| w.txe().set_bit()); // flush_txdr
+            i2c.icr.write(|w| w.stopcf().set_bit()); // stop_detection_clear_flag
+            self.handler.stop();
+        }
+    }
+
+    /// Handles bus errors, arbitration loss and overruns. Call this from the I2C error
+    /// interrupt.
+    pub fn handle_error(&mut self) {
+        let i2c = self.i2c.0;
+        let isr = i2c.isr.read();
+
+        if isr.berr().bit_is_set() {
+            i2c.icr.write(|w| w.berrcf().set_bit()); // bus_error_clear_flag
+            self.handler.error(Error::BusError);
+        }
+        if isr.arlo().bit_is_set() {
+            i2c.icr.write(|w| w.arlocf().set_bit()); // arbitration_lost_clear_flag
+            self.handler.error(Error::ArbitrationLoss);
+        }
+        if isr.ovr().bit_is_set() {
+            i2c.icr.write(|w| w.ovrcf().set_bit()); // overrun_underrun_clear_flag
+            self.handler.error(Error::Overrun);
+        }
+    }
+
+    /// Disables the own addresses and the target mode interrupts.
+    pub fn release(self) -> (I2C<'a>, H) {
+        let i2c = self.i2c.0;
+
+        i2c.cr1.modify(|_, w| {
+            w.gcen().clear_bit(); // general_call
+            w.addrie().clear_bit(); // address_match_interrupt_enable
+            w.rxie().clear_bit(); // receive_interrupt_enable
+            w.txie().clear_bit(); // transmit_interrupt_enable
+            w.stopie().clear_bit(); // stop_detection_interrupt_enable
+            w.nackie().clear_bit(); // not_acknowledge_received_interrupt_enable
+            w.errie().clear_bit(); // error_interrupts_enable
+            w
+        });
+        i2c.oar1.modify(|_, w| w.oa1en().clear_bit()); // own_address_1_enable
+        i2c.oar2.modify(|_, w| w.oa2en().clear_bit()); // own_address_2_enable
+        i2c.icr.write(|w| icr_clear_all(w));
+
+        (self.i2c, self.handler)
+    }
+
+    fn matched_address(&self, addcode: u8) -> MatchedAddress {
+        if addcode == 0 && self.config.general_call {
+            return MatchedAddress::GeneralCall;
+        }
+        match self.config.own_address_1 {
+            // the address code contains the 10 bit header `0b11110xx`
+            Some(OwnAddress::Bits10(addr)) if addcode == (0b11110 << 2) | (addr >> 8) as u8 => {
+                MatchedAddress::OwnAddress1
+            }
+            Some(OwnAddress::Bits7(addr)) if addcode == addr => MatchedAddress::OwnAddress1,
+            _ => MatchedAddress::OwnAddress2(addcode),
+        }
+    }
+}
+
+/// A `TargetHandler` that collects written data in a buffer and answers reads from a
+/// response buffer.
+///
+/// The response is sent from the beginning for every read transfer. If the controller reads
+/// more bytes than available, `0xff` is sent.
+pub struct BufferHandler {
+    received: ArrayVec<[u8; 64]>,
+    response: ArrayVec<[u8; 64]>,
+    response_position: usize,
+    overflow: bool,
+    transfer_complete: bool,
+}
+
+impl BufferHandler {
+    pub fn new() -> BufferHandler {
+        BufferHandler {
+            received: ArrayVec::new(),
+            response: ArrayVec::new(),
+            response_position: 0,
+            overflow: false,
+            transfer_complete: false,
+        }
+    }
+
+    /// Sets the data that is sent on the next read transfers.
+    ///
+    /// Panics if `response` is larger than 64 bytes.
+    pub fn set_response(&mut self, response: &[u8]) {
+        assert!(response.len() <= self.response.capacity());
+        self.response.clear();
+        self.response.extend(response.iter().cloned());
+        self.response_position = 0;
+    }
+
+    /// Returns the data of the last completed write transfer and clears it, or `None` if no
+    /// write transfer was completed since the last call.
+    ///
+    /// The boolean is true if the controller wrote more than 64 bytes, in which case the
+    /// additional bytes were dropped.
+    pub fn take_received(&mut self) -> Option<(ArrayVec<[u8; 64]>, bool)> {
+        if !self.transfer_complete {
+            return None;
+        }
+        self.transfer_complete = false;
+        let overflow = self.overflow;
+        self.overflow = false;
+        Some((mem::replace(&mut self.received, ArrayVec::new()), overflow))
+    }
+}
+
+impl TargetHandler for BufferHandler {
+    fn address_match(&mut self, _address: MatchedAddress, direction: Direction) {
+        match direction {
+            Direction::Receive => {
+                self.received.clear();
+                self.overflow = false;
+                self.transfer_complete = false;
+            }
+            Direction::Transmit => self.response_position = 0,
+        }
+    }
+
+    fn receive(&mut self, byte: u8) {
+        if self.received.try_push(byte).is_err() {
+            self.overflow = true;
+        }
+    }
+
+    fn transmit(&mut self) -> u8 {
+        let byte = self
+            .response
+            .get(self.response_position)
+            .cloned()
+            .unwrap_or(0xff);
+        self.response_position += 1;
+        byte
+    }
+
+    fn stop(&mut self) {
+        if !self.received.is_empty() {
+            self.transfer_complete = true;
+        }
+    }
+}
@@ -1,96 +1,23 @@
-use i2c::{self, I2C};
+use clocks::{ClockConfig, Clocks};
+use i2c::{self, I2cBus, I2C};
 use lcd::{self, Lcd};
 use stm32f7::stm32f7x6::{i2c1, FLASH, FMC, LTDC, PWR, RCC, SAI2, SYST};
-use system_clock;
+use system_clock::{self, Hz};
 
 pub use self::pins::init as pins;
 
 mod pins;
 
-pub fn init_system_clock_216mhz(rcc: &mut RCC, pwr: &mut PWR, flash: &mut FLASH) {
-    // enable power control clock
-    rcc.apb1enr.modify(|_, w| w.pwren().enabled());
-    rcc.apb1enr.read(); // delay
-
-    // reset HSEON and HSEBYP bits before configuring HSE
-    rcc.cr.modify(|_, w| {
-        w.hseon().clear_bit();
-        w.hsebyp().clear_bit();
-        w
-    });
-    // wait until HSE is disabled
-    while rcc.cr.read().hserdy().bit_is_set() {}
-    // turn HSE on
-    rcc.cr.modify(|_, w| w.hseon().set_bit());
-    // wait until HSE is enabled
-    while rcc.cr.read().hserdy().bit_is_clear() {}
-
-    // disable main PLL
-    rcc.cr.modify(|_, w| w.pllon().clear_bit());
-    while rcc.cr.read().pllrdy().bit_is_set() {}
-
-    // Configure the main PLL clock source, multiplication and division factors.
-    // HSE is used as clock source. HSE runs at 25 MHz.
-    // PLLM = 25: Division factor for the main PLLs (PLL, PLLI2S and PLLSAI) input clock
-    // VCO input frequency = PLL input clock frequency / PLLM with 2 ≤ PLLM ≤ 63
-    // => VCO input frequency = 25_000 kHz / 25 = 1_000 kHz = 1 MHz
-    // PPLM = 432: Main PLL (PLL) multiplication factor for VCO
-    // VCO output frequency = VCO input frequency × PLLN with 50 ≤ PLLN ≤ 432
-    // => VCO output frequency 1 Mhz * 432 = 432 MHz
-    // PPLQ = 0 =^= division factor 2: Main PLL (PLL) division factor for main system clock
-    // PLL output clock frequency = VCO frequency / PLLP with PLLP = 2, 4, 6, or 8
-    // => PLL output clock frequency = 432 MHz / 2 = 216 MHz
-    rcc.pllcfgr.modify(|_, w| {
-        w.pllsrc().hse();
-        w.pllp().div2();
-        unsafe {
-            // Frequency = ((TICKS / pllm) * plln) / pllp
-            // HSE runs at 25 MHz
-            w.pllm().bits(25);
-            w.plln().bits(432); // 400 for 200 MHz, 432 for 216 MHz
-            w.pllq().bits(9); // 8 for 200 MHz, 9 for 216 MHz
-        }
-        w
-    });
-    // enable main PLL
-    rcc.cr.modify(|_, w| w.pllon().set_bit());
-    while rcc.cr.read().pllrdy().bit_is_clear() {}
-
-    // enable overdrive
-    pwr.cr1.modify(|_, w| w.oden().set_bit());
-    while pwr.csr1.read().odrdy().bit_is_clear() {}
-    // enable overdrive switching
-    pwr.cr1.modify(|_, w| w.odswen().set_bit());
-    while pwr.csr1.read().odswrdy().bit_is_clear() {}
-
-    // Program the new number of wait states to the LATENCY bits in the FLASH_ACR register
-    flash.acr.modify(|_, w| w.latency().bits(5));
-    // Check that the new number of wait states is taken into account to access the Flash
-    // memory by reading the FLASH_ACR register
-    assert_eq!(flash.acr.read().latency().bits(), 5);
-
-    // HCLK Configuration
-    // HPRE = system clock not divided: AHB prescaler
-    // => AHB clock frequency = system clock / 1 = 216 MHz / 1 = 216 MHz
-    rcc.cfgr.modify(|_, w| w.hpre().div1());
-    // SYSCLK Configuration
-    rcc.cfgr.modify(|_, w| w.sw().pll());
-    while !rcc.cfgr.read().sws().is_pll() {}
-
-    // PCLK1 Configuration
-    // PPRE1: APB Low-speed prescaler (APB1)
-    // => APB low-speed clock frequency = AHB clock / 4 = 216 Mhz / 4 = 54 MHz
-    // FIXME: Frequency should not exceed 45 MHz
-    rcc.cfgr.modify(|_, w| w.ppre1().div4());
-    // PCLK2 Configuration
-    // PPRE2: APB high-speed prescaler (APB2)
-    // => APB high-speed clock frequency = AHB clock / 2 = 216 Mhz / 2 = 108 MHz
-    // FIXME: Frequency should not exceed 90 MHz
-    rcc.cfgr.modify(|_, w| w.ppre2().div2());
+/// Configures SYSCLK and HCLK to 216 MHz, APB1 to 54 MHz and APB2 to 108 MHz from the 25 MHz
+/// HSE.
+pub fn init_system_clock_216mhz(rcc: &mut RCC, pwr: &mut PWR, flash: &mut FLASH) -> Clocks {
+    ClockConfig::default()
+        .freeze(rcc, pwr, flash)
+        .expect("invalid clock configuration")
 }
 
-pub fn init_systick(frequency: system_clock::Hz, systick: &mut SYST, rcc: &RCC) {
-    system_clock::init(frequency, systick, rcc)
+pub fn init_systick(frequency: system_clock::Hz, systick: &mut SYST, clocks: &Clocks) {
+    system_clock::init(frequency, systick, clocks)
 }
 
 pub fn enable_gpio_ports(rcc: &mut RCC) {
@@ -268,15 +195,15 @@ pub fn init_sdram(rcc: &mut RCC, fmc: &mut FMC) {
     }
 }
 
-pub fn init_lcd<'a>(ltdc: &'a mut LTDC, rcc: &mut RCC) -> Lcd<'a> {
-    lcd::init(ltdc, rcc)
+pub fn init_lcd<'a>(ltdc: &'a mut LTDC, rcc: &mut RCC, clocks: &Clocks) -> Lcd<'a> {
+    lcd::init(ltdc, rcc, clocks)
 }
 
-pub fn init_i2c_3<'a>(i2c: &'a i2c1::RegisterBlock, rcc: &mut RCC) -> I2C<'a> {
-    i2c::init(i2c, rcc)
+pub fn init_i2c_3<'a>(i2c: &'a i2c1::RegisterBlock, rcc: &mut RCC, clocks: &Clocks) -> I2C<'a> {
+    i2c::init(i2c, rcc, clocks)
 }
 
-pub fn init_sai_2(sai: &mut SAI2, rcc: &mut RCC) {
+pub fn init_sai_2(sai: &mut SAI2, rcc: &mut RCC, clocks: &Clocks) {
     let audio_frequency = 16000;
 
     // disable block a and block b
@@ -322,8 +249,11 @@ pub fn init_sai_2(sai: &mut SAI2, rcc: &mut RCC) {
     // PLLI2S_VCO Input  = PLL_SOURCE/PLLM
     // PLLI2S_VCO Output = PLLI2S_VCO Input * PLLI2SN
     // SAI_CLK(first level) = PLLI2S_VCO Output/PLLI2SQ
+    let plli2sn = clocks
+        .pll_multiplier(Hz(344_000_000))
+        .expect("PLLI2S VCO of 344 MHz not reachable from the PLL input");
     rcc.plli2scfgr.modify(|_, w| unsafe {
-        w.plli2sn().bits(344);
+        w.plli2sn().bits(plli2sn);
         w.plli2sq().bits(7);
         w
     });
@@ -354,8 +284,7 @@ pub fn init_sai_2(sai: &mut SAI2, rcc: &mut RCC) {
         let freq = {
             // Configure the PLLSAI division factor
             // PLLSAI_VCO Input  = PLL_SOURCE/PLLM
-            // In Case the PLL Source is HSE (External Clock)
-            let vcoinput = 25000000 / u32::from(rcc.pllcfgr.read().pllm().bits());
+            let vcoinput = clocks.pll_input().0 as u32;
 
             // PLLSAI_VCO Output = PLLSAI_VCO Input * PLLSAIN
             // SAI_CLK(first level) = PLLSAI_VCO Output/PLLSAIQ
@@ -476,7 +405,16 @@ pub fn init_sai_2(sai: &mut SAI2, rcc: &mut RCC) {
 
 const WM8994_ADDRESS: i2c::Address = i2c::Address::bits_7(0b0011010);
 
-pub fn init_wm8994(i2c_3: &mut i2c::I2C) -> Result<(), i2c::Error> {
+pub fn init_wm8994<B: I2cBus>(i2c_3: &mut B) -> Result<(), i2c::Error> {
+    configure_wm8994(i2c_3, system_clock::wait_ms)
+}
+
+/// Writes the register sequence of `init_wm8994`, waiting through `wait_ms`.
+fn configure_wm8994<B, D>(i2c_3: &mut B, wait_ms: D) -> Result<(), i2c::Error>
+where
+    B: I2cBus,
+    D: FnOnce(usize),
+{
     i2c_3.connect::<u16, _>(WM8994_ADDRESS, |mut conn| {
         // read and check device family ID
         assert_eq!(conn.read(0).ok(), Some(0x8994));
@@ -494,7 +432,7 @@ pub fn init_wm8994(i2c_3: &mut i2c::I2C) -> Result<(), i2c::Error> {
         // Enable bias generator, Enable VMID
         conn.write(0x01, 0x0003)?;
 
-        system_clock::wait_ms(50);
+        wait_ms(50);
 
         // INPUT_DEVICE_DIGITAL_MICROPHONE_2 :
 
@@ -563,3 +501,78 @@ pub fn init_wm8994(i2c_3: &mut i2c::I2C) -> Result<(), i2c::Error> {
         Ok(())
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use i2c::mock::{MockI2c, Transaction};
+
+    fn register_write(register: u16, value: u16) -> Transaction {
+        Transaction::write(
+            WM8994_ADDRESS,
+            &[
+                (register >> 8) as u8,
+                register as u8,
+                (value >> 8) as u8,
+                value as u8,
+            ],
+        )
+    }
+
+    #[test]
+    fn wm8994_register_sequence() {
+        let writes = [
+            (0x000, 0x0000),
+            (0x102, 0x0003),
+            (0x817, 0x0000),
+            (0x102, 0x0000),
+            (0x039, 0x006C),
+            (0x001, 0x0003),
+            (0x004, 0x0C30),
+            (0x450, 0x00DB),
+            (0x002, 0x6000),
+            (0x608, 0x0002),
+            (0x609, 0x0002),
+            (0x700, 0x000E),
+            (0x210, 0x0033),
+            (0x300, 0x4010),
+            (0x302, 0x0000),
+            (0x208, 0x000A),
+            (0x200, 0x0001),
+            (0x001, 0x0013),
+            (0x620, 0x0002),
+            (0x411, 0x3800),
+            (0x400, 0x01EF),
+            (0x401, 0x01EF),
+            (0x404, 0x01EF),
+            (0x405, 0x01EF),
+        ];
+        let mut expected = vec![
+            Transaction::write(WM8994_ADDRESS, &[0x00, 0x00]),
+            Transaction::read(WM8994_ADDRESS, &[0x89, 0x94]),
+        ];
+        for &(register, value) in writes.iter() {
+            expected.push(register_write(register, value));
+        }
+        expected.push(Transaction::stop());
+        let mut bus = MockI2c::new(expected);
+
+        let mut waited = None;
+        configure_wm8994(&mut bus, |ms| waited = Some(ms)).unwrap();
+        bus.done();
+        assert_eq!(waited, Some(50));
+    }
+
+    #[test]
+    fn wm8994_write_error() {
+        let mut bus = MockI2c::new(vec![
+            Transaction::write(WM8994_ADDRESS, &[0x00, 0x00]),
+            Transaction::read(WM8994_ADDRESS, &[0x89, 0x94]),
+            register_write(0x000, 0x0000).with_error(i2c::Error::Nack),
+        ]);
+
+        let result = configure_wm8994(&mut bus, |_| panic!("waited after a failed write"));
+        assert_eq!(result, Err(i2c::Error::Nack));
+        bus.done();
+    }
+}
@@ -1,10 +1,15 @@
 use self::pin_wrapper::PortPins;
+use gpio::pin::{
+    Pin, Unused, P0, P1, P10, P14, P15, P2, P3, P4, P6, P7, P8, P9, PA, PB, PC, PF, PG, PH, PI,
+};
 use gpio::{
     AlternateFunction, GpioPort, InputPin, OutputPin, OutputSpeed, OutputType, RegisterBlockA,
-    RegisterBlockB, RegisterBlockD, Resistor,
+    RegisterBlockB, RegisterBlockC, RegisterBlockD, RegisterBlockE, RegisterBlockF, RegisterBlockG,
+    RegisterBlockH, RegisterBlockI, RegisterBlockJ, RegisterBlockK, Resistor,
 };
 
 pub struct Pins<
+    'a,
     Led: OutputPin,
     Button: InputPin,
     DisplayEnable: OutputPin,
@@ -16,21 +21,72 @@ pub struct Pins<
     pub display_enable: DisplayEnable,
     pub backlight: Backlight,
     pub sdcard_present: SdcardPresent,
+    pub arduino: ArduinoPins<'a>,
+}
+
+/// The unconfigured pins of the Arduino Uno compatible connector.
+///
+/// D13 is connected to the user LED, so it is available as `Pins::led`.
+///
+/// The Arduino connector is the only general purpose header of the board. The pins of the
+/// other connectors are deliberately not returned: the camera connector (DCMI) shares PH11
+/// with I2C4 and PB8/PB9 with D15/D14, and the USB OTG, audio, microSD, ethernet and LCD
+/// connectors are wired to their on-board peripherals.
+pub struct ArduinoPins<'a> {
+    /// USART6_RX
+    pub d0: Pin<'a, PC, P7, Unused>,
+    /// USART6_TX
+    pub d1: Pin<'a, PC, P6, Unused>,
+    pub d2: Pin<'a, PG, P6, Unused>,
+    /// TIM3_CH1
+    pub d3: Pin<'a, PB, P4, Unused>,
+    pub d4: Pin<'a, PG, P7, Unused>,
+    /// TIM5_CH4
+    pub d5: Pin<'a, PI, P0, Unused>,
+    /// TIM12_CH1
+    pub d6: Pin<'a, PH, P6, Unused>,
+    pub d7: Pin<'a, PI, P3, Unused>,
+    pub d8: Pin<'a, PI, P2, Unused>,
+    /// TIM2_CH1
+    pub d9: Pin<'a, PA, P15, Unused>,
+    /// TIM1_CH1
+    pub d10: Pin<'a, PA, P8, Unused>,
+    /// SPI2_MOSI, TIM12_CH2
+    pub d11: Pin<'a, PB, P15, Unused>,
+    /// SPI2_MISO
+    pub d12: Pin<'a, PB, P14, Unused>,
+    /// I2C1_SDA
+    pub d14: Pin<'a, PB, P9, Unused>,
+    /// I2C1_SCL
+    pub d15: Pin<'a, PB, P8, Unused>,
+    /// ADC3_IN0
+    pub a0: Pin<'a, PA, P0, Unused>,
+    /// ADC3_IN8
+    pub a1: Pin<'a, PF, P10, Unused>,
+    /// ADC3_IN7
+    pub a2: Pin<'a, PF, P9, Unused>,
+    /// ADC3_IN6
+    pub a3: Pin<'a, PF, P8, Unused>,
+    /// ADC3_IN5
+    pub a4: Pin<'a, PF, P7, Unused>,
+    /// ADC3_IN4
+    pub a5: Pin<'a, PF, P6, Unused>,
 }
 
 pub fn init<'a>(
     mut gpio_a: GpioPort<RegisterBlockA<'a>>,
     mut gpio_b: GpioPort<RegisterBlockB<'a>>,
-    mut gpio_c: GpioPort<RegisterBlockD<'a>>,
+    mut gpio_c: GpioPort<RegisterBlockC<'a>>,
     mut gpio_d: GpioPort<RegisterBlockD<'a>>,
-    mut gpio_e: GpioPort<RegisterBlockD<'a>>,
-    mut gpio_f: GpioPort<RegisterBlockD<'a>>,
-    mut gpio_g: GpioPort<RegisterBlockD<'a>>,
-    mut gpio_h: GpioPort<RegisterBlockD<'a>>,
-    mut gpio_i: GpioPort<RegisterBlockD<'a>>,
-    mut gpio_j: GpioPort<RegisterBlockD<'a>>,
-    mut gpio_k: GpioPort<RegisterBlockD<'a>>,
+    mut gpio_e: GpioPort<RegisterBlockE<'a>>,
+    mut gpio_f: GpioPort<RegisterBlockF<'a>>,
+    mut gpio_g: GpioPort<RegisterBlockG<'a>>,
+    mut gpio_h: GpioPort<RegisterBlockH<'a>>,
+    mut gpio_i: GpioPort<RegisterBlockI<'a>>,
+    mut gpio_j: GpioPort<RegisterBlockJ<'a>>,
+    mut gpio_k: GpioPort<RegisterBlockK<'a>>,
 ) -> Pins<
+    'a,
     impl OutputPin + 'a,
     impl InputPin + 'a,
     impl OutputPin + 'a,
@@ -67,9 +123,7 @@ pub fn init<'a>(
         let typ = OutputType::PushPull;
         let res = Resistor::PullUp;
 
-        let b_pins = &[
-            gpio_b_pins.pin_5.pin(), // sdcke1
-        ];
+        // only SDRAM bank 1 is used, so sdcke1 (B5) and sdne1 (H6) are not reserved
         let c_pins = &[
             gpio_c_pins.pin_3.pin(), // sdcke0
         ];
@@ -117,13 +171,9 @@ pub fn init<'a>(
         ];
         let h_pins = &[
             gpio_h_pins.pin_3.pin(), // sdne0
-            gpio_h_pins.pin_6.pin(), // sdne1
             gpio_h_pins.pin_5.pin(), // sdnwe
         ];
 
-        gpio_b
-            .to_alternate_function_all(b_pins, alt_fn, typ, speed, res)
-            .expect("failed to reserve SDRAM GPIO B pins");
         gpio_c
             .to_alternate_function_all(c_pins, alt_fn, typ, speed, res)
             .expect("failed to reserve SDRAM GPIO C pins");
@@ -290,27 +340,19 @@ pub fn init<'a>(
         let typ = OutputType::PushPull;
         let res = Resistor::PullUp;
 
-        // dx = data ports. For Default Bus mode only d0 is needed.
-        let b_pins = &[
-            gpio_b_pins.pin_8.pin(), // d4
-            gpio_b_pins.pin_9.pin(), // d5
-        ];
+        // dx = data ports. For Default Bus mode only d0 is needed. The card slot is connected
+        // with a 4 bit bus, so d4 to d7 are not reserved.
         let c_pins = &[
             gpio_c_pins.pin_8.pin(),  // d0
             gpio_c_pins.pin_9.pin(),  // d1
             gpio_c_pins.pin_10.pin(), // d2
             gpio_c_pins.pin_11.pin(), // d3
-            gpio_c_pins.pin_6.pin(),  // d6
-            gpio_c_pins.pin_7.pin(),  // d7
             gpio_c_pins.pin_12.pin(), // ck (clock)
         ];
         let d_pins = &[
             gpio_d_pins.pin_2.pin(), // cmd
         ];
 
-        gpio_b
-            .to_alternate_function_all(b_pins, alt_fn, typ, speed, res)
-            .expect("Failed to reserve SD card GPIO B pins");
         gpio_c
             .to_alternate_function_all(c_pins, alt_fn, typ, speed, res)
             .expect("Failed to reserve SD card GPIO C pins");
@@ -359,12 +401,38 @@ pub fn init<'a>(
             .expect("Failed to reserve ethernet GPIO G pins");
     }
 
+    // arduino connector pins
+    let arduino = ArduinoPins {
+        d0: gpio_c.take_pin().expect("Arduino pin D0 already in use"),
+        d1: gpio_c.take_pin().expect("Arduino pin D1 already in use"),
+        d2: gpio_g.take_pin().expect("Arduino pin D2 already in use"),
+        d3: gpio_b.take_pin().expect("Arduino pin D3 already in use"),
+        d4: gpio_g.take_pin().expect("Arduino pin D4 already in use"),
+        d5: gpio_i.take_pin().expect("Arduino pin D5 already in use"),
+        d6: gpio_h.take_pin().expect("Arduino pin D6 already in use"),
+        d7: gpio_i.take_pin().expect("Arduino pin D7 already in use"),
+        d8: gpio_i.take_pin().expect("Arduino pin D8 already in use"),
+        d9: gpio_a.take_pin().expect("Arduino pin D9 already in use"),
+        d10: gpio_a.take_pin().expect("Arduino pin D10 already in use"),
+        d11: gpio_b.take_pin().expect("Arduino pin D11 already in use"),
+        d12: gpio_b.take_pin().expect("Arduino pin D12 already in use"),
+        d14: gpio_b.take_pin().expect("Arduino pin D14 already in use"),
+        d15: gpio_b.take_pin().expect("Arduino pin D15 already in use"),
+        a0: gpio_a.take_pin().expect("Arduino pin A0 already in use"),
+        a1: gpio_f.take_pin().expect("Arduino pin A1 already in use"),
+        a2: gpio_f.take_pin().expect("Arduino pin A2 already in use"),
+        a3: gpio_f.take_pin().expect("Arduino pin A3 already in use"),
+        a4: gpio_f.take_pin().expect("Arduino pin A4 already in use"),
+        a5: gpio_f.take_pin().expect("Arduino pin A5 already in use"),
+    };
+
     Pins {
         led,
         button,
         display_enable,
         backlight,
         sdcard_present,
+        arduino,
     }
 }
 
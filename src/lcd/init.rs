@@ -1,7 +1,9 @@
 use super::Lcd;
+use clocks::Clocks;
 use stm32f7::stm32f7x6::{LTDC, RCC};
+use system_clock::Hz;
 
-pub fn init<'a>(ltdc: &'a mut LTDC, rcc: &mut RCC) -> Lcd<'a> {
+pub fn init<'a>(ltdc: &'a mut LTDC, rcc: &mut RCC, clocks: &Clocks) -> Lcd<'a> {
     use lcd::{self, LAYER_1_START, LAYER_2_START};
     const HEIGHT: u16 = lcd::HEIGHT as u16;
     const WIDTH: u16 = lcd::WIDTH as u16;
@@ -19,8 +21,12 @@ pub fn init<'a>(ltdc: &'a mut LTDC, rcc: &mut RCC) -> Lcd<'a> {
     rcc.cr.modify(|_, w| w.pllsaion().clear_bit());
     while rcc.cr.read().pllsairdy().bit_is_set() {}
 
+    // PLLSAI_VCO Output = 192 MHz, LCD_CLK = 192 MHz / PLLSAIR / PLLSAIDIVR = 9.6 MHz
+    let pllsain = clocks
+        .pll_multiplier(Hz(192_000_000))
+        .expect("PLLSAI VCO of 192 MHz not reachable from the PLL input");
     rcc.pllsaicfgr.modify(|_, w| unsafe {
-        w.pllsain().bits(192);
+        w.pllsain().bits(pllsain);
         w.pllsair().bits(5);
         w
     });
@@ -8,6 +8,7 @@
 extern crate alloc;
 extern crate arrayvec;
 extern crate cortex_m;
+extern crate cortex_m_rt;
 extern crate font8x8;
 extern crate spin;
 extern crate stm32f7;
@@ -15,15 +16,23 @@ extern crate stm32f7;
 extern crate bitflags;
 extern crate bit_field;
 extern crate byteorder;
+extern crate embedded_hal;
 extern crate smoltcp;
 extern crate volatile;
 
+pub mod button;
+pub mod clocks;
+pub mod crash;
 pub mod ethernet;
 pub mod gpio;
 pub mod i2c;
 pub mod init;
 pub mod lcd;
+pub mod power;
 pub mod random;
+pub mod rtc;
 pub mod sd;
 pub mod system_clock;
+pub mod timer;
 pub mod touch;
+pub mod watchdog;
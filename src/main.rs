@@ -30,19 +30,27 @@ use smoltcp::{
         Socket, SocketSet, TcpSocket, TcpSocketBuffer, UdpPacketMetadata, UdpSocket,
         UdpSocketBuffer,
     },
-    time::Instant,
     wire::{EthernetAddress, IpAddress, IpEndpoint, Ipv4Address},
 };
-use stm32f7::stm32f7x6::{CorePeripherals, Interrupt, Peripherals};
+use stm32f7::stm32f7x6::{CorePeripherals, Peripherals};
 use stm32f7_discovery::{
-    ethernet,
-    gpio::{GpioPort, InputPin, OutputPin},
-    init,
+    button::{self, Button},
+    crash, ethernet,
+    gpio::{
+        exti::{self, Edge, Exti},
+        GpioPort, OutputPin,
+    },
+    i2c, init,
     lcd::{self, Color},
     random::Rng,
     sd,
-    system_clock::{self, Hz},
+    system_clock::{
+        self,
+        timer::{self, Context},
+        Duration, Hz,
+    },
     touch,
+    watchdog::{IndependentWatchdog, ResetCause},
 };
 
 #[global_allocator]
@@ -72,30 +80,31 @@ fn main() -> ! {
     let mut ethernet_mac = peripherals.ETHERNET_MAC;
     let mut ethernet_dma = peripherals.ETHERNET_DMA;
 
-    init::init_system_clock_216mhz(&mut rcc, &mut pwr, &mut flash);
+    let reset_cause = ResetCause::read_and_clear(&mut rcc);
+    let clocks = init::init_system_clock_216mhz(&mut rcc, &mut pwr, &mut flash);
     init::enable_gpio_ports(&mut rcc);
 
     let gpio_a = GpioPort::new_a(&peripherals.GPIOA);
     let gpio_b = GpioPort::new_b(&peripherals.GPIOB);
-    let gpio_c = GpioPort::new(&peripherals.GPIOC);
-    let gpio_d = GpioPort::new(&peripherals.GPIOD);
-    let gpio_e = GpioPort::new(&peripherals.GPIOE);
-    let gpio_f = GpioPort::new(&peripherals.GPIOF);
-    let gpio_g = GpioPort::new(&peripherals.GPIOG);
-    let gpio_h = GpioPort::new(&peripherals.GPIOH);
-    let gpio_i = GpioPort::new(&peripherals.GPIOI);
-    let gpio_j = GpioPort::new(&peripherals.GPIOJ);
-    let gpio_k = GpioPort::new(&peripherals.GPIOK);
+    let gpio_c = GpioPort::new_c(&peripherals.GPIOC);
+    let gpio_d = GpioPort::new_d(&peripherals.GPIOD);
+    let gpio_e = GpioPort::new_e(&peripherals.GPIOE);
+    let gpio_f = GpioPort::new_f(&peripherals.GPIOF);
+    let gpio_g = GpioPort::new_g(&peripherals.GPIOG);
+    let gpio_h = GpioPort::new_h(&peripherals.GPIOH);
+    let gpio_i = GpioPort::new_i(&peripherals.GPIOI);
+    let gpio_j = GpioPort::new_j(&peripherals.GPIOJ);
+    let gpio_k = GpioPort::new_k(&peripherals.GPIOK);
     let mut pins = init::pins(
         gpio_a, gpio_b, gpio_c, gpio_d, gpio_e, gpio_f, gpio_g, gpio_h, gpio_i, gpio_j, gpio_k,
     );
 
     // configures the system timer to trigger a SysTick exception every second
-    init::init_systick(Hz(100), &mut systick, &rcc);
+    init::init_systick(Hz(100), &mut systick, &clocks);
     systick.enable_interrupt();
 
     init::init_sdram(&mut rcc, &mut fmc);
-    let mut lcd = init::init_lcd(&mut ltdc, &mut rcc);
+    let mut lcd = init::init_lcd(&mut ltdc, &mut rcc, &clocks);
     pins.display_enable.set(true);
     pins.backlight.set(true);
 
@@ -108,27 +117,43 @@ fn main() -> ! {
     lcd::init_stdout(layer_2);
 
     println!("Hello World");
+    println!("Reset cause: {:?}", reset_cause);
+    if let Some(dump) = crash::take() {
+        println!("{}", dump);
+    }
 
     // Initialize the allocator BEFORE you use it
     unsafe { ALLOCATOR.init(rt::heap_start() as usize, HEAP_SIZE) }
 
+    // print a `.` every 500ms
+    timer::start_periodic(500, Context::Interrupt, print_dot);
+
     let xs = vec![1, 2, 3];
 
-    let mut i2c_3 = init::init_i2c_3(&peripherals.I2C3, &mut rcc);
+    let mut i2c_3 = init::init_i2c_3(&peripherals.I2C3, &mut rcc, &clocks);
     i2c_3.test_1();
-    i2c_3.test_2();
 
-    nvic.enable(Interrupt::EXTI0);
+    let mut exti = Exti::new(&peripherals.EXTI, &mut rcc);
+    let button_pin = exti
+        .listen(
+            pins.button,
+            Edge::Rising,
+            button_pressed,
+            &mut syscfg,
+            &mut nvic,
+        ).expect("EXTI line of the button already in use");
+    let mut button = Button::new(button_pin, button::Config::default());
 
-    let mut sd = sd::Sd::new(&mut sdmmc, &mut rcc, &pins.sdcard_present);
+    let mut sd = sd::Sd::new(&mut sdmmc, &mut rcc, &clocks, &pins.sdcard_present);
 
-    init::init_sai_2(&mut sai_2, &mut rcc);
+    init::init_sai_2(&mut sai_2, &mut rcc, &clocks);
     init::init_wm8994(&mut i2c_3).expect("WM8994 init failed");
     // touch initialization should be done after audio initialization, because the touch
     // controller might not be ready yet
     touch::check_family_id(&mut i2c_3).unwrap();
+    print!("{}", i2c::scan::report(&mut i2c_3));
 
-    let mut rng = Rng::init(&mut rng, &mut rcc).expect("RNG init failed");
+    let mut rng = Rng::init(&mut rng, &mut rcc, &clocks).expect("RNG init failed");
     print!("Random numbers: ");
     for _ in 0..4 {
         print!(
@@ -144,11 +169,15 @@ fn main() -> ! {
         Default::default(),
         Default::default(),
         &mut rcc,
+        &clocks,
         &mut syscfg,
         &mut ethernet_mac,
         &mut ethernet_dma,
         ETH_ADDR,
-    ).map(|device| device.into_interface(IP_ADDR));
+    ).map(|mut device| {
+        device.listen(&mut nvic);
+        device.into_interface(IP_ADDR)
+    });
     if let Err(e) = ethernet_interface {
         println!("ethernet init failed: {:?}", e);
     };
@@ -170,19 +199,23 @@ fn main() -> ! {
         sockets.add(example_tcp_socket);
     }
 
-    let mut previous_button_state = pins.button.get();
+    // reset the board if the main loop hangs
+    let mut watchdog = IndependentWatchdog::new(peripherals.IWDG);
+    watchdog.stop_in_debug();
+    watchdog.start(Duration::from_secs(4));
+
     loop {
-        // poll button state
-        let current_button_state = pins.button.get();
-        if current_button_state != previous_button_state {
-            if current_button_state {
-                pins.led.toggle();
+        watchdog.feed();
 
-                // trigger the `EXTI0` interrupt
-                nvic.set_pending(Interrupt::EXTI0);
-            }
+        // run the callbacks of expired deferred timers
+        timer::run_deferred();
 
-            previous_button_state = current_button_state;
+        // toggle the led when the button was clicked
+        button.poll();
+        while let Some(event) = button.next_event() {
+            if event == button::Event::Click {
+                pins.led.toggle();
+            }
         }
 
         // poll for new touch data
@@ -203,11 +236,18 @@ fn main() -> ! {
         audio_writer.set_next_col(data0, data1);
 
         // handle new ethernet packets
+        let ethernet_events = ethernet::take_events();
+        if ethernet_events.intersects(ethernet::Events::ERRORS) {
+            println!("Ethernet DMA error: {:?}", ethernet_events);
+        }
+        if ethernet_events.contains(ethernet::Events::LINK_UP) {
+            println!("Ethernet link up");
+        }
+        if ethernet_events.contains(ethernet::Events::LINK_DOWN) {
+            println!("Ethernet link down");
+        }
         if let Ok(ref mut eth) = ethernet_interface {
-            match eth.poll(
-                &mut sockets,
-                Instant::from_millis(system_clock::ms() as i64),
-            ) {
+            match eth.poll(&mut sockets, system_clock::Instant::now().into()) {
                 Err(::smoltcp::Error::Exhausted) => continue,
                 Err(::smoltcp::Error::Unrecognized) => {}
                 Err(e) => println!("Network error: {:?}", e),
@@ -276,18 +316,30 @@ fn poll_socket(socket: &mut Socket) -> Result<(), smoltcp::Error> {
     Ok(())
 }
 
-interrupt!(EXTI0, exti0, state: Option<HStdout> = None);
-
-fn exti0(_state: &mut Option<HStdout>) {
+fn button_pressed() {
     println!("Interrupt fired! This means that the button was pressed.");
 }
 
+interrupt!(ETH, eth);
+
+fn eth() {
+    ethernet::handle_interrupt();
+}
+
+interrupt!(EXTI15_10, exti15_10);
+
+fn exti15_10() {
+    exti::handle_interrupt();
+}
+
 exception!(SysTick, sys_tick, state: Option<HStdout> = None);
 
 fn sys_tick(_state: &mut Option<HStdout>) {
     system_clock::tick();
-    // print a `.` every 500ms
-    if system_clock::ticks() % 50 == 0 && lcd::stdout::is_initialized() {
+}
+
+fn print_dot() {
+    if lcd::stdout::is_initialized() {
         print!(".");
     }
 }
@@ -295,6 +347,7 @@ fn sys_tick(_state: &mut Option<HStdout>) {
 exception!(HardFault, hard_fault);
 
 fn hard_fault(ef: &ExceptionFrame) -> ! {
+    crash::record_hard_fault(ef);
     panic!("HardFault at {:#?}", ef);
 }
 
@@ -314,16 +367,21 @@ fn rust_oom(_: AllocLayout) -> ! {
 fn panic(info: &PanicInfo) -> ! {
     interrupt::disable();
 
+    crash::record_panic(info);
+
     if lcd::stdout::is_initialized() {
         println!("{}", info);
     }
 
-    if let Ok(mut hstdout) = hio::hstdout() {
-        let _ = writeln!(hstdout, "{}", info);
+    // without a debugger, semihosting and the breakpoint would cause a HardFault
+    if crash::debugger_attached() {
+        if let Ok(mut hstdout) = hio::hstdout() {
+            let _ = writeln!(hstdout, "{}", info);
+        }
+        asm::bkpt();
+        loop {}
     }
 
-    // OK to fire a breakpoint here because we know the microcontroller is connected to a debugger
-    asm::bkpt();
-
-    loop {}
+    // the dump is printed after the reset
+    crash::system_reset()
 }
@@ -0,0 +1,248 @@
+//! Low-power modes and wakeup sources.
+//!
+//! - Sleep: the core clock stops until any enabled interrupt occurs, e.g. the next SysTick.
+//! - Stop: all clocks of the 1.2 V domain stop, the SRAM and registers are kept. Any EXTI line
+//!   in interrupt mode wakes the system, e.g. a gpio pin registered through `gpio::exti` or an
+//!   RTC event enabled through `enable_rtc_wakeup`. The system runs from the HSI after wakeup,
+//!   so `stop` configures the clocks again. The SysTick doesn't run during stop mode, so the
+//!   `system_clock` time lags behind afterwards.
+//! - Standby: the 1.2 V domain is powered off and only the backup domain is kept. The system
+//!   is reset by a rising or falling edge on an enabled WKUP pin, an RTC event or the reset pin.
+//!
+//! Example
+//!
+//! ```ignore
+//! let mut power = Power::new(&mut pwr, &mut rcc);
+//! power.enable_wakeup_pin(WakeupPin::Pi11, WakeupEdge::Rising); // user button
+//! let clocks = power
+//!     .stop(StopMode::default(), ClockConfig::default(), &mut rcc, &mut flash, &mut scb)
+//!     .expect("invalid clock configuration");
+//! ```
+
+use clocks::{self, ClockConfig, Clocks};
+use cortex_m::asm;
+use cortex_m::peripheral::{NVIC, SCB};
+use stm32f7::stm32f7x6::{Interrupt, EXTI, FLASH, PWR, RCC};
+
+/// The pins that can wake the system from standby mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum WakeupPin {
+    Pa0 = 0,
+    Pa2,
+    Pc1,
+    Pc13,
+    Pi8,
+    /// The user button of the discovery board.
+    Pi11,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeupEdge {
+    Rising,
+    Falling,
+}
+
+/// The RTC events that can wake the system from stop mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcEvent {
+    /// RTC alarm A or B, EXTI line 17.
+    Alarm,
+    /// The RTC wakeup timer, EXTI line 22.
+    WakeupTimer,
+}
+
+impl RtcEvent {
+    fn exti_line(self) -> u32 {
+        match self {
+            RtcEvent::Alarm => 17,
+            RtcEvent::WakeupTimer => 22,
+        }
+    }
+
+    fn interrupt(self) -> Interrupt {
+        match self {
+            RtcEvent::Alarm => Interrupt::RTC_ALARM,
+            RtcEvent::WakeupTimer => Interrupt::RTC_WKUP,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StopMode {
+    /// Reduces the consumption by running the voltage regulator in low-power mode, at the cost
+    /// of a longer wakeup time.
+    pub low_power_regulator: bool,
+    /// Powers the flash memory down, at the cost of a longer wakeup time.
+    pub flash_power_down: bool,
+}
+
+impl Default for StopMode {
+    fn default() -> StopMode {
+        StopMode {
+            low_power_regulator: true,
+            flash_power_down: true,
+        }
+    }
+}
+
+pub struct Power<'a> {
+    pwr: &'a mut PWR,
+}
+
+impl<'a> Power<'a> {
+    /// Enables the power control clock.
+    pub fn new(pwr: &'a mut PWR, rcc: &mut RCC) -> Power<'a> {
+        rcc.apb1enr.modify(|_, w| w.pwren().enabled());
+        // delay after an RCC peripheral clock enabling
+        let _unused = rcc.apb1enr.read();
+
+        Power { pwr: pwr }
+    }
+
+    /// Stops the core clock until an interrupt occurs.
+    pub fn sleep(&mut self, scb: &mut SCB) {
+        scb.clear_sleepdeep();
+        asm::wfi();
+    }
+
+    /// Enters stop mode until an EXTI line wakes the system and configures the clocks again.
+    ///
+    /// The PLLI2S and the PLLSAI are disabled after wakeup, so the LCD and the SAI must be
+    /// initialized again. No EXTI line may be pending when this function is called, otherwise
+    /// the system wakes up immediately.
+    pub fn stop(
+        &mut self,
+        mode: StopMode,
+        clock_config: ClockConfig,
+        rcc: &mut RCC,
+        flash: &mut FLASH,
+        scb: &mut SCB,
+    ) -> Result<Clocks, clocks::Error> {
+        // check the configuration before it is too late
+        clock_config.compute()?;
+
+        self.pwr.cr1.modify(|_, w| {
+            w.pdds().clear_bit();
+            w.lpds().bit(mode.low_power_regulator);
+            w.fpds().bit(mode.flash_power_down)
+        });
+
+        scb.set_sleepdeep();
+        asm::wfi();
+        scb.clear_sleepdeep();
+
+        // the system runs from the HSI now
+        clock_config.freeze(rcc, self.pwr, flash)
+    }
+
+    /// Enters standby mode. The system is reset at wakeup.
+    pub fn standby(self, scb: &mut SCB) -> ! {
+        // clear stale wakeup and standby flags, otherwise the system wakes up immediately
+        clear_wakeup_pin_flags(self.pwr);
+        self.pwr.cr1.modify(|_, w| {
+            w.csbf().set_bit();
+            w.pdds().set_bit()
+        });
+
+        scb.set_sleepdeep();
+        loop {
+            asm::wfi();
+        }
+    }
+
+    /// Enables waking up from standby mode by an edge on the pin.
+    pub fn enable_wakeup_pin(&mut self, pin: WakeupPin, edge: WakeupEdge) {
+        let falling = edge == WakeupEdge::Falling;
+        self.pwr.cr2.modify(|_, w| match pin {
+            WakeupPin::Pa0 => w.wupp1().bit(falling),
+            WakeupPin::Pa2 => w.wupp2().bit(falling),
+            WakeupPin::Pc1 => w.wupp3().bit(falling),
+            WakeupPin::Pc13 => w.wupp4().bit(falling),
+            WakeupPin::Pi8 => w.wupp5().bit(falling),
+            WakeupPin::Pi11 => w.wupp6().bit(falling),
+        });
+        self.set_wakeup_pin_enabled(pin, true);
+    }
+
+    pub fn disable_wakeup_pin(&mut self, pin: WakeupPin) {
+        self.set_wakeup_pin_enabled(pin, false);
+    }
+
+    fn set_wakeup_pin_enabled(&mut self, pin: WakeupPin, enabled: bool) {
+        self.pwr.csr2.modify(|_, w| match pin {
+            WakeupPin::Pa0 => w.ewup1().bit(enabled),
+            WakeupPin::Pa2 => w.ewup2().bit(enabled),
+            WakeupPin::Pc1 => w.ewup3().bit(enabled),
+            WakeupPin::Pc13 => w.ewup4().bit(enabled),
+            WakeupPin::Pi8 => w.ewup5().bit(enabled),
+            WakeupPin::Pi11 => w.ewup6().bit(enabled),
+        });
+    }
+
+    /// Returns true if the system was reset by a wakeup from standby mode.
+    pub fn woke_from_standby(&self) -> bool {
+        self.pwr.csr1.read().sbf().bit_is_set()
+    }
+
+    /// Returns true if the pin caused a wakeup from standby mode.
+    pub fn woke_by_pin(&self, pin: WakeupPin) -> bool {
+        let csr2 = self.pwr.csr2.read();
+        match pin {
+            WakeupPin::Pa0 => csr2.wupf1().bit_is_set(),
+            WakeupPin::Pa2 => csr2.wupf2().bit_is_set(),
+            WakeupPin::Pc1 => csr2.wupf3().bit_is_set(),
+            WakeupPin::Pc13 => csr2.wupf4().bit_is_set(),
+            WakeupPin::Pi8 => csr2.wupf5().bit_is_set(),
+            WakeupPin::Pi11 => csr2.wupf6().bit_is_set(),
+        }
+    }
+
+    /// Clears the standby and wakeup flags.
+    pub fn clear_wakeup_flags(&mut self) {
+        clear_wakeup_pin_flags(self.pwr);
+        self.pwr.cr1.modify(|_, w| w.csbf().set_bit());
+    }
+
+    /// Enables waking up from stop mode by the RTC event.
+    ///
+    /// This routes the event to its EXTI line and enables its interrupt, so the interrupt
+    /// handler must call `clear_rtc_wakeup` and clear the event flag in the RTC. The RTC event
+    /// itself must be enabled through the RTC.
+    pub fn enable_rtc_wakeup(&mut self, event: RtcEvent, exti: &EXTI, nvic: &mut NVIC) {
+        enable_rtc_interrupt(event, exti, nvic);
+    }
+
+    pub fn disable_rtc_wakeup(&mut self, event: RtcEvent, exti: &EXTI, nvic: &mut NVIC) {
+        let mask = 1 << event.exti_line();
+        exti.imr.modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+        exti.rtsr.modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+        nvic.disable(event.interrupt());
+    }
+}
+
+/// Clears the wakeup flags of all WKUP pins.
+fn clear_wakeup_pin_flags(pwr: &PWR) {
+    pwr.cr2.modify(|_, w| {
+        w.cwupf1().set_bit();
+        w.cwupf2().set_bit();
+        w.cwupf3().set_bit();
+        w.cwupf4().set_bit();
+        w.cwupf5().set_bit();
+        w.cwupf6().set_bit()
+    });
+}
+
+/// Routes the RTC event to its EXTI line and enables its interrupt.
+pub(crate) fn enable_rtc_interrupt(event: RtcEvent, exti: &EXTI, nvic: &mut NVIC) {
+    let mask = 1 << event.exti_line();
+    exti.rtsr.modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+    exti.pr.write(|w| unsafe { w.bits(mask) });
+    exti.imr.modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+    nvic.enable(event.interrupt());
+}
+
+/// Clears the pending EXTI line of the RTC event.
+pub fn clear_rtc_wakeup(event: RtcEvent, exti: &EXTI) {
+    exti.pr.write(|w| unsafe { w.bits(1 << event.exti_line()) });
+}
@@ -4,7 +4,7 @@
 //! Example
 //!
 //! ```
-//! let mut random_gen = rng::init(rng, rcc);
+//! let mut random_gen = rng::init(rng, rcc, &clocks);
 //! match random_gen.poll_and_get() {
 //!
 //!         Ok(random_number) => {
@@ -25,6 +25,7 @@
 //!
 //! Iter is currently not implemented. Pull Requests welcome!
 
+use clocks::Clocks;
 use core::ops::Drop;
 use core::result::Result;
 use stm32f7::stm32f7x6::{RCC, RNG};
@@ -46,12 +47,17 @@ pub enum ErrorType {
     SEIS,
     AlreadyEnabled,
     NotReady,
+    /// The 48 MHz clock is disabled in the clock configuration.
+    NoClock,
 }
 
 impl<'a> Rng<'a> {
     ///! This will take semi-ownership (with &'static) for the rng struct
     /// from board::rng.
-    pub fn init(rng: &'a mut RNG, rcc: &mut RCC) -> Result<Self, ErrorType> {
+    pub fn init(rng: &'a mut RNG, rcc: &mut RCC, clocks: &Clocks) -> Result<Self, ErrorType> {
+        if clocks.clk48().is_none() {
+            return Err(ErrorType::NoClock);
+        }
         let control_register = rng.cr.read().rngen();
         if control_register.bit_is_set() {
             return Err(ErrorType::AlreadyEnabled);
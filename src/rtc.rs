@@ -0,0 +1,559 @@
+//! Real-time clock with calendar, alarms, wakeup timer and backup memory.
+//!
+//! The RTC runs from the 32.768 kHz LSE crystal and keeps running across resets and in the
+//! low-power modes, so it is only configured once after a power loss. The 32 backup registers
+//! and the 4 KB backup SRAM keep their content as long as the backup domain is powered.
+//!
+//! Alarms and the wakeup timer are connected to the interrupts `RTC_ALARM` (alarm A and B) and
+//! `RTC_WKUP` through EXTI lines 17 and 22. Their handlers must clear the event with
+//! `clear_event` and the EXTI line with `power::clear_rtc_wakeup`.
+//!
+//! Example
+//!
+//! ```ignore
+//! let mut rtc = Rtc::new(&mut peripherals.RTC, &mut rcc, &mut pwr).expect("LSE not ready");
+//! if !rtc.is_set() {
+//!     rtc.set_date_time(DateTime::new(2018, 7, 15, 12, 0, 0))
+//!         .expect("RTC initialization failed");
+//! }
+//! let now = rtc.date_time().expect("RTC not synchronized");
+//! rtc.set_alarm(Alarm::A, AlarmTime::daily(7, 30, 0));
+//! rtc.listen(Event::AlarmA, &peripherals.EXTI, &mut nvic);
+//! ```
+
+use core::slice;
+use cortex_m::peripheral::NVIC;
+use crash;
+use power::{self, RtcEvent};
+use stm32f7::stm32f7x6::{EXTI, PWR, RCC, RTC};
+use system_clock::{self, Duration};
+
+// RCC BDCR RTCSEL
+const RTCSEL_NONE: u8 = 0b00;
+const RTCSEL_LSE: u8 = 0b01;
+
+/// The LSE may need up to two seconds to start.
+const LSE_TIMEOUT_MS: usize = 2000;
+const INIT_TIMEOUT_MS: usize = 100;
+const SYNC_TIMEOUT_MS: usize = 100;
+
+/// The number of backup registers.
+pub const BACKUP_REGISTERS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The LSE crystal did not start.
+    LseTimeout,
+    /// The RTC did not enter initialization mode.
+    InitTimeout,
+    /// The calendar shadow registers were not synchronized in time.
+    SyncTimeout,
+    /// The date or time is invalid or not between the years 2000 and 2099.
+    InvalidDateTime,
+    /// The wakeup period is longer than 2^17 seconds.
+    InvalidPeriod,
+}
+
+/// A date and time between 2000-01-01 and 2099-12-31 in 24 hour format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1 to 12.
+    pub month: u8,
+    /// 1 to 31.
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Weekday {
+    Monday = 1,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl DateTime {
+    pub fn new(year: u16, month: u8, day: u8, hours: u8, minutes: u8, seconds: u8) -> DateTime {
+        DateTime {
+            year: year,
+            month: month,
+            day: day,
+            hours: hours,
+            minutes: minutes,
+            seconds: seconds,
+        }
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        // Sakamoto's method, 0 = Sunday
+        const OFFSETS: [u16; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+        let year = if self.month < 3 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let day = (year + year / 4 - year / 100
+            + year / 400
+            + OFFSETS[self.month as usize - 1]
+            + u16::from(self.day))
+            % 7;
+        match day {
+            1 => Weekday::Monday,
+            2 => Weekday::Tuesday,
+            3 => Weekday::Wednesday,
+            4 => Weekday::Thursday,
+            5 => Weekday::Friday,
+            6 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+
+    /// The number of seconds since 1970-01-01 00:00:00.
+    pub fn unix_timestamp(&self) -> u32 {
+        const DAYS_BEFORE_MONTH: [u32; 12] =
+            [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let year = u32::from(self.year);
+        let leap_day = if year % 4 == 0 && self.month > 2 {
+            1
+        } else {
+            0
+        };
+        // all years between 1970 and 2099 that are divisible by 4 are leap years
+        let days = (year - 1970) * 365
+            + (year - 1969) / 4
+            + DAYS_BEFORE_MONTH[self.month as usize - 1]
+            + leap_day
+            + u32::from(self.day)
+            - 1;
+        ((days * 24 + u32::from(self.hours)) * 60 + u32::from(self.minutes)) * 60
+            + u32::from(self.seconds)
+    }
+
+    fn is_valid(&self) -> bool {
+        const DAYS_IN_MONTH: [u8; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+        self.year >= 2000
+            && self.year <= 2099
+            && self.month >= 1
+            && self.month <= 12
+            && self.day >= 1
+            && self.day <= DAYS_IN_MONTH[self.month as usize - 1]
+            && (self.month != 2 || self.day <= 28 || self.year % 4 == 0)
+            && self.hours < 24
+            && self.minutes < 60
+            && self.seconds < 60
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alarm {
+    A,
+    B,
+}
+
+/// The time at which an alarm fires. Fields that are `None` match any value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlarmTime {
+    /// The day of the month.
+    pub day: Option<u8>,
+    pub hours: Option<u8>,
+    pub minutes: Option<u8>,
+    pub seconds: Option<u8>,
+}
+
+impl AlarmTime {
+    /// Fires every day at the given time.
+    pub fn daily(hours: u8, minutes: u8, seconds: u8) -> AlarmTime {
+        AlarmTime {
+            day: None,
+            hours: Some(hours),
+            minutes: Some(minutes),
+            seconds: Some(seconds),
+        }
+    }
+}
+
+/// The RTC interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    AlarmA,
+    AlarmB,
+    Wakeup,
+}
+
+impl Event {
+    fn rtc_event(self) -> RtcEvent {
+        match self {
+            Event::AlarmA | Event::AlarmB => RtcEvent::Alarm,
+            Event::Wakeup => RtcEvent::WakeupTimer,
+        }
+    }
+
+    fn set_interrupt_enabled(self, rtc: &RTC, enabled: bool) {
+        rtc.cr.modify(|_, w| match self {
+            Event::AlarmA => w.alraie().bit(enabled), // alarm A interrupt enable
+            Event::AlarmB => w.alrbie().bit(enabled), // alarm B interrupt enable
+            Event::Wakeup => w.wutie().bit(enabled),  // wakeup timer interrupt enable
+        });
+    }
+}
+
+/// Writes the alarm time to ALRMAR or ALRMBR. Fields that are `None` are masked, so that
+/// they are not compared.
+macro_rules! write_alarm {
+    ($register:expr, $time:expr) => {{
+        let time = $time;
+        $register.write(|w| unsafe {
+            match time.day {
+                Some(day) => {
+                    w.dt().bits(day / 10); // date tens
+                    w.du().bits(day % 10); // date units
+                }
+                None => {
+                    w.msk4().set_bit(); // date mask
+                }
+            }
+            match time.hours {
+                Some(hours) => {
+                    w.ht().bits(hours / 10); // hour tens
+                    w.hu().bits(hours % 10); // hour units
+                }
+                None => {
+                    w.msk3().set_bit(); // hours mask
+                }
+            }
+            match time.minutes {
+                Some(minutes) => {
+                    w.mnt().bits(minutes / 10); // minute tens
+                    w.mnu().bits(minutes % 10); // minute units
+                }
+                None => {
+                    w.msk2().set_bit(); // minutes mask
+                }
+            }
+            match time.seconds {
+                Some(seconds) => {
+                    w.st().bits(seconds / 10); // second tens
+                    w.su().bits(seconds % 10); // second units
+                }
+                None => {
+                    w.msk1().set_bit(); // seconds mask
+                }
+            }
+            w
+        })
+    }};
+}
+
+pub struct Rtc<'a> {
+    rtc: &'a mut RTC,
+}
+
+impl<'a> Rtc<'a> {
+    /// Enables the LSE and the RTC, unless they are already running since a previous reset.
+    ///
+    /// This also enables write access to the backup domain.
+    pub fn new(rtc: &'a mut RTC, rcc: &mut RCC, pwr: &mut PWR) -> Result<Rtc<'a>, Error> {
+        enable_backup_domain_access(rcc, pwr);
+
+        let bdcr = rcc.bdcr.read();
+        let rtcsel = bdcr.rtcsel().bits();
+        if !bdcr.rtcen().bit_is_set() || rtcsel != RTCSEL_LSE {
+            // another clock source can only be changed by a backup domain reset, which also
+            // clears the calendar and the backup registers
+            if rtcsel != RTCSEL_NONE && rtcsel != RTCSEL_LSE {
+                rcc.bdcr.modify(|_, w| w.bdrst().set_bit());
+                rcc.bdcr.modify(|_, w| w.bdrst().clear_bit());
+            }
+
+            rcc.bdcr.modify(|_, w| w.lseon().set_bit());
+            let start = system_clock::ms();
+            while rcc.bdcr.read().lserdy().bit_is_clear() {
+                if system_clock::ms().wrapping_sub(start) > LSE_TIMEOUT_MS {
+                    return Err(Error::LseTimeout);
+                }
+            }
+            rcc.bdcr.modify(|_, w| {
+                unsafe { w.rtcsel().bits(RTCSEL_LSE) };
+                w.rtcen().set_bit()
+            });
+        }
+
+        let mut rtc = Rtc { rtc: rtc };
+        if !rtc.is_set() {
+            // 32768 Hz / (127 + 1) / (255 + 1) = 1 Hz
+            rtc.modify_calendar(|rtc| {
+                rtc.prer.write(|w| unsafe { w.prediv_a().bits(127) });
+                rtc.prer.write(|w| unsafe {
+                    w.prediv_a().bits(127); // asynchronous prescaler
+                    w.prediv_s().bits(255) // synchronous prescaler
+                });
+            })?;
+        }
+        Ok(rtc)
+    }
+
+    /// Returns true if the calendar was set since the last power loss of the backup domain.
+    pub fn is_set(&self) -> bool {
+        self.rtc.isr.read().inits().bit_is_set()
+    }
+
+    /// Sets the date and time.
+    ///
+    /// Returns `Error::InvalidDateTime` if the date or time is invalid or not between the
+    /// years 2000 and 2099.
+    pub fn set_date_time(&mut self, date_time: DateTime) -> Result<(), Error> {
+        if !date_time.is_valid() {
+            return Err(Error::InvalidDateTime);
+        }
+
+        let year = (date_time.year - 2000) as u8;
+        self.modify_calendar(|rtc| {
+            rtc.tr.write(|w| unsafe {
+                w.ht().bits(date_time.hours / 10); // hour tens
+                w.hu().bits(date_time.hours % 10); // hour units
+                w.mnt().bits(date_time.minutes / 10); // minute tens
+                w.mnu().bits(date_time.minutes % 10); // minute units
+                w.st().bits(date_time.seconds / 10); // second tens
+                w.su().bits(date_time.seconds % 10) // second units
+            });
+            rtc.dr.write(|w| unsafe {
+                w.yt().bits(year / 10); // year tens
+                w.yu().bits(year % 10); // year units
+                w.wdu().bits(date_time.weekday() as u8); // weekday
+                w.mt().bit(date_time.month >= 10); // month tens
+                w.mu().bits(date_time.month % 10); // month units
+                w.dt().bits(date_time.day / 10); // date tens
+                w.du().bits(date_time.day % 10) // date units
+            });
+        })
+    }
+
+    /// Reads the current date and time.
+    pub fn date_time(&mut self) -> Result<DateTime, Error> {
+        // RSF may still be set from before a wakeup from stop mode, so it is cleared and the
+        // next synchronization of the shadow registers is awaited
+        self.unlocked(|rtc| {
+            rtc.isr.modify(|_, w| {
+                w.rsf().clear_bit(); // registers synchronization flag
+                w.init().clear_bit() // initialization mode
+            })
+        });
+        let start = system_clock::ms();
+        while self.rtc.isr.read().rsf().bit_is_clear() {
+            if system_clock::ms().wrapping_sub(start) > SYNC_TIMEOUT_MS {
+                return Err(Error::SyncTimeout);
+            }
+        }
+
+        // reading TR locks DR until it is read
+        let time = self.rtc.tr.read();
+        let date = self.rtc.dr.read();
+        Ok(DateTime {
+            year: 2000 + u16::from(date.yt().bits() * 10 + date.yu().bits()),
+            month: date.mt().bit() as u8 * 10 + date.mu().bits(),
+            day: date.dt().bits() * 10 + date.du().bits(),
+            hours: time.ht().bits() * 10 + time.hu().bits(),
+            minutes: time.mnt().bits() * 10 + time.mnu().bits(),
+            seconds: time.st().bits() * 10 + time.su().bits(),
+        })
+    }
+
+    /// Configures and enables the alarm.
+    pub fn set_alarm(&mut self, alarm: Alarm, time: AlarmTime) {
+        self.unlocked(|rtc| match alarm {
+            Alarm::A => {
+                rtc.cr.modify(|_, w| w.alrae().clear_bit());
+                while rtc.isr.read().alrawf().bit_is_clear() {}
+                write_alarm!(rtc.alrmar, time);
+                rtc.cr.modify(|_, w| w.alrae().set_bit());
+            }
+            Alarm::B => {
+                rtc.cr.modify(|_, w| w.alrbe().clear_bit());
+                while rtc.isr.read().alrbwf().bit_is_clear() {}
+                write_alarm!(rtc.alrmbr, time);
+                rtc.cr.modify(|_, w| w.alrbe().set_bit());
+            }
+        });
+    }
+
+    pub fn disable_alarm(&mut self, alarm: Alarm) {
+        self.unlocked(|rtc| {
+            rtc.cr.modify(|_, w| match alarm {
+                Alarm::A => w.alrae().clear_bit(),
+                Alarm::B => w.alrbe().clear_bit(),
+            })
+        });
+    }
+
+    /// Starts the periodic wakeup timer.
+    ///
+    /// Periods up to 32 seconds have a resolution of about 0.5 ms, longer periods up to 2^17
+    /// seconds (about 36 hours) a resolution of one second. Longer periods return
+    /// `Error::InvalidPeriod`.
+    pub fn start_wakeup_timer(&mut self, period: Duration) -> Result<(), Error> {
+        let micros = period.as_secs() * 1_000_000 + u64::from(period.subsec_micros());
+        let (wucksel, reload) = if micros <= 32_000_000 {
+            // RTCCLK / 16 = 2048 Hz
+            (0b000, micros * 2048 / 1_000_000)
+        } else if period.as_secs() <= 0x1_0000 {
+            // ck_spre = 1 Hz
+            (0b100, period.as_secs())
+        } else if period.as_secs() <= 0x2_0000 {
+            // ck_spre with 2^16 added to the reload value
+            (0b110, period.as_secs() - 0x1_0000)
+        } else {
+            return Err(Error::InvalidPeriod);
+        };
+        let reload = reload.max(1) - 1;
+
+        self.unlocked(|rtc| {
+            rtc.cr.modify(|_, w| w.wute().clear_bit());
+            while rtc.isr.read().wutwf().bit_is_clear() {}
+            rtc.wutr.write(|w| unsafe { w.wut().bits(reload as u16) });
+            rtc.cr.modify(|_, w| {
+                unsafe { w.wcksel().bits(wucksel) }; // wakeup clock selection
+                w.wute().set_bit() // wakeup timer enable
+            });
+        });
+        Ok(())
+    }
+
+    pub fn stop_wakeup_timer(&mut self) {
+        self.unlocked(|rtc| rtc.cr.modify(|_, w| w.wute().clear_bit()));
+    }
+
+    /// Enables the interrupt of the event.
+    pub fn listen(&mut self, event: Event, exti: &EXTI, nvic: &mut NVIC) {
+        self.unlocked(|rtc| event.set_interrupt_enabled(rtc, true));
+        power::enable_rtc_interrupt(event.rtc_event(), exti, nvic);
+    }
+
+    pub fn unlisten(&mut self, event: Event) {
+        self.unlocked(|rtc| event.set_interrupt_enabled(rtc, false));
+    }
+
+    /// Returns true if the event occurred since its flag was cleared.
+    pub fn is_pending(&self, event: Event) -> bool {
+        let isr = self.rtc.isr.read();
+        match event {
+            Event::AlarmA => isr.alraf().bit_is_set(),
+            Event::AlarmB => isr.alrbf().bit_is_set(),
+            Event::Wakeup => isr.wutf().bit_is_set(),
+        }
+    }
+
+    pub fn clear_event(&mut self, event: Event) {
+        // the flags are cleared by writing 0, INIT must stay 0
+        self.rtc.isr.modify(|_, w| {
+            match event {
+                Event::AlarmA => w.alraf().clear_bit(),
+                Event::AlarmB => w.alrbf().clear_bit(),
+                Event::Wakeup => w.wutf().clear_bit(),
+            };
+            w.init().clear_bit()
+        });
+    }
+
+    /// Reads one of the 32 backup registers.
+    pub fn read_backup(&self, index: usize) -> u32 {
+        assert!(index < BACKUP_REGISTERS);
+        let register = &self.rtc.bkp0r as *const _ as *const u32;
+        unsafe { register.add(index).read_volatile() }
+    }
+
+    /// Writes one of the 32 backup registers. They are cleared on a backup domain reset.
+    pub fn write_backup(&mut self, index: usize, value: u32) {
+        assert!(index < BACKUP_REGISTERS);
+        let register = &self.rtc.bkp0r as *const _ as *mut u32;
+        unsafe { register.add(index).write_volatile(value) }
+    }
+
+    /// Calls `f` with disabled write protection.
+    fn unlocked<F: FnOnce(&RTC) -> R, R>(&mut self, f: F) -> R {
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xca) });
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0x53) });
+        let ret = f(self.rtc);
+        // any wrong key enables the write protection again
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xff) });
+        ret
+    }
+
+    /// Calls `f` in initialization mode, in which the calendar is stopped and can be written.
+    fn modify_calendar<F: FnOnce(&RTC)>(&mut self, f: F) -> Result<(), Error> {
+        self.unlocked(|rtc| {
+            rtc.isr.modify(|_, w| w.init().set_bit());
+            let start = system_clock::ms();
+            while rtc.isr.read().initf().bit_is_clear() {
+                if system_clock::ms().wrapping_sub(start) > INIT_TIMEOUT_MS {
+                    rtc.isr.modify(|_, w| w.init().clear_bit());
+                    return Err(Error::InitTimeout);
+                }
+            }
+
+            f(rtc);
+
+            // the shadow registers are synchronized again after leaving initialization mode
+            rtc.isr.modify(|_, w| {
+                w.init().clear_bit(); // initialization mode
+                w.rsf().clear_bit() // registers synchronization flag
+            });
+            Ok(())
+        })
+    }
+}
+
+/// The 4 KB backup SRAM, which keeps its content in standby mode and on VBAT.
+///
+/// The last `crash::RESERVED_SIZE` bytes are used for crash dumps and are not part of the
+/// slice.
+pub struct BackupSram {
+    memory: &'static mut [u8],
+}
+
+impl BackupSram {
+    const ADDRESS: usize = 0x4002_4000;
+    const SIZE: usize = 4 * 1024 - crash::RESERVED_SIZE;
+
+    /// Enables the backup SRAM and its low-power regulator.
+    ///
+    /// Returns `None` if the backup SRAM was already taken.
+    pub fn take(rcc: &mut RCC, pwr: &mut PWR) -> Option<BackupSram> {
+        use core::sync::atomic::{AtomicBool, Ordering};
+        static TAKEN: AtomicBool = AtomicBool::new(false);
+
+        if TAKEN.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        enable_backup_domain_access(rcc, pwr);
+        rcc.ahb1enr.modify(|_, w| w.bkpsramen().enabled());
+        pwr.csr1.modify(|_, w| w.bre().set_bit());
+        while pwr.csr1.read().brr().bit_is_clear() {}
+
+        let memory = unsafe { slice::from_raw_parts_mut(Self::ADDRESS as *mut u8, Self::SIZE) };
+        Some(BackupSram { memory: memory })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.memory
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.memory
+    }
+}
+
+fn enable_backup_domain_access(rcc: &mut RCC, pwr: &mut PWR) {
+    rcc.apb1enr.modify(|_, w| w.pwren().enabled());
+    // delay after an RCC peripheral clock enabling
+    let _unused = rcc.apb1enr.read();
+    pwr.cr1.modify(|_, w| w.dbp().set_bit());
+    while pwr.cr1.read().dbp().bit_is_clear() {}
+}
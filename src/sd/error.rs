@@ -2,6 +2,7 @@
 pub enum Error {
     Error,                            // Unknown Error
     NoSdCard,                         // No SD Card
+    NoClock,                          // The 48 MHz clock is disabled in the clock configuration
     Timeout,                          // Timeout while waiting for a response
     InvalidVoltrange,                 // Voltage Trial failed
     CardError { t: CardStatusFlags }, // Card Error, see CardStatusFlags
@@ -8,8 +8,9 @@ use stm32f7::stm32f7x6::{RCC, SDMMC1};
 ///
 /// # Errors
 ///
-/// This function returns `NoSdCard` Error, if there is no SD Card inserted. This function also
-/// fails, if a command to the SDMMC-Controller fails.
+/// This function returns `NoSdCard` Error, if there is no SD Card inserted, and `NoClock` Error,
+/// if the 48 MHz clock is disabled. This function also fails, if a command to the
+/// SDMMC-Controller fails.
 ///
 /// # Examples
 /// Initialization, if a card is inserted on startup.
@@ -18,7 +19,7 @@ use stm32f7::stm32f7x6::{RCC, SDMMC1};
 /// fn main(hw: board::Hardware) -> ! {
 ///     // Setup board...
 ///
-///     let mut sd = sd::Sd::new(sdmmc, &mut gpio, rcc);
+///     let mut sd = sd::Sd::new(sdmmc, rcc, &clocks, &present_pin);
 ///     sd::init(&mut sd).expect("Init failed");
 ///
 ///     loop {}
@@ -31,7 +32,7 @@ use stm32f7::stm32f7x6::{RCC, SDMMC1};
 /// fn main(hw: board::Hardware) -> ! {
 ///     // Setup board...
 ///
-///     let mut sd = sd::Sd::new(sdmmc, &mut gpio, rcc);
+///     let mut sd = sd::Sd::new(sdmmc, rcc, &clocks, &present_pin);
 ///
 ///     loop {
 ///         if sd.card_present() && !sd.card_initialized() {
@@ -56,6 +57,10 @@ pub fn init<P: InputPin>(sd: &mut Sd<P>) -> Result<(), Error> {
         return Ok(());
     }
 
+    // SDMMC_CK = SDMMCCLK / (CLKDIV + 2) must not exceed 400 kHz during identification
+    let clk48 = sd.clk48.ok_or(Error::NoClock)?;
+    let clkdiv = ((clk48.0 + 400_000 - 1) / 400_000).max(2) - 2;
+
     // default clock configuration
     sd.sdmmc.clkcr.modify(|_, w| {
         w.negedge().clear_bit();
@@ -64,7 +69,7 @@ pub fn init<P: InputPin>(sd: &mut Sd<P>) -> Result<(), Error> {
         w.hwfc_en().clear_bit();
         unsafe {
             w.widbus().bits(0);
-            w.clkdiv().bits(0x76);
+            w.clkdiv().bits(clkdiv as u8);
         }
         w
     });
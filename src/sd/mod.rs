@@ -8,15 +8,19 @@ mod sdmmc_cmd;
 
 use self::error::*;
 use alloc::vec::Vec;
+use clocks::Clocks;
 use core::cmp::min;
 use gpio::InputPin;
 use stm32f7::stm32f7x6::{RCC, SDMMC1};
+use system_clock::Hz;
 
 /// SD handle.
 pub struct Sd<'a, PresentPin: InputPin + 'a> {
     sdmmc: &'a mut SDMMC1,
     card_info: Option<CardInfo>,
     present_pin: &'a PresentPin,
+    /// The SDMMC kernel clock.
+    clk48: Option<Hz>,
 }
 
 impl<'a, PresentPin: InputPin> Sd<'a, PresentPin> {
@@ -30,7 +34,7 @@ impl<'a, PresentPin: InputPin> Sd<'a, PresentPin> {
     ///     // Setup board...
     ///
     ///     // Create SD handle
-    ///     let mut sd = sd::Sd::new(sdmmc, &mut gpio, rcc);
+    ///     let mut sd = sd::Sd::new(sdmmc, rcc, &clocks, &present_pin);
     ///     // Initialize SD Card
     ///     if let Some(i_err) = sd::init(&mut sd).err() {
     ///         hprintln!("{:?}", i_err);
@@ -39,13 +43,19 @@ impl<'a, PresentPin: InputPin> Sd<'a, PresentPin> {
     ///     loop {}
     /// }
     /// ```
-    pub fn new(sdmmc: &'a mut SDMMC1, rcc: &mut RCC, present_pin: &'a PresentPin) -> Self {
+    pub fn new(
+        sdmmc: &'a mut SDMMC1,
+        rcc: &mut RCC,
+        clocks: &Clocks,
+        present_pin: &'a PresentPin,
+    ) -> Self {
         self::init::init_hw(rcc);
 
         Sd {
             sdmmc: sdmmc,
             card_info: None,
             present_pin: present_pin,
+            clk48: clocks.clk48(),
         }
     }
 
@@ -78,7 +88,7 @@ impl<'a, PresentPin: InputPin> Sd<'a, PresentPin> {
     /// fn main(hw: board::Hardware) -> ! {
     ///     // Setup board...
     ///
-    ///     let mut sd = sd::Sd::new(sdmmc, &mut gpio, rcc);
+    ///     let mut sd = sd::Sd::new(sdmmc, rcc, &clocks, &present_pin);
     ///     sd::init(&mut sd).expect("Init failed");
     ///
     ///     match sd.read_blocks(42, 2) {
@@ -120,7 +130,7 @@ impl<'a, PresentPin: InputPin> Sd<'a, PresentPin> {
     /// fn main(hw: board::Hardware) -> ! {
     ///     // Setup board...
     ///
-    ///     let mut sd = sd::Sd::new(sdmmc, &mut gpio, rcc);
+    ///     let mut sd = sd::Sd::new(sdmmc, rcc, &clocks, &present_pin);
     ///     sd::init(&mut sd).expect("Init failed");
     ///
     ///     let data = vec![0; 256];
@@ -0,0 +1,298 @@
+use clocks::Clocks;
+use core::convert::TryFrom;
+use core::ops::{Add, AddAssign, Sub};
+use core::sync::atomic::{AtomicUsize, Ordering};
+pub use core::time::Duration;
+use cortex_m::peripheral::SCB;
+use cortex_m::{asm, interrupt};
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use stm32f7::stm32f7x6::SYST;
+
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+/// The upper word of the 64-bit tick count, incremented when `TICKS` wraps.
+static TICKS_HIGH: AtomicUsize = AtomicUsize::new(0);
+static SYSTEM_CLOCK_SPEED: AtomicUsize = AtomicUsize::new(0);
+static FREQUENCY: AtomicUsize = AtomicUsize::new(0);
+/// The number of core clock cycles per tick.
+static CYCLES_PER_TICK: AtomicUsize = AtomicUsize::new(0);
+
+// SCB ICSR
+const PENDSTSET: u32 = 1 << 26;
+
+pub mod timer;
+
+/// Advances the time by one tick and runs the expired timers, must be called from the SysTick
+/// exception.
+pub fn tick() {
+    interrupt::free(|_| {
+        if TICKS.fetch_add(1, Ordering::AcqRel) == usize::max_value() {
+            TICKS_HIGH.fetch_add(1, Ordering::AcqRel);
+        }
+    });
+    timer::expire(ticks_u64());
+}
+
+/// The number of ticks since `init`, wrapping at `usize::max_value()`.
+///
+/// Use `wrapping_sub` to compute the difference of two tick counts.
+pub fn ticks() -> usize {
+    TICKS.load(Ordering::Acquire)
+}
+
+/// The number of ticks since `init`, which does not overflow in practice.
+pub fn ticks_u64() -> u64 {
+    loop {
+        let high = TICKS_HIGH.load(Ordering::Acquire);
+        let low = TICKS.load(Ordering::Acquire);
+        if TICKS_HIGH.load(Ordering::Acquire) == high {
+            return (high as u64) << 32 | low as u64;
+        }
+    }
+}
+
+/// The number of milliseconds since `init`, wrapping at `usize::max_value()`.
+pub fn ms() -> usize {
+    let frequency = FREQUENCY.load(Ordering::Acquire) as u64;
+    (ticks_u64() * 1000 / frequency) as usize
+}
+
+/// Waits until `ticks` ticks have passed, sleeping between the ticks.
+pub fn wait_ticks(ticks: usize) {
+    let start = self::ticks();
+    while self::ticks().wrapping_sub(start) < ticks {
+        asm::wfi();
+    }
+}
+
+pub fn wait_ms(ms: usize) {
+    wait(Duration::from_millis(ms as u64));
+}
+
+pub fn wait_us(us: usize) {
+    wait(Duration::from_micros(us as u64));
+}
+
+/// Waits until `duration` has passed, with microsecond resolution.
+///
+/// The core sleeps until the next tick as long as the deadline is more than a tick away and
+/// busy-waits for the rest. Returns immediately if `init` was not called yet, because the time
+/// doesn't advance without the SysTick.
+pub fn wait(duration: Duration) {
+    let frequency = FREQUENCY.load(Ordering::Acquire) as u64;
+    if frequency == 0 {
+        return;
+    }
+    let deadline = Instant::now() + duration;
+    let tick = Duration::from_micros(1_000_000 / frequency);
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        if deadline - now > tick {
+            asm::wfi();
+        }
+    }
+}
+
+pub fn init(Hz(frequency): Hz, systick: &mut SYST, clocks: &Clocks) {
+    use cortex_m::peripheral::syst::SystClkSource;
+
+    // the SysTick runs at the core clock, which is HCLK
+    let Hz(system_clock_speed) = clocks.hclk();
+    let reload_ticks = u32::try_from(system_clock_speed / frequency).unwrap();
+
+    SYSTEM_CLOCK_SPEED.store(system_clock_speed, Ordering::Release);
+    FREQUENCY.store(frequency, Ordering::Release);
+    CYCLES_PER_TICK.store(reload_ticks as usize, Ordering::Release);
+
+    // SysTick Reload Value Register = 216_000_000 / 1000 - 1 = 215_999
+    // => SysTick interrupt tiggers every 1 ms
+    systick.set_clock_source(SystClkSource::Core);
+    systick.set_reload(reload_ticks - 1);
+    systick.clear_current();
+    systick.enable_counter();
+}
+
+pub fn system_clock_speed() -> Hz {
+    Hz(SYSTEM_CLOCK_SPEED.load(Ordering::Acquire))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(transparent)]
+pub struct Hz(pub usize);
+
+pub fn ticks_to_ms(ticks: usize) -> usize {
+    let frequency = FREQUENCY.load(Ordering::Acquire) as u64;
+    (ticks as u64 * 1000 / frequency) as usize
+}
+
+pub fn ms_to_ticks(ms: usize) -> usize {
+    let frequency = FREQUENCY.load(Ordering::Acquire) as u64;
+    let ticks_x1000 = frequency * ms as u64;
+    let ticks = if ticks_x1000 % 1000 == 0 {
+        ticks_x1000 / 1000
+    } else {
+        (ticks_x1000 / 1000) + 1 // round up
+    };
+    ticks as usize
+}
+
+/// A point in time, measured in microseconds since `init`.
+///
+/// The time is derived from the tick count and the current value of the SysTick counter, so it
+/// has microsecond resolution independent of the tick frequency. The 64-bit value does not
+/// overflow in practice.
+///
+/// A pending SysTick exception is taken into account, so the time is also correct in a critical
+/// section or in a handler with higher priority, as long as it does not block the SysTick
+/// exception for more than one tick. Before `init`, `now` always returns the zero instant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    micros: u64,
+}
+
+impl Instant {
+    pub fn now() -> Instant {
+        let frequency = FREQUENCY.load(Ordering::Acquire) as u64;
+        if frequency == 0 {
+            // `init` was not called yet
+            return Instant::from_micros(0);
+        }
+        let cycles_per_tick = CYCLES_PER_TICK.load(Ordering::Acquire) as u64;
+        let system_clock_speed = SYSTEM_CLOCK_SPEED.load(Ordering::Acquire) as u64;
+
+        // read the tick count again if a tick happened while reading the counter value, because
+        // the counter value could belong to either of the two ticks
+        let (ticks, current) = loop {
+            let ticks = ticks_u64();
+            let mut current = unsafe { (*SYST::ptr()).cvr.read() };
+            // the counter wrapped, but the tick was not counted yet; the counter value is read
+            // again because the wrap could have happened after the first read
+            let pending = unsafe { (*SCB::ptr()).icsr.read() } & PENDSTSET != 0;
+            if pending {
+                current = unsafe { (*SYST::ptr()).cvr.read() };
+            }
+            if ticks_u64() == ticks {
+                break (ticks + pending as u64, u64::from(current));
+            }
+        };
+
+        // the SysTick counts down from `cycles_per_tick - 1` to 0
+        let cycles = cycles_per_tick - 1 - current.min(cycles_per_tick - 1);
+        Instant {
+            micros: ticks * 1_000_000 / frequency + cycles * 1_000_000 / system_clock_speed,
+        }
+    }
+
+    pub fn from_micros(micros: u64) -> Instant {
+        Instant { micros: micros }
+    }
+
+    pub fn from_millis(millis: u64) -> Instant {
+        Instant {
+            micros: millis * 1000,
+        }
+    }
+
+    pub fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    pub fn as_millis(&self) -> u64 {
+        self.micros / 1000
+    }
+
+    /// Returns the time since `earlier`, or zero if `earlier` is later than `self`.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_micros(self.micros.saturating_sub(earlier.micros))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+fn duration_as_micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant::from_micros(self.micros + duration_as_micros(duration))
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    /// Saturates at the time of `init`.
+    fn sub(self, duration: Duration) -> Instant {
+        Instant::from_micros(self.micros.saturating_sub(duration_as_micros(duration)))
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+impl From<Instant> for ::smoltcp::time::Instant {
+    fn from(instant: Instant) -> ::smoltcp::time::Instant {
+        ::smoltcp::time::Instant::from_millis(instant.as_millis() as i64)
+    }
+}
+
+/// Blocking delay provider for `embedded-hal` drivers.
+///
+/// The delays wait until the `Instant` reaches the deadline, so `init` must be called and the
+/// SysTick exception must call `tick`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Delay;
+
+impl DelayMs<u32> for Delay {
+    fn delay_ms(&mut self, ms: u32) {
+        wait_ms(ms as usize);
+    }
+}
+
+impl DelayMs<u16> for Delay {
+    fn delay_ms(&mut self, ms: u16) {
+        wait_ms(ms.into());
+    }
+}
+
+impl DelayMs<u8> for Delay {
+    fn delay_ms(&mut self, ms: u8) {
+        wait_ms(ms.into());
+    }
+}
+
+impl DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        wait_us(us as usize);
+    }
+}
+
+impl DelayUs<u16> for Delay {
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(u32::from(us));
+    }
+}
+
+impl DelayUs<u8> for Delay {
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(u32::from(us));
+    }
+}
@@ -0,0 +1,277 @@
+//! One-shot and periodic software timers.
+//!
+//! The timers are checked on every tick, so their resolution is one tick. A timer callback
+//! either runs in interrupt context directly from `system_clock::tick`, or it is deferred and
+//! runs when the main loop calls `run_deferred`. Interrupt context callbacks must be short, but
+//! they may start and cancel timers.
+//!
+//! The pending timers are kept in a binary heap ordered by their deadline, so a tick only looks
+//! at the timers that are due and starting or cancelling a timer is cheap even with hundreds of
+//! timers.
+//!
+//! Example
+//!
+//! ```ignore
+//! let blink = timer::start_periodic(500, Context::Deferred, toggle_led);
+//! loop {
+//!     timer::run_deferred();
+//!     // …
+//! }
+//! timer::cancel(blink);
+//! ```
+
+use super::{ms_to_ticks, ticks_u64};
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::mem;
+use cortex_m::interrupt;
+use spin::Mutex;
+
+/// Called when a timer expires.
+pub type Handler = fn();
+
+/// Where the callback of a timer runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    /// In the SysTick exception.
+    Interrupt,
+    /// In the next call of `run_deferred`.
+    Deferred,
+}
+
+/// Identifies a started timer, e.g. for cancelling it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId {
+    index: usize,
+    generation: u32,
+}
+
+struct Slot {
+    generation: u32,
+    /// `None` if the slot is free.
+    callback: Option<Handler>,
+    context: Context,
+    period: Option<u64>,
+    deadline: u64,
+    /// True while the timer is in the heap.
+    armed: bool,
+    /// The number of expirations that were not run by `run_deferred` yet.
+    pending: usize,
+}
+
+struct Timers {
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+    /// The deadline, index and generation of all armed timers, earliest deadline first. Each
+    /// slot has at most one entry.
+    queue: BinaryHeap<Reverse<(u64, usize, u32)>>,
+    /// The slots with pending deferred callbacks, each contained at most once.
+    ///
+    /// The capacity of `free`, `queue` and `ready` is at least the number of slots, so pushing
+    /// never allocates in the SysTick exception.
+    ready: Vec<usize>,
+}
+
+static TIMERS: Mutex<Option<Timers>> = Mutex::new(None);
+
+/// Calls `callback` once after `delay_ms` milliseconds.
+pub fn start_once(delay_ms: usize, context: Context, callback: Handler) -> TimerId {
+    start(ms_to_ticks(delay_ms) as u64, None, context, callback)
+}
+
+/// Calls `callback` every `period_ms` milliseconds, starting `period_ms` milliseconds from now.
+pub fn start_periodic(period_ms: usize, context: Context, callback: Handler) -> TimerId {
+    let period = ms_to_ticks(period_ms).max(1) as u64;
+    start(period, Some(period), context, callback)
+}
+
+fn start(delay: u64, period: Option<u64>, context: Context, callback: Handler) -> TimerId {
+    interrupt::free(|_| {
+        let mut timers = TIMERS.lock();
+        let timers = timers.get_or_insert_with(|| Timers {
+            slots: Vec::new(),
+            free: Vec::new(),
+            queue: BinaryHeap::new(),
+            ready: Vec::new(),
+        });
+
+        let index = match timers.free.pop() {
+            Some(index) => index,
+            None => {
+                timers.slots.push(Slot {
+                    generation: 0,
+                    callback: None,
+                    context: context,
+                    period: None,
+                    deadline: 0,
+                    armed: false,
+                    pending: 0,
+                });
+                // `free`, `queue` and `ready` never contain more entries than there are slots
+                let slots = timers.slots.len();
+                let (free, queue, ready) =
+                    (timers.free.len(), timers.queue.len(), timers.ready.len());
+                timers.free.reserve(slots - free);
+                timers.queue.reserve(slots - queue);
+                timers.ready.reserve(slots - ready);
+                slots - 1
+            }
+        };
+
+        let deadline = ticks_u64() + delay;
+        let slot = &mut timers.slots[index];
+        slot.callback = Some(callback);
+        slot.context = context;
+        slot.period = period;
+        slot.deadline = deadline;
+        slot.armed = true;
+        slot.pending = 0;
+        let generation = slot.generation;
+
+        timers.queue.push(Reverse((deadline, index, generation)));
+        TimerId {
+            index: index,
+            generation: generation,
+        }
+    })
+}
+
+/// Stops the timer and discards its pending deferred callbacks.
+///
+/// Returns false if the timer was already cancelled or was a one-shot timer that has run.
+pub fn cancel(id: TimerId) -> bool {
+    interrupt::free(|_| {
+        let mut timers = TIMERS.lock();
+        match timers.as_mut() {
+            Some(timers) => {
+                let in_use = match timers.slots.get(id.index) {
+                    Some(slot) => slot.generation == id.generation && slot.callback.is_some(),
+                    None => false,
+                };
+                if in_use {
+                    timers.ready.retain(|&index| index != id.index);
+                    timers.unqueue(id.index);
+                    timers.release(id.index);
+                }
+                in_use
+            }
+            None => false,
+        }
+    })
+}
+
+/// Runs the callbacks of expired deferred timers.
+///
+/// Should be called regularly from the main loop. A callback runs once for every expiration,
+/// even if the main loop was too slow to run it in time.
+pub fn run_deferred() {
+    loop {
+        let callback = interrupt::free(|_| {
+            let mut timers = TIMERS.lock();
+            let timers = timers.as_mut()?;
+            loop {
+                let index = *timers.ready.last()?;
+                let (callback, done) = {
+                    let slot = &mut timers.slots[index];
+                    slot.pending = slot.pending.saturating_sub(1);
+                    (slot.callback, slot.pending == 0)
+                };
+                if done {
+                    timers.ready.pop();
+                    if !timers.slots[index].armed {
+                        timers.release(index);
+                    }
+                }
+                if callback.is_some() {
+                    return callback;
+                }
+            }
+        });
+
+        match callback {
+            Some(callback) => callback(),
+            None => break,
+        }
+    }
+}
+
+/// Runs or defers the callbacks of all timers that are due at `now`.
+pub(super) fn expire(now: u64) {
+    loop {
+        let callback = interrupt::free(|_| {
+            let mut timers = TIMERS.lock();
+            let timers = timers.as_mut()?;
+            loop {
+                let Reverse((deadline, index, generation)) = *timers.queue.peek()?;
+                if deadline > now {
+                    return None;
+                }
+                timers.queue.pop();
+                if let Some(callback) = timers.fire(index, generation) {
+                    return Some(callback);
+                }
+            }
+        });
+
+        match callback {
+            Some(callback) => callback(),
+            None => break,
+        }
+    }
+}
+
+impl Timers {
+    /// Reschedules or disarms an expired timer and returns its callback if it should run now.
+    fn fire(&mut self, index: usize, generation: u32) -> Option<Handler> {
+        let (callback, context) = {
+            let slot = &mut self.slots[index];
+            if slot.generation != generation || !slot.armed {
+                // cancelled or restarted
+                return None;
+            }
+            match slot.period {
+                Some(period) => {
+                    slot.deadline += period;
+                    self.queue.push(Reverse((slot.deadline, index, generation)));
+                }
+                None => slot.armed = false,
+            }
+            (slot.callback, slot.context)
+        };
+
+        match context {
+            Context::Interrupt => {
+                if !self.slots[index].armed {
+                    self.release(index);
+                }
+                callback
+            }
+            Context::Deferred => {
+                let slot = &mut self.slots[index];
+                slot.pending += 1;
+                if slot.pending == 1 {
+                    self.ready.push(index);
+                }
+                None
+            }
+        }
+    }
+
+    /// Removes the queue entry of the slot, so that the queue doesn't grow when timers are
+    /// cancelled and restarted before their deadline.
+    fn unqueue(&mut self, index: usize) {
+        let mut queue = mem::replace(&mut self.queue, BinaryHeap::new()).into_vec();
+        queue.retain(|&Reverse((_, entry, _))| entry != index);
+        self.queue = BinaryHeap::from(queue);
+    }
+
+    fn release(&mut self, index: usize) {
+        let slot = &mut self.slots[index];
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.callback = None;
+        slot.armed = false;
+        slot.pending = 0;
+        self.free.push(index);
+    }
+}
@@ -0,0 +1,182 @@
+use super::*;
+
+// input capture selection
+const CC_INPUT_SAME: u8 = 0b01;
+const CC_INPUT_OTHER: u8 = 0b10;
+// trigger selection
+const TS_TI1FP1: u8 = 0b101;
+// slave mode selection
+const SMS_RESET: u8 = 0b100;
+
+/// A timer that measures the period and pulse width of the signal on a channel 1 pin.
+///
+/// Channel 1 captures the counter at every rising edge, which also resets the counter, and
+/// channel 2 captures it at every falling edge.
+pub struct PwmInput<T: SlaveMode, P> {
+    timer: Timer<T>,
+    pin: P,
+    resolution: Hz,
+    last: Option<Measurement>,
+}
+
+/// A measured period of the input signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Measurement {
+    /// The period in counter cycles.
+    pub period: u32,
+    /// The time the signal was high in counter cycles.
+    pub pulse_width: u32,
+    /// The frequency of the counter.
+    pub resolution: Hz,
+}
+
+impl Measurement {
+    pub fn frequency(&self) -> Hz {
+        Hz(self.resolution.0 / self.period.max(1) as usize)
+    }
+
+    /// The ratio of the pulse width to the period, between 0 and 1.
+    pub fn duty_cycle(&self) -> f32 {
+        self.pulse_width as f32 / self.period.max(1) as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncoderMode {
+    /// Counts the edges of the channel 1 input.
+    Channel1,
+    /// Counts the edges of the channel 2 input.
+    Channel2,
+    /// Counts the edges of both inputs, four counts per encoder step.
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// A timer that counts the steps of a quadrature encoder on the channel 1 and 2 pins.
+pub struct Encoder<T: SlaveMode, P1, P2> {
+    timer: Timer<T>,
+    pins: (P1, P2),
+}
+
+impl<T: SlaveMode> Timer<T> {
+    /// Starts measuring the input signal on the pin.
+    ///
+    /// The counter runs at `resolution`, which limits the lowest measurable frequency to
+    /// `resolution / 2^16` for 16 bit timers.
+    pub fn pwm_input<P: ChannelPin<T, C1>>(
+        self,
+        pin: P,
+        resolution: Hz,
+    ) -> PwmInput<T, P::Alternate> {
+        self.set_counter_frequency(resolution);
+        let resolution = Hz(self.clock.0 / (T::prescaler() as usize + 1));
+
+        T::set_input_capture(0, CC_INPUT_SAME);
+        T::set_input_capture(1, CC_INPUT_OTHER);
+        T::set_channel_inverted(0, false);
+        T::set_channel_inverted(1, true);
+        T::set_channel_enabled(0, true);
+        T::set_channel_enabled(1, true);
+        // reset the counter at every rising edge
+        T::set_slave_mode(TS_TI1FP1, SMS_RESET);
+        // the resets don't set the update flag, so it signals a counter overflow
+        self.restart();
+
+        PwmInput {
+            timer: self,
+            pin: pin.into_alternate(),
+            resolution: resolution,
+            last: None,
+        }
+    }
+
+    /// Starts counting the encoder steps.
+    ///
+    /// The counter counts from zero up to the maximum and wraps around.
+    pub fn encoder<P1, P2>(
+        self,
+        pin_1: P1,
+        pin_2: P2,
+        mode: EncoderMode,
+    ) -> Encoder<T, P1::Alternate, P2::Alternate>
+    where
+        P1: ChannelPin<T, C1>,
+        P2: ChannelPin<T, C2>,
+    {
+        T::set_prescaler(0);
+        T::set_auto_reload(self.max_count() as u32);
+        T::set_channel_enabled(0, false);
+        T::set_channel_enabled(1, false);
+        T::set_input_capture(0, CC_INPUT_SAME);
+        T::set_input_capture(1, CC_INPUT_SAME);
+        T::set_channel_inverted(0, false);
+        T::set_channel_inverted(1, false);
+        let sms = match mode {
+            EncoderMode::Channel1 => 0b010,
+            EncoderMode::Channel2 => 0b001,
+            EncoderMode::Both => 0b011,
+        };
+        T::set_slave_mode(0, sms);
+        self.restart();
+
+        Encoder {
+            timer: self,
+            pins: (pin_1.into_alternate(), pin_2.into_alternate()),
+        }
+    }
+}
+
+impl<T: SlaveMode, P> PwmInput<T, P> {
+    /// Returns the last complete period of the input signal, or `None` if there was no rising
+    /// edge during a whole counter overflow period.
+    pub fn measure(&mut self) -> Option<Measurement> {
+        if T::clear_update() {
+            self.last = None;
+        }
+        if T::capture_1_pending() {
+            // reading CCR1 clears the capture flag
+            self.last = Some(Measurement {
+                period: T::compare(0),
+                pulse_width: T::compare(1),
+                resolution: self.resolution,
+            });
+        }
+        self.last
+    }
+
+    /// Resets the timer and returns it together with the pin.
+    pub fn release(self) -> (Timer<T>, P) {
+        T::reset();
+        (self.timer, self.pin)
+    }
+}
+
+impl<T: SlaveMode, P1, P2> Encoder<T, P1, P2> {
+    pub fn count(&self) -> u32 {
+        T::counter()
+    }
+
+    pub fn set_count(&mut self, count: u32) {
+        T::set_counter(count);
+    }
+
+    /// The direction of the last step.
+    pub fn direction(&self) -> Direction {
+        if T::counting_down() {
+            Direction::Down
+        } else {
+            Direction::Up
+        }
+    }
+
+    /// Resets the timer and returns it together with the pins.
+    pub fn release(self) -> (Timer<T>, P1, P2) {
+        T::reset();
+        (self.timer, self.pins.0, self.pins.1)
+    }
+}
@@ -0,0 +1,542 @@
+//! General-purpose and advanced-control timers TIM1 to TIM14.
+//!
+//! A `Timer` takes ownership of a timer peripheral and computes its prescaler and auto-reload
+//! values from the timer clock in `Clocks`. It can generate periodic update interrupts or be
+//! turned into one of the following modes:
+//!
+//! - `Pwm`: PWM output with one `PwmChannel` per pin and duty cycle control.
+//! - `OnePulse`: a single pulse with a given delay and width after a software trigger.
+//! - `PwmInput`: measurement of the frequency and duty cycle of an input signal.
+//! - `Encoder`: counting the edges of a quadrature encoder.
+//!
+//! Channel pins are type-state `gpio::pin::Pin`s that implement `ChannelPin` for the timer and
+//! channel, so only pins that are connected to a timer channel can be used. They are switched to
+//! the corresponding alternate function automatically.
+//!
+//! The basic timers TIM6 and TIM7 only support periodic update events, so they don't implement
+//! `Channels`. `PwmInput` and `Encoder` need the slave mode controller, which only the timers
+//! that implement `SlaveMode` (TIM1 to TIM5 and TIM8) have. The update interrupts of some timers
+//! share a vector, e.g. TIM1 and TIM10 use `TIM1_UP_TIM10`.
+//!
+//! Example
+//!
+//! ```ignore
+//! let mut pwm = Timer::new(peripherals.TIM3, &clocks, &mut rcc).pwm(Hz(1000));
+//! let mut d3 = pwm.channel(pins.arduino.d3);
+//! d3.set_duty(pwm.max_duty() / 4);
+//! d3.enable();
+//! ```
+
+use clocks::Clocks;
+use cortex_m::peripheral::NVIC;
+use stm32f7::stm32f7x6::{
+    Interrupt, RCC, TIM1, TIM10, TIM11, TIM12, TIM13, TIM14, TIM2, TIM3, TIM4, TIM5, TIM6, TIM7,
+    TIM8, TIM9,
+};
+use system_clock::{Duration, Hz};
+
+pub use self::capture::{Direction, Encoder, EncoderMode, Measurement, PwmInput};
+pub use self::pins::{ChannelId, ChannelPin, C1, C2, C3, C4};
+pub use self::pwm::{OnePulse, Pwm, PwmChannel};
+
+mod capture;
+mod pins;
+mod pwm;
+
+/// A timer peripheral.
+///
+/// The register blocks of the timers differ, so the register accesses that the modes need are
+/// implemented for each timer.
+pub trait Instance {
+    /// The counter has 32 bits instead of 16 (TIM2 and TIM5).
+    const COUNTER_32BIT: bool;
+    /// The interrupt vector of the update event.
+    const INTERRUPT: Interrupt;
+
+    fn enable_clock(rcc: &mut RCC);
+
+    /// The input clock of the prescaler, which depends on the APB the timer is connected to.
+    fn timer_clock(clocks: &Clocks) -> Hz;
+
+    /// Restores the reset configuration, which also stops the counter.
+    fn reset();
+
+    fn set_counter_enabled(enabled: bool);
+
+    fn counter_enabled() -> bool;
+
+    /// Only counter overflows set the update flag, not `generate_update`. `preload` buffers the
+    /// auto-reload value until the next update event, `one_pulse` stops the counter at the next
+    /// update event.
+    fn configure_counter(preload: bool, one_pulse: bool);
+
+    /// Loads the prescaler and auto-reload value and resets the counter.
+    fn generate_update();
+
+    /// Clears all status flags.
+    fn clear_flags();
+
+    fn set_update_interrupt(enabled: bool);
+
+    /// Returns true and clears the flag if an update event occurred.
+    fn clear_update() -> bool;
+
+    /// Sets the prescaler, which divides the timer clock by `prescaler + 1`.
+    fn set_prescaler(prescaler: u16);
+
+    fn prescaler() -> u16;
+
+    fn set_auto_reload(value: u32);
+
+    fn auto_reload() -> u32;
+
+    fn counter() -> u32;
+
+    fn set_counter(value: u32);
+
+    /// Enables the channel outputs of the advanced-control timers TIM1 and TIM8 through the MOE
+    /// bit. Does nothing for the other timers.
+    fn enable_outputs();
+}
+
+/// A timer with capture/compare channels.
+///
+/// The channels are identified by their zero-based index, see `ChannelId::INDEX`.
+pub trait Channels: Instance {
+    /// Configures the channel as output with the given output compare mode.
+    fn set_output_compare(channel: usize, mode: u8, preload: bool);
+
+    /// Configures the channel as input with the given capture selection.
+    fn set_input_capture(channel: usize, selection: u8);
+
+    fn set_channel_enabled(channel: usize, enabled: bool);
+
+    /// Inverts the output polarity, or captures at falling instead of rising edges.
+    fn set_channel_inverted(channel: usize, inverted: bool);
+
+    fn set_compare(channel: usize, value: u32);
+
+    fn compare(channel: usize) -> u32;
+}
+
+/// A timer with a slave mode controller that supports the reset and encoder modes.
+pub trait SlaveMode: Channels {
+    /// Sets the trigger selection and the slave mode selection.
+    fn set_slave_mode(trigger: u8, mode: u8);
+
+    /// Returns true if the counter counts down.
+    fn counting_down() -> bool;
+
+    /// Returns true if channel 1 captured the counter since the last read of its capture
+    /// value.
+    fn capture_1_pending() -> bool;
+}
+
+macro_rules! instances {
+    ($($tim:ident: $enr:ident, $en:ident, $timclk:ident, $interrupt:ident, $bits32:expr,
+       [$($register:ident),*] $(, $bdtr:ident)*;)*) => {
+        $(
+            impl Instance for $tim {
+                const COUNTER_32BIT: bool = $bits32;
+                const INTERRUPT: Interrupt = Interrupt::$interrupt;
+
+                fn enable_clock(rcc: &mut RCC) {
+                    rcc.$enr.modify(|_, w| w.$en().set_bit());
+                    // delay after an RCC peripheral clock enabling
+                    let _unused = rcc.$enr.read();
+                }
+
+                fn timer_clock(clocks: &Clocks) -> Hz {
+                    clocks.$timclk()
+                }
+
+                fn reset() {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.cr1.reset();
+                    tim.dier.reset();
+                    // only the registers that the timer has, the channels must be disabled
+                    // before their mode can be changed
+                    $(tim.$register.reset();)*
+                    tim.cnt.reset();
+                    tim.sr.reset();
+                }
+
+                fn set_counter_enabled(enabled: bool) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.cr1.modify(|_, w| w.cen().bit(enabled));
+                }
+
+                fn counter_enabled() -> bool {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.cr1.read().cen().bit_is_set()
+                }
+
+                fn configure_counter(preload: bool, one_pulse: bool) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.cr1.modify(|_, w| {
+                        w.urs().set_bit(); // update request source: overflow only
+                        w.arpe().bit(preload); // auto-reload preload enable
+                        w.opm().bit(one_pulse); // one-pulse mode
+                        w
+                    });
+                }
+
+                fn generate_update() {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.egr.write(|w| w.ug().set_bit());
+                }
+
+                fn clear_flags() {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.sr.reset();
+                }
+
+                fn set_update_interrupt(enabled: bool) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.dier.modify(|_, w| w.uie().bit(enabled));
+                }
+
+                fn clear_update() -> bool {
+                    let tim = unsafe { &*$tim::ptr() };
+                    let pending = tim.sr.read().uif().bit_is_set();
+                    if pending {
+                        // the flags are cleared by writing 0, writing 1 has no effect
+                        tim.sr.write(|w| unsafe { w.bits(!0) }.uif().clear_bit());
+                    }
+                    pending
+                }
+
+                fn set_prescaler(prescaler: u16) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.psc.write(|w| unsafe { w.psc().bits(prescaler) });
+                }
+
+                fn prescaler() -> u16 {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.psc.read().psc().bits()
+                }
+
+                // the fields of the 32 bit counters of TIM2 and TIM5 are split into two halves,
+                // so the counter and auto-reload registers are accessed as a whole
+
+                fn set_auto_reload(value: u32) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.arr.write(|w| unsafe { w.bits(value) });
+                }
+
+                fn auto_reload() -> u32 {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.arr.read().bits()
+                }
+
+                fn counter() -> u32 {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.cnt.read().bits()
+                }
+
+                fn set_counter(value: u32) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.cnt.write(|w| unsafe { w.bits(value) });
+                }
+
+                fn enable_outputs() {
+                    $(
+                        let tim = unsafe { &*$tim::ptr() };
+                        tim.$bdtr.modify(|_, w| w.moe().set_bit());
+                    )*
+                }
+            }
+        )*
+    };
+}
+
+instances! {
+    TIM1: apb2enr, tim1en, timclk2, TIM1_UP_TIM10, false,
+        [cr2, smcr, ccer, bdtr, ccmr1_output, ccmr2_output], bdtr;
+    TIM2: apb1enr, tim2en, timclk1, TIM2, true, [cr2, smcr, ccer, ccmr1_output, ccmr2_output];
+    TIM3: apb1enr, tim3en, timclk1, TIM3, false, [cr2, smcr, ccer, ccmr1_output, ccmr2_output];
+    TIM4: apb1enr, tim4en, timclk1, TIM4, false, [cr2, smcr, ccer, ccmr1_output, ccmr2_output];
+    TIM5: apb1enr, tim5en, timclk1, TIM5, true, [cr2, smcr, ccer, ccmr1_output, ccmr2_output];
+    TIM6: apb1enr, tim6en, timclk1, TIM6_DAC, false, [cr2];
+    TIM7: apb1enr, tim7en, timclk1, TIM7, false, [cr2];
+    TIM8: apb2enr, tim8en, timclk2, TIM8_UP_TIM13, false,
+        [cr2, smcr, ccer, bdtr, ccmr1_output, ccmr2_output], bdtr;
+    TIM9: apb2enr, tim9en, timclk2, TIM1_BRK_TIM9, false, [smcr, ccer, ccmr1_output];
+    TIM10: apb2enr, tim10en, timclk2, TIM1_UP_TIM10, false, [ccer, ccmr1_output];
+    TIM11: apb2enr, tim11en, timclk2, TIM1_TRG_COM_TIM11, false, [ccer, ccmr1_output];
+    TIM12: apb1enr, tim12en, timclk1, TIM8_BRK_TIM12, false, [smcr, ccer, ccmr1_output];
+    TIM13: apb1enr, tim13en, timclk1, TIM8_UP_TIM13, false, [ccer, ccmr1_output];
+    TIM14: apb1enr, tim14en, timclk1, TIM8_TRG_COM_TIM14, false, [ccer, ccmr1_output];
+}
+
+macro_rules! channels {
+    ($($tim:ident {
+        $($index:tt => $ccmr:ident, $ccr:ident, $ccs:ident, $ocm:ident, $ocpe:ident, $cce:ident,
+            $ccp:ident;)*
+    })*) => {
+        $(
+            impl Channels for $tim {
+                fn set_output_compare(channel: usize, mode: u8, preload: bool) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    match channel {
+                        $($index => tim.$ccmr.modify(|_, w| unsafe {
+                            w.$ccs().bits(0); // capture/compare selection: output
+                            w.$ocm().bits(mode); // output compare mode
+                            w.$ocpe().bit(preload); // output compare preload enable
+                            w
+                        }),)*
+                        _ => unreachable!(),
+                    }
+                }
+
+                fn set_input_capture(channel: usize, selection: u8) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    match channel {
+                        $($index => tim.$ccmr.modify(|_, w| unsafe {
+                            w.$ccs().bits(selection); // capture/compare selection
+                            // no input prescaler and filter
+                            w.$ocm().bits(0);
+                            w.$ocpe().clear_bit();
+                            w
+                        }),)*
+                        _ => unreachable!(),
+                    }
+                }
+
+                fn set_channel_enabled(channel: usize, enabled: bool) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    match channel {
+                        $($index => tim.ccer.modify(|_, w| w.$cce().bit(enabled)),)*
+                        _ => unreachable!(),
+                    }
+                }
+
+                fn set_channel_inverted(channel: usize, inverted: bool) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    match channel {
+                        $($index => tim.ccer.modify(|_, w| w.$ccp().bit(inverted)),)*
+                        _ => unreachable!(),
+                    }
+                }
+
+                fn set_compare(channel: usize, value: u32) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    match channel {
+                        $($index => tim.$ccr.write(|w| unsafe { w.bits(value) }),)*
+                        _ => unreachable!(),
+                    }
+                }
+
+                fn compare(channel: usize) -> u32 {
+                    let tim = unsafe { &*$tim::ptr() };
+                    match channel {
+                        $($index => tim.$ccr.read().bits(),)*
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+channels! {
+    TIM1 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+        1 => ccmr1_output, ccr2, cc2s, oc2m, oc2pe, cc2e, cc2p;
+        2 => ccmr2_output, ccr3, cc3s, oc3m, oc3pe, cc3e, cc3p;
+        3 => ccmr2_output, ccr4, cc4s, oc4m, oc4pe, cc4e, cc4p;
+    }
+    TIM2 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+        1 => ccmr1_output, ccr2, cc2s, oc2m, oc2pe, cc2e, cc2p;
+        2 => ccmr2_output, ccr3, cc3s, oc3m, oc3pe, cc3e, cc3p;
+        3 => ccmr2_output, ccr4, cc4s, oc4m, oc4pe, cc4e, cc4p;
+    }
+    TIM3 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+        1 => ccmr1_output, ccr2, cc2s, oc2m, oc2pe, cc2e, cc2p;
+        2 => ccmr2_output, ccr3, cc3s, oc3m, oc3pe, cc3e, cc3p;
+        3 => ccmr2_output, ccr4, cc4s, oc4m, oc4pe, cc4e, cc4p;
+    }
+    TIM4 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+        1 => ccmr1_output, ccr2, cc2s, oc2m, oc2pe, cc2e, cc2p;
+        2 => ccmr2_output, ccr3, cc3s, oc3m, oc3pe, cc3e, cc3p;
+        3 => ccmr2_output, ccr4, cc4s, oc4m, oc4pe, cc4e, cc4p;
+    }
+    TIM5 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+        1 => ccmr1_output, ccr2, cc2s, oc2m, oc2pe, cc2e, cc2p;
+        2 => ccmr2_output, ccr3, cc3s, oc3m, oc3pe, cc3e, cc3p;
+        3 => ccmr2_output, ccr4, cc4s, oc4m, oc4pe, cc4e, cc4p;
+    }
+    TIM8 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+        1 => ccmr1_output, ccr2, cc2s, oc2m, oc2pe, cc2e, cc2p;
+        2 => ccmr2_output, ccr3, cc3s, oc3m, oc3pe, cc3e, cc3p;
+        3 => ccmr2_output, ccr4, cc4s, oc4m, oc4pe, cc4e, cc4p;
+    }
+    TIM9 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+        1 => ccmr1_output, ccr2, cc2s, oc2m, oc2pe, cc2e, cc2p;
+    }
+    TIM10 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+    }
+    TIM11 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+    }
+    TIM12 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+        1 => ccmr1_output, ccr2, cc2s, oc2m, oc2pe, cc2e, cc2p;
+    }
+    TIM13 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+    }
+    TIM14 {
+        0 => ccmr1_output, ccr1, cc1s, oc1m, oc1pe, cc1e, cc1p;
+    }
+}
+
+macro_rules! slave_mode {
+    ($($tim:ident,)*) => {
+        $(
+            impl SlaveMode for $tim {
+                fn set_slave_mode(trigger: u8, mode: u8) {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.smcr.modify(|_, w| unsafe {
+                        w.ts().bits(trigger); // trigger selection
+                        w.sms().bits(mode); // slave mode selection
+                        w
+                    });
+                }
+
+                fn counting_down() -> bool {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.cr1.read().dir().bit_is_set()
+                }
+
+                fn capture_1_pending() -> bool {
+                    let tim = unsafe { &*$tim::ptr() };
+                    tim.sr.read().cc1if().bit_is_set()
+                }
+            }
+        )*
+    };
+}
+
+slave_mode! {
+    TIM1,
+    TIM2,
+    TIM3,
+    TIM4,
+    TIM5,
+    TIM8,
+}
+
+pub struct Timer<T: Instance> {
+    tim: T,
+    clock: Hz,
+}
+
+impl<T: Instance> Timer<T> {
+    /// Enables the clock of the timer and resets its configuration.
+    pub fn new(tim: T, clocks: &Clocks, rcc: &mut RCC) -> Timer<T> {
+        T::enable_clock(rcc);
+        T::reset();
+        Timer {
+            tim: tim,
+            clock: T::timer_clock(clocks),
+        }
+    }
+
+    /// The input clock of the prescaler.
+    pub fn clock(&self) -> Hz {
+        self.clock
+    }
+
+    /// Starts generating update events with the given frequency.
+    pub fn start(&mut self, frequency: Hz) {
+        self.stop();
+        self.set_frequency(frequency);
+        self.restart();
+    }
+
+    pub fn stop(&mut self) {
+        T::set_counter_enabled(false);
+    }
+
+    /// Enables the update interrupt.
+    pub fn listen(&mut self, nvic: &mut NVIC) {
+        T::set_update_interrupt(true);
+        nvic.enable(T::INTERRUPT);
+    }
+
+    pub fn unlisten(&mut self) {
+        T::set_update_interrupt(false);
+    }
+
+    /// Returns true and clears the flag if an update event occurred.
+    ///
+    /// Must be called in the interrupt handler, otherwise the interrupt fires again.
+    pub fn clear_update(&mut self) -> bool {
+        T::clear_update()
+    }
+
+    /// Resets the configuration and returns the timer peripheral.
+    pub fn release(self) -> T {
+        T::reset();
+        self.tim
+    }
+
+    fn max_count(&self) -> u64 {
+        if T::COUNTER_32BIT {
+            u64::from(u32::max_value())
+        } else {
+            0xffff
+        }
+    }
+
+    /// Sets the prescaler and auto-reload value for an update frequency that is as close as
+    /// possible to `frequency`.
+    fn set_frequency(&self, Hz(frequency): Hz) {
+        assert!(frequency > 0, "timer frequency must not be zero");
+        let ticks = (self.clock.0 as u64 / frequency as u64).max(1);
+        self.set_period_ticks(ticks);
+    }
+
+    /// Sets the prescaler and auto-reload value for a period of `ticks` timer clock cycles.
+    ///
+    /// Returns the prescaler division factor.
+    fn set_period_ticks(&self, ticks: u64) -> u64 {
+        let prescaler = (ticks - 1) / (self.max_count() + 1) + 1;
+        assert!(prescaler <= 0x1_0000, "timer frequency too low");
+        T::set_prescaler((prescaler - 1) as u16);
+        T::set_auto_reload((ticks / prescaler - 1) as u32);
+        prescaler
+    }
+
+    /// Sets the prescaler for a counter frequency that is as close as possible to `frequency`
+    /// and the auto-reload value to the maximum.
+    fn set_counter_frequency(&self, Hz(frequency): Hz) {
+        assert!(frequency > 0, "timer frequency must not be zero");
+        let prescaler = (self.clock.0 / frequency).max(1);
+        assert!(prescaler <= 0x1_0000, "timer frequency too low");
+        T::set_prescaler((prescaler - 1) as u16);
+        T::set_auto_reload(self.max_count() as u32);
+    }
+
+    /// Converts a duration to timer clock cycles.
+    fn duration_ticks(&self, duration: Duration) -> u64 {
+        let micros = duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros());
+        self.clock.0 as u64 * micros / 1_000_000
+    }
+
+    /// Loads the prescaler and auto-reload value, clears the flags and starts the counter.
+    fn restart(&self) {
+        T::configure_counter(true, false);
+        T::generate_update();
+        T::clear_flags();
+        T::set_counter_enabled(true);
+    }
+}
@@ -0,0 +1,146 @@
+//! The pins that are connected to the timer channels, as listed in the alternate function
+//! mapping table of the STM32F74x datasheet. Complementary outputs are not supported.
+
+use gpio::pin::{
+    Alternate, Pin, Unused, P0, P1, P10, P11, P12, P13, P14, P15, P2, P3, P4, P5, P6, P7, P8, P9,
+    PA, PB, PC, PD, PE, PF, PH, PI,
+};
+use gpio::{AlternateFunction, OutputSpeed, OutputType, Resistor};
+use stm32f7::stm32f7x6::{
+    TIM1, TIM10, TIM11, TIM12, TIM13, TIM14, TIM2, TIM3, TIM4, TIM5, TIM8, TIM9,
+};
+
+/// A timer channel at the type level.
+pub trait ChannelId {
+    /// The zero-based channel index.
+    const INDEX: usize;
+}
+
+pub struct C1;
+pub struct C2;
+pub struct C3;
+pub struct C4;
+
+impl ChannelId for C1 {
+    const INDEX: usize = 0;
+}
+
+impl ChannelId for C2 {
+    const INDEX: usize = 1;
+}
+
+impl ChannelId for C3 {
+    const INDEX: usize = 2;
+}
+
+impl ChannelId for C4 {
+    const INDEX: usize = 3;
+}
+
+/// An unused pin that can be connected to channel `C` of timer `T`.
+pub trait ChannelPin<T, C: ChannelId> {
+    /// The pin after it was switched to the alternate function of the channel.
+    type Alternate;
+
+    fn into_alternate(self) -> Self::Alternate;
+}
+
+macro_rules! channel_pins {
+    ($($tim:ident, $channel:ident: $port:ident $pin:ident $af:ident;)*) => {
+        $(
+            impl<'a> ChannelPin<$tim, $channel> for Pin<'a, $port, $pin, Unused> {
+                type Alternate = Pin<'a, $port, $pin, Alternate>;
+
+                fn into_alternate(self) -> Self::Alternate {
+                    self.into_alternate_function(
+                        AlternateFunction::$af,
+                        OutputType::PushPull,
+                        OutputSpeed::High,
+                        Resistor::NoPull,
+                    )
+                }
+            }
+        )*
+    };
+}
+
+channel_pins! {
+    TIM1, C1: PA P8 AF1;
+    TIM1, C1: PE P9 AF1;
+    TIM1, C2: PA P9 AF1;
+    TIM1, C2: PE P11 AF1;
+    TIM1, C3: PA P10 AF1;
+    TIM1, C3: PE P13 AF1;
+    TIM1, C4: PA P11 AF1;
+    TIM1, C4: PE P14 AF1;
+
+    TIM2, C1: PA P0 AF1;
+    TIM2, C1: PA P5 AF1;
+    TIM2, C1: PA P15 AF1;
+    TIM2, C2: PA P1 AF1;
+    TIM2, C2: PB P3 AF1;
+    TIM2, C3: PA P2 AF1;
+    TIM2, C3: PB P10 AF1;
+    TIM2, C4: PA P3 AF1;
+    TIM2, C4: PB P11 AF1;
+
+    TIM3, C1: PA P6 AF2;
+    TIM3, C1: PB P4 AF2;
+    TIM3, C1: PC P6 AF2;
+    TIM3, C2: PA P7 AF2;
+    TIM3, C2: PB P5 AF2;
+    TIM3, C2: PC P7 AF2;
+    TIM3, C3: PB P0 AF2;
+    TIM3, C3: PC P8 AF2;
+    TIM3, C4: PB P1 AF2;
+    TIM3, C4: PC P9 AF2;
+
+    TIM4, C1: PB P6 AF2;
+    TIM4, C1: PD P12 AF2;
+    TIM4, C2: PB P7 AF2;
+    TIM4, C2: PD P13 AF2;
+    TIM4, C3: PB P8 AF2;
+    TIM4, C3: PD P14 AF2;
+    TIM4, C4: PB P9 AF2;
+    TIM4, C4: PD P15 AF2;
+
+    TIM5, C1: PA P0 AF2;
+    TIM5, C1: PH P10 AF2;
+    TIM5, C2: PA P1 AF2;
+    TIM5, C2: PH P11 AF2;
+    TIM5, C3: PA P2 AF2;
+    TIM5, C3: PH P12 AF2;
+    TIM5, C4: PA P3 AF2;
+    TIM5, C4: PI P0 AF2;
+
+    TIM8, C1: PC P6 AF3;
+    TIM8, C1: PI P5 AF3;
+    TIM8, C2: PC P7 AF3;
+    TIM8, C2: PI P6 AF3;
+    TIM8, C3: PC P8 AF3;
+    TIM8, C3: PI P7 AF3;
+    TIM8, C4: PC P9 AF3;
+    TIM8, C4: PI P2 AF3;
+
+    TIM9, C1: PA P2 AF3;
+    TIM9, C1: PE P5 AF3;
+    TIM9, C2: PA P3 AF3;
+    TIM9, C2: PE P6 AF3;
+
+    TIM10, C1: PB P8 AF3;
+    TIM10, C1: PF P6 AF3;
+
+    TIM11, C1: PB P9 AF3;
+    TIM11, C1: PF P7 AF3;
+
+    TIM12, C1: PB P14 AF9;
+    TIM12, C1: PH P6 AF9;
+    TIM12, C2: PB P15 AF9;
+    TIM12, C2: PH P9 AF9;
+
+    TIM13, C1: PA P6 AF9;
+    TIM13, C1: PF P8 AF9;
+
+    TIM14, C1: PA P7 AF9;
+    TIM14, C1: PF P9 AF9;
+}
@@ -0,0 +1,185 @@
+use super::*;
+use core::marker::PhantomData;
+use embedded_hal::PwmPin;
+
+// output compare modes
+const OC_FROZEN: u8 = 0b000;
+const OC_PWM_MODE_1: u8 = 0b110;
+const OC_PWM_MODE_2: u8 = 0b111;
+
+/// A timer in PWM mode.
+///
+/// All channels share the frequency of the timer. The channels are independent of the `Pwm`
+/// and keep their configuration when it is changed.
+pub struct Pwm<T: Channels> {
+    timer: Timer<T>,
+}
+
+/// A PWM output on a single pin.
+pub struct PwmChannel<T: Channels, C: ChannelId, P> {
+    pin: P,
+    phantom: PhantomData<(T, C)>,
+}
+
+impl<T: Channels> Timer<T> {
+    /// Starts the counter with the given PWM frequency.
+    pub fn pwm(self, frequency: Hz) -> Pwm<T> {
+        self.set_frequency(frequency);
+        T::enable_outputs();
+        self.restart();
+        Pwm { timer: self }
+    }
+
+    /// Configures a pin to output a single pulse after `delay` with the given `width` each time
+    /// `OnePulse::trigger` is called.
+    pub fn one_pulse<C: ChannelId, P: ChannelPin<T, C>>(
+        self,
+        pin: P,
+        delay: Duration,
+        width: Duration,
+    ) -> OnePulse<T, C, P::Alternate> {
+        // at least one cycle of delay, so that the output starts inactive
+        let delay = self.duration_ticks(delay).max(1);
+        let width = self.duration_ticks(width).max(1);
+        let prescaler = self.set_period_ticks(delay + width);
+
+        // PWM mode 2: the output is active from CCR to ARR
+        T::set_compare(C::INDEX, (delay / prescaler) as u32);
+        T::set_output_compare(C::INDEX, OC_PWM_MODE_2, false);
+        T::set_channel_inverted(C::INDEX, false);
+        T::set_channel_enabled(C::INDEX, true);
+        T::enable_outputs();
+        // load the prescaler without starting the counter
+        T::configure_counter(false, true);
+        T::generate_update();
+        T::clear_flags();
+
+        OnePulse {
+            timer: self,
+            pin: pin.into_alternate(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Channels> Pwm<T> {
+    /// Configures the pin as PWM output of its channel with a duty cycle of zero.
+    ///
+    /// The output stays disabled until `PwmChannel::enable` is called.
+    pub fn channel<C: ChannelId, P: ChannelPin<T, C>>(
+        &mut self,
+        pin: P,
+    ) -> PwmChannel<T, C, P::Alternate> {
+        T::set_channel_enabled(C::INDEX, false);
+        T::set_channel_inverted(C::INDEX, false);
+        T::set_compare(C::INDEX, 0);
+        T::set_output_compare(C::INDEX, OC_PWM_MODE_1, true);
+
+        PwmChannel {
+            pin: pin.into_alternate(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Changes the PWM frequency. The duty cycles must be set again afterwards, because the
+    /// maximum duty changes.
+    pub fn set_frequency(&mut self, frequency: Hz) {
+        self.timer.set_frequency(frequency);
+    }
+
+    /// The duty value that corresponds to a duty cycle of 100%.
+    pub fn max_duty(&self) -> u32 {
+        max_duty::<T>()
+    }
+
+    /// Stops the counter and returns the timer.
+    pub fn release(self) -> Timer<T> {
+        T::reset();
+        self.timer
+    }
+}
+
+fn max_duty<T: Instance>() -> u32 {
+    T::auto_reload().saturating_add(1)
+}
+
+impl<T: Channels, C: ChannelId, P> PwmChannel<T, C, P> {
+    pub fn enable(&mut self) {
+        T::set_channel_enabled(C::INDEX, true);
+    }
+
+    pub fn disable(&mut self) {
+        T::set_channel_enabled(C::INDEX, false);
+    }
+
+    /// Sets the number of timer cycles per period during which the output is high.
+    ///
+    /// The new value is used from the next period on.
+    pub fn set_duty(&mut self, duty: u32) {
+        T::set_compare(C::INDEX, duty);
+    }
+
+    pub fn duty(&self) -> u32 {
+        T::compare(C::INDEX)
+    }
+
+    pub fn max_duty(&self) -> u32 {
+        max_duty::<T>()
+    }
+
+    /// Disables the output and returns the pin.
+    pub fn release(mut self) -> P {
+        self.disable();
+        T::set_output_compare(C::INDEX, OC_FROZEN, false);
+        self.pin
+    }
+}
+
+impl<T: Channels, C: ChannelId, P> PwmPin for PwmChannel<T, C, P> {
+    type Duty = u32;
+
+    fn disable(&mut self) {
+        PwmChannel::disable(self)
+    }
+
+    fn enable(&mut self) {
+        PwmChannel::enable(self)
+    }
+
+    fn get_duty(&self) -> u32 {
+        self.duty()
+    }
+
+    fn get_max_duty(&self) -> u32 {
+        self.max_duty()
+    }
+
+    fn set_duty(&mut self, duty: u32) {
+        PwmChannel::set_duty(self, duty)
+    }
+}
+
+/// A timer that outputs single pulses.
+pub struct OnePulse<T: Channels, C: ChannelId, P> {
+    timer: Timer<T>,
+    pin: P,
+    phantom: PhantomData<C>,
+}
+
+impl<T: Channels, C: ChannelId, P> OnePulse<T, C, P> {
+    /// Starts a pulse. The counter stops automatically at the end of the pulse.
+    pub fn trigger(&mut self) {
+        T::set_counter_enabled(true);
+    }
+
+    /// Returns true while the delay or the pulse is running.
+    pub fn is_running(&self) -> bool {
+        T::counter_enabled()
+    }
+
+    /// Resets the timer and returns it together with the pin.
+    pub fn release(self) -> (Timer<T>, P) {
+        T::reset();
+        (self.timer, self.pin)
+    }
+}
@@ -1,5 +1,5 @@
 use arrayvec::ArrayVec;
-use i2c::{self, I2C};
+use i2c::{self, I2cBus};
 
 const FT5336_ADDRESS: i2c::Address = i2c::Address::bits_7(0b0111000);
 const FT5336_FAMILY_ID_REGISTER: u8 = 0xA8;
@@ -8,7 +8,7 @@ const FT5336_STATUS_REGISTER: u8 = 0x02;
 // Start locations for reading pressed touches
 const FT5336_DATA_REGISTERS: [u8; 5] = [0x03, 0x09, 0x0F, 0x15, 0x1B];
 
-pub fn check_family_id(i2c_3: &mut I2C) -> Result<(), i2c::Error> {
+pub fn check_family_id<B: I2cBus>(i2c_3: &mut B) -> Result<(), i2c::Error> {
     i2c_3.connect::<u8, _>(FT5336_ADDRESS, |mut conn| {
         // read and check device family ID
         assert_eq!(conn.read(FT5336_FAMILY_ID_REGISTER).ok(), Some(0x51));
@@ -22,7 +22,7 @@ pub struct Touch {
     pub y: u16,
 }
 
-pub fn touches(i2c_3: &mut I2C) -> Result<ArrayVec<[Touch; 5]>, i2c::Error> {
+pub fn touches<B: I2cBus>(i2c_3: &mut B) -> Result<ArrayVec<[Touch; 5]>, i2c::Error> {
     let mut touches = ArrayVec::new();
     i2c_3.connect::<u8, _>(FT5336_ADDRESS, |mut conn| {
         let status = conn.read(FT5336_STATUS_REGISTER)?;
@@ -43,3 +43,51 @@ pub fn touches(i2c_3: &mut I2C) -> Result<ArrayVec<[Touch; 5]>, i2c::Error> {
 
     Ok(touches)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use i2c::mock::{MockI2c, Transaction};
+
+    #[test]
+    fn family_id() {
+        let mut bus = MockI2c::new(vec![
+            Transaction::write(FT5336_ADDRESS, &[FT5336_FAMILY_ID_REGISTER]),
+            Transaction::read(FT5336_ADDRESS, &[0x51]),
+            Transaction::stop(),
+        ]);
+        check_family_id(&mut bus).unwrap();
+        bus.done();
+    }
+
+    #[test]
+    fn two_touches() {
+        let mut bus = MockI2c::new(vec![
+            Transaction::write(FT5336_ADDRESS, &[FT5336_STATUS_REGISTER]),
+            Transaction::read(FT5336_ADDRESS, &[0x02]),
+            Transaction::write(FT5336_ADDRESS, &[0x03]),
+            Transaction::read(FT5336_ADDRESS, &[0x41, 0x10, 0x82, 0x20]),
+            Transaction::write(FT5336_ADDRESS, &[0x09]),
+            Transaction::read(FT5336_ADDRESS, &[0x00, 0x05, 0x00, 0x07]),
+            Transaction::stop(),
+        ]);
+        let touches = touches(&mut bus).unwrap();
+        bus.done();
+
+        assert_eq!(touches.len(), 2);
+        // the upper nibble of the high bytes contains the event flags
+        assert_eq!((touches[0].x, touches[0].y), (0x220, 0x110));
+        assert_eq!((touches[1].x, touches[1].y), (7, 5));
+    }
+
+    #[test]
+    fn invalid_touch_count() {
+        let mut bus = MockI2c::new(vec![
+            Transaction::write(FT5336_ADDRESS, &[FT5336_STATUS_REGISTER]),
+            Transaction::read(FT5336_ADDRESS, &[0x0F]),
+            Transaction::stop(),
+        ]);
+        assert!(touches(&mut bus).unwrap().is_empty());
+        bus.done();
+    }
+}
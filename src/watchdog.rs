@@ -0,0 +1,233 @@
+//! Independent and window watchdogs and the cause of the last reset.
+//!
+//! - The independent watchdog (IWDG) runs from the LSI and resets the system if it is not fed
+//!   within its timeout. It keeps running if the main clock fails.
+//! - The window watchdog (WWDG) runs from PCLK1 and has short timeouts of up to about 40 ms.
+//!   It also resets the system if it is fed too early, before the window opens, and can trigger
+//!   an early wakeup interrupt shortly before the reset.
+//!
+//! Both watchdogs can't be stopped once they are started, only by a reset.
+//!
+//! Example
+//!
+//! ```ignore
+//! let reset_cause = ResetCause::read_and_clear(&mut rcc);
+//! if reset_cause.is_watchdog() {
+//!     println!("reset by watchdog");
+//! }
+//!
+//! let mut watchdog = IndependentWatchdog::new(peripherals.IWDG);
+//! watchdog.start(Duration::from_secs(2));
+//! loop {
+//!     watchdog.feed();
+//!     // …
+//! }
+//! ```
+
+use clocks::Clocks;
+use cortex_m::peripheral::NVIC;
+use stm32f7::stm32f7x6::{Interrupt, DBGMCU, IWDG, RCC, WWDG};
+use system_clock::{Duration, Hz};
+
+// IWDG KR
+const KEY_START: u16 = 0xcccc;
+const KEY_FEED: u16 = 0xaaaa;
+const KEY_UNLOCK: u16 = 0x5555;
+
+/// The nominal LSI frequency. The actual frequency varies between 17 and 47 kHz, so the
+/// timeout of the independent watchdog is not exact.
+const LSI: Hz = Hz(32_000);
+
+/// Why the system was reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetCause {
+    /// The supply voltage was switched on.
+    PowerOn,
+    /// The supply voltage dropped below the brown-out threshold.
+    BrownOut,
+    /// The reset pin was pulled low, e.g. by the reset button or a debugger.
+    Pin,
+    /// A software reset through `SCB::system_reset`.
+    Software,
+    IndependentWatchdog,
+    WindowWatchdog,
+    /// Entering standby or stop mode was prohibited by the option bytes.
+    LowPower,
+    /// No reset flag was set, e.g. because they were cleared before.
+    Unknown,
+}
+
+impl ResetCause {
+    /// Reads the reset flags of the RCC and clears them, so that the next reset is reported
+    /// correctly.
+    pub fn read_and_clear(rcc: &mut RCC) -> ResetCause {
+        let flags = rcc.csr.read();
+        rcc.csr.modify(|_, w| w.rmvf().set_bit());
+
+        // the pin reset flag is set on every reset and a power-on reset also sets the brown-out
+        // flag, so the most specific flag wins
+        if flags.lpwrrstf().bit_is_set() {
+            ResetCause::LowPower
+        } else if flags.wwdgrstf().bit_is_set() {
+            ResetCause::WindowWatchdog
+        } else if flags.wdgrstf().bit_is_set() {
+            ResetCause::IndependentWatchdog
+        } else if flags.sftrstf().bit_is_set() {
+            ResetCause::Software
+        } else if flags.porrstf().bit_is_set() {
+            ResetCause::PowerOn
+        } else if flags.borrstf().bit_is_set() {
+            ResetCause::BrownOut
+        } else if flags.padrstf().bit_is_set() {
+            ResetCause::Pin
+        } else {
+            ResetCause::Unknown
+        }
+    }
+
+    /// Returns true if the system was reset by one of the watchdogs.
+    pub fn is_watchdog(self) -> bool {
+        self == ResetCause::IndependentWatchdog || self == ResetCause::WindowWatchdog
+    }
+}
+
+/// The independent watchdog.
+pub struct IndependentWatchdog {
+    iwdg: IWDG,
+}
+
+impl IndependentWatchdog {
+    pub fn new(iwdg: IWDG) -> IndependentWatchdog {
+        IndependentWatchdog { iwdg: iwdg }
+    }
+
+    /// Starts the watchdog with the given timeout, which must be between 125 µs and 32 s.
+    ///
+    /// Can be called again to change the timeout of a running watchdog.
+    pub fn start(&mut self, timeout: Duration) {
+        let micros = timeout.as_secs() * 1_000_000 + u64::from(timeout.subsec_micros());
+        let ticks = (micros * LSI.0 as u64 / 1_000_000).max(4);
+
+        // the prescaler divides by 4 << prescaler, the reload value has 12 bits
+        let prescaler: u8 = (0..7)
+            .find(|&prescaler| ticks <= 0x1000 << (prescaler + 2))
+            .expect("watchdog timeout too long");
+        let reload = ((ticks >> (prescaler + 2)).max(1) - 1) as u16;
+
+        // the LSI is enabled by the start key
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_START) });
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_UNLOCK) });
+        self.iwdg.pr.write(|w| unsafe { w.pr().bits(prescaler) });
+        self.iwdg.rlr.write(|w| unsafe { w.rl().bits(reload) });
+        // disable the window
+        self.iwdg.winr.write(|w| unsafe { w.win().bits(0xfff) });
+        // wait until the prescaler, reload and window values are updated
+        loop {
+            let sr = self.iwdg.sr.read();
+            if sr.pvu().bit_is_clear() && sr.rvu().bit_is_clear() && sr.wvu().bit_is_clear() {
+                break;
+            }
+        }
+        self.feed();
+    }
+
+    /// Reloads the counter.
+    pub fn feed(&mut self) {
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_FEED) });
+    }
+
+    /// Stops the counter while the core is halted by a debugger.
+    pub fn stop_in_debug(&mut self) {
+        let dbgmcu = unsafe { &*DBGMCU::ptr() };
+        dbgmcu.apb1_fz.modify(|_, w| w.dbg_iwdg_stop().set_bit());
+    }
+}
+
+/// The window watchdog.
+pub struct WindowWatchdog {
+    wwdg: WWDG,
+    pclk1: Hz,
+    /// The counter value that is loaded by `feed`.
+    reload: u8,
+}
+
+impl WindowWatchdog {
+    /// Enables the clock of the window watchdog.
+    pub fn new(wwdg: WWDG, rcc: &mut RCC, clocks: &Clocks) -> WindowWatchdog {
+        rcc.apb1enr.modify(|_, w| w.wwdgen().set_bit());
+        // delay after an RCC peripheral clock enabling
+        let _unused = rcc.apb1enr.read();
+
+        WindowWatchdog {
+            wwdg: wwdg,
+            pclk1: clocks.pclk1(),
+            reload: 0x7f,
+        }
+    }
+
+    /// Starts the watchdog, which resets the system if it isn't fed within `timeout` or if it
+    /// is fed earlier than `window` after the previous feed.
+    ///
+    /// Panics if the timeout is too long for PCLK1 (about 40 ms at 54 MHz) or if the window is
+    /// longer than the timeout.
+    pub fn start(&mut self, timeout: Duration, window: Duration) {
+        assert!(window <= timeout, "watchdog window longer than the timeout");
+        let micros = |duration: Duration| {
+            duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+        };
+        // the counter clock is PCLK1 / 4096 divided by 1 << prescaler
+        let counter_clock = self.pclk1.0 as u64 / 4096;
+        let ticks = (micros(timeout) * counter_clock / 1_000_000).max(1);
+
+        // the counter counts down from at most 0x7f and resets the system when it falls below
+        // 0x40
+        let prescaler = (0..4)
+            .find(|&prescaler| ticks <= 0x40 << prescaler)
+            .expect("watchdog timeout too long");
+        let counter_clock = counter_clock >> prescaler;
+        let ticks = (ticks >> prescaler).max(1);
+        let window_ticks = micros(window) * counter_clock / 1_000_000;
+
+        self.reload = (0x3f + ticks) as u8;
+        // feeding is allowed while the counter is at most the window value
+        let window = self.reload - (window_ticks as u8).min(ticks as u8 - 1);
+
+        self.wwdg.cfr.modify(|_, w| unsafe {
+            w.wdgtb().bits(prescaler as u8); // timer base
+            w.w().bits(window) // window value
+        });
+        self.feed();
+    }
+
+    /// Reloads the counter.
+    ///
+    /// Resets the system if the window is not open yet.
+    pub fn feed(&mut self) {
+        let reload = self.reload;
+        self.wwdg.cr.write(|w| unsafe {
+            w.t().bits(reload); // counter value
+            w.wdga().set_bit() // activation
+        });
+    }
+
+    /// Enables the early wakeup interrupt `WWDG`, which occurs when the counter reaches 0x40,
+    /// one counter clock cycle before the reset.
+    pub fn listen(&mut self, nvic: &mut NVIC) {
+        self.wwdg.sr.write(|w| w.ewif().clear_bit());
+        self.wwdg.cfr.modify(|_, w| w.ewi().set_bit());
+        nvic.enable(Interrupt::WWDG);
+    }
+
+    /// Clears the early wakeup flag. Must be called in the interrupt handler.
+    pub fn clear_early_wakeup(&mut self) -> bool {
+        let pending = self.wwdg.sr.read().ewif().bit_is_set();
+        self.wwdg.sr.write(|w| w.ewif().clear_bit());
+        pending
+    }
+
+    /// Stops the counter while the core is halted by a debugger.
+    pub fn stop_in_debug(&mut self) {
+        let dbgmcu = unsafe { &*DBGMCU::ptr() };
+        dbgmcu.apb1_fz.modify(|_, w| w.dbg_wwdg_stop().set_bit());
+    }
+}